@@ -1,10 +1,40 @@
-use clap::Args;
+use clap::{ArgAction, Args};
+use indicatif::HumanBytes;
 use miette::Diagnostic;
-use owo_colors::OwoColorize as _;
-use std::process::Command;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
 use thiserror::Error;
+use tracing::{Level, debug};
 
-use crate::{project::Project, symlink::symlink};
+use crate::{
+    config::{Config, ConfigSource, EffectiveConfig},
+    msg,
+    project::Project,
+    symlink::{ActivateOpts, ensure_usable, link_path, symlink},
+    toolchain::find_tool,
+    ui,
+};
+
+mod binary;
+mod context;
+pub mod diagnostics;
+pub mod inspect;
+pub mod limits;
+mod preflight;
+mod symbols;
+pub mod toolset;
+
+pub use context::BuildContext;
+use diagnostics::{CompilerDiagnostic, DiagnosticParser, Summary};
+use limits::{LimitExceeded, MemoryFootprint};
+use preflight::find_on_path;
+pub use preflight::{SwiftInfo, preflight, swift_info};
 
 #[derive(Debug, Error, Diagnostic)]
 pub enum BuildError {
@@ -12,9 +42,198 @@ pub enum BuildError {
     OutputFolderInvalid,
     #[error("Executable package name is invalid UTF-8 or doesn't exist")]
     ExecutableNameInvalid,
+    #[error("`swift build` failed ({status})")]
+    #[diagnostic(code(swift_v5::build::swift_failed))]
+    SwiftFailed { status: ExitStatus },
+    #[error("Failed to convert the ELF to a binary ({status})\n{stderr}")]
+    #[diagnostic(code(swift_v5::build::objcopy_failed))]
+    ObjcopyFailed { status: ExitStatus, stderr: String },
+    #[error("Couldn't convert {} to a binary: {reason}", path.display())]
+    #[diagnostic(code(swift_v5::build::elf_conversion_failed))]
+    #[diagnostic(help(
+        "try converting it with the toolchain's objcopy instead, with `--use-objcopy`"
+    ))]
+    ElfConversionFailed { path: PathBuf, reason: String },
+    #[error("The post-build hook `{hook}` failed ({status})")]
+    #[diagnostic(code(swift_v5::build::hook_failed))]
+    #[diagnostic(help(
+        "the program was built, but hooks after this one didn't run; pass `--no-hooks` to skip them"
+    ))]
+    HookFailed { hook: String, status: ExitStatus },
+    #[error("Couldn't run the post-build hook `{hook}` with `{shell}`")]
+    #[diagnostic(code(swift_v5::build::hook_spawn_failed))]
+    #[diagnostic(help(
+        "the program was built, but no hooks from this one on ran; pass `--no-hooks` to skip them"
+    ))]
+    HookSpawnFailed {
+        hook: String,
+        shell: &'static str,
+        #[source]
+        source: io::Error,
+    },
+    #[error("`{tool}` failed ({status})")]
+    #[diagnostic(code(swift_v5::build::tool_failed))]
+    ToolFailed { tool: String, status: ExitStatus },
+    #[error("The project hasn't been built yet")]
+    #[diagnostic(code(swift_v5::build::not_built))]
+    #[diagnostic(help("run `{command}` first"))]
+    NotBuilt { command: String },
+    #[error("`llvm-size` failed\n{stderr}")]
+    #[diagnostic(code(swift_v5::build::size_failed))]
+    SizeFailed { stderr: String },
+    #[error(
+        "The program is too large to run on the V5:\n{}",
+        exceeded.iter().map(|limit| format!(" • {limit}")).collect::<Vec<_>>().join("\n")
+    )]
+    #[diagnostic(code(swift_v5::build::size_limit_exceeded))]
+    #[diagnostic(help("build with `-Osize`, or look for large symbols with `--emit-map`"))]
+    SizeLimitExceeded { exceeded: Vec<LimitExceeded> },
+    #[error("`llvm-nm` failed\n{stderr}")]
+    #[diagnostic(code(swift_v5::build::symbols_failed))]
+    SymbolsFailed { stderr: String },
+    #[error("Failed to strip debug info from the ELF\n{stderr}")]
+    #[diagnostic(code(swift_v5::build::strip_failed))]
+    StripFailed { stderr: String },
+    #[error("Failed to split debug info into {}\n{stderr}", path.display())]
+    #[diagnostic(code(swift_v5::build::split_debug_failed))]
+    SplitDebugFailed { path: PathBuf, stderr: String },
+    #[error("`swift --version` failed\n{stderr}")]
+    #[diagnostic(code(swift_v5::build::swift_version_failed))]
+    #[diagnostic(help(
+        "check that your Swift installation works, or skip this check with `--no-preflight`"
+    ))]
+    SwiftVersionFailed { stderr: String },
+    #[error(
+        "The Swift toolchain {} isn't installed\n{stderr}",
+        version.as_deref().unwrap_or("selected by swiftly")
+    )]
+    #[diagnostic(code(swift_v5::build::swiftly_toolchain_missing))]
+    #[diagnostic(help("run `swiftly install` in this project"))]
+    SwiftlyToolchainMissing {
+        version: Option<String>,
+        stderr: String,
+    },
+    #[error(
+        "This project uses Swift {expected} (from .swift-version), but `swift` is version {found}"
+    )]
+    #[diagnostic(code(swift_v5::build::swift_version_mismatch))]
+    #[diagnostic(help("run `swiftly install` in this project"))]
+    SwiftVersionMismatch { expected: String, found: String },
+    #[error(
+        "This Swift toolchain ({}{version}) does not support Embedded Swift",
+        if *is_xcode { "Xcode " } else { "" }
+    )]
+    #[diagnostic(code(swift_v5::build::embedded_unsupported))]
+    #[diagnostic(help(
+        "install Swift 6 or newer with swiftly (https://www.swift.org/install), then run `swiftly install` in this project"
+    ))]
+    EmbeddedUnsupported { version: String, is_xcode: bool },
+    #[error(
+        "Couldn't find the `swift` command{}",
+        if *swiftly_found { " (swiftly is installed, but hasn't set up a Swift toolchain)" } else { "" }
+    )]
+    #[diagnostic(code(swift_v5::build::swift_not_found))]
+    #[diagnostic(help(
+        "install Swift with swiftly (https://www.swift.org/install), then run `swiftly install` in this project"
+    ))]
+    SwiftNotFound { swiftly_found: bool },
+    #[error("Couldn't find `{}`", program.display())]
+    #[diagnostic(code(swift_v5::build::objcopy_not_found))]
+    #[diagnostic(help(
+        "run `{}` to install the LLVM toolchain",
+        crate::invocation::command("install")
+    ))]
+    ObjcopyNotFound { program: PathBuf },
+    #[error("`swift package describe` failed\n{stderr}")]
+    #[diagnostic(code(swift_v5::build::describe_failed))]
+    DescribeFailed { stderr: String },
+    #[error("Couldn't read the output of `swift package describe`: {reason}")]
+    #[diagnostic(code(swift_v5::build::describe_invalid))]
+    DescribeInvalid { reason: String },
+    #[error(
+        "This package has more than one executable product:\n{}",
+        names.iter().map(|name| format!(" • {name}")).collect::<Vec<_>>().join("\n")
+    )]
+    #[diagnostic(code(swift_v5::build::multiple_executables))]
+    #[diagnostic(help("remove all but one executable product from Package.swift"))]
+    MultipleExecutables { names: Vec<String> },
+    #[error("Couldn't find {}", path.display())]
+    #[diagnostic(code(swift_v5::build::toolset_missing))]
+    #[diagnostic(help(
+        "builds for the V5 need a SwiftPM toolset pointing at the LLVM toolchain; pass \
+         `--skip-toolset-check` if your setup provides one another way"
+    ))]
+    ToolsetMissing { path: PathBuf },
+    #[error("{} isn't a valid toolset: {reason}", path.display())]
+    #[diagnostic(code(swift_v5::build::toolset_invalid))]
+    #[diagnostic(help("fix the file, which should be a JSON object in SwiftPM's toolset format"))]
+    ToolsetInvalid { path: PathBuf, reason: String },
+    #[error(
+        "{} points at paths which don't exist:\n{}",
+        path.display(),
+        missing.iter().map(|(key, path)| format!(" • {} ({key})", path.display())).collect::<Vec<_>>().join("\n")
+    )]
+    #[diagnostic(code(swift_v5::build::toolset_paths_missing))]
+    #[diagnostic(help(
+        "run `{}` to link the LLVM toolchain, or fix the paths in the toolset",
+        crate::invocation::command("activate")
+    ))]
+    ToolsetPathsMissing {
+        path: PathBuf,
+        missing: Vec<(String, PathBuf)>,
+    },
+    #[error(
+        "There is no build profile named `{name}`.\nDefined profiles:\n{}",
+        defined.iter().map(|name| format!(" • {name}")).collect::<Vec<_>>().join("\n")
+    )]
+    #[diagnostic(code(swift_v5::build::unknown_profile))]
+    #[diagnostic(help("custom profiles are defined in v5.toml, like `[profiles.competition]`"))]
+    UnknownProfile { name: String, defined: Vec<String> },
+    #[error("`{flag}` is missing the flag to forward")]
+    #[diagnostic(code(swift_v5::build::flag_value_missing))]
+    #[diagnostic(help("put the flag to forward right after it, like `--Xswiftc -Osize`"))]
+    FlagValueMissing { flag: String },
+}
+
+impl BuildError {
+    /// The exit code of the tool which failed, which swift-v5 should exit with too.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            Self::SwiftFailed { status }
+            | Self::ObjcopyFailed { status, .. }
+            | Self::ToolFailed { status, .. }
+            | Self::HookFailed { status, .. } => Some(status.code().unwrap_or(1)),
+            _ => None,
+        }
+    }
+
+    /// Converts an error from spawning `swift` into a diagnostic explaining how to install it.
+    pub(crate) fn from_swift_spawn(error: io::Error) -> crate::Error {
+        if error.kind() == ErrorKind::NotFound {
+            Self::SwiftNotFound {
+                swiftly_found: find_on_path("swiftly").is_some(),
+            }
+            .into()
+        } else {
+            error.into()
+        }
+    }
+
+    /// Converts an error from spawning `program` into a diagnostic explaining how to install it.
+    fn from_objcopy_spawn(program: &Path, error: io::Error) -> crate::Error {
+        if error.kind() == ErrorKind::NotFound {
+            Self::ObjcopyNotFound {
+                program: program.to_owned(),
+            }
+            .into()
+        } else {
+            error.into()
+        }
+    }
 }
 
-#[derive(Debug, Error, Clone, clap::ValueEnum)]
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum BuildTarget {
     Release,
     Debug,
@@ -33,52 +252,940 @@ impl std::fmt::Display for BuildTarget {
     }
 }
 
-#[derive(Args, Debug)]
+/// A named set of build settings, either built in or defined in `v5.toml`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildProfile {
+    pub name: String,
+    /// The SwiftPM configuration to build with.
+    pub base: BuildTarget,
+    /// Arguments passed to `swift build` in addition to the ones in `[build]`.
+    pub swift_args: Vec<String>,
+}
+
+impl BuildProfile {
+    const BUILT_IN: [BuildTarget; 2] = [BuildTarget::Release, BuildTarget::Debug];
+
+    /// Picks the name of the profile to build with: the one given on the command line, or
+    /// `build.default-target` from v5.toml, which defaults to `release`.
+    pub fn selected_name<'a>(
+        flag: Option<&'a str>,
+        config: &'a EffectiveConfig,
+    ) -> (&'a str, ConfigSource) {
+        match flag {
+            Some(name) => (name, ConfigSource::Flag),
+            None => (
+                &config.default_target,
+                config
+                    .source("build.default-target")
+                    .unwrap_or(ConfigSource::Default),
+            ),
+        }
+    }
+
+    /// Finds the profile called `name`, preferring the ones defined in `v5.toml` over the
+    /// built-in `release` and `debug` profiles.
+    pub fn resolve(
+        name: &str,
+        profiles: &BTreeMap<String, BuildProfile>,
+    ) -> Result<Self, BuildError> {
+        if let Some(profile) = profiles.get(name) {
+            return Ok(profile.clone());
+        }
+
+        if let Some(base) = Self::BUILT_IN.into_iter().find(|base| base.arg() == name) {
+            return Ok(Self {
+                name: name.to_string(),
+                base,
+                swift_args: Vec::new(),
+            });
+        }
+
+        let mut defined: Vec<String> = Self::BUILT_IN.iter().map(BuildTarget::arg).collect();
+        for name in profiles.keys() {
+            if !defined.contains(name) {
+                defined.push(name.clone());
+            }
+        }
+        Err(BuildError::UnknownProfile {
+            name: name.to_string(),
+            defined,
+        })
+    }
+}
+
+/// The platform a build produces code for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BuildPlatform {
+    /// Cross-compile for the V5 brain.
+    #[default]
+    V5,
+    /// Compile for the current machine, e.g. to run logic tests off-robot.
+    Host,
+}
+
+impl BuildPlatform {
+    pub const V5_TRIPLE: &str = "armv7-none-none-eabi";
+
+    /// Arguments passed to `swift build` to select this platform.
+    pub fn args(&self) -> &'static [&'static str] {
+        match self {
+            BuildPlatform::V5 => &["--triple", Self::V5_TRIPLE],
+            BuildPlatform::Host => &[],
+        }
+    }
+}
+
+#[derive(Args, Debug, Default)]
+pub struct BuildOpts {
+    /// The build profile to use: `release`, `debug`, or one defined under `[profiles]` in v5.toml.
+    /// Defaults to `build.default-target` in v5.toml, or `release`
+    #[arg(long, value_name = "PROFILE")]
+    pub target: Option<String>,
+    /// Build for this computer instead of the V5 brain, e.g. to run logic tests
+    #[arg(long)]
+    pub host: bool,
+    /// Remove debug info from the ELF before converting it to a binary
+    #[arg(long, conflicts_with = "host")]
+    pub strip: bool,
+    /// Save the ELF's debug info to a separate file so crash addresses can be symbolized later
+    #[arg(long, value_name = "PATH", conflicts_with = "host")]
+    pub split_debug: Option<PathBuf>,
+    /// How many jobs `swift build` may run in parallel, instead of `build.jobs` in v5.toml
+    #[arg(long, short, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub jobs: Option<u32>,
+    /// Build in release configuration, optimizing for size with `-Osize` so more fits in the
+    /// V5's memory. Overrides a debug profile's configuration and other optimization flags,
+    /// with a warning
+    #[arg(long)]
+    pub size_opt: bool,
+    /// Convert the ELF to a binary with the toolchain's `llvm-objcopy` instead of swift-v5's
+    /// built-in converter
+    #[arg(long, conflicts_with = "host")]
+    pub use_objcopy: bool,
+    /// Don't run the `[hooks] post-build` commands from v5.toml
+    #[arg(long, conflicts_with = "host")]
+    pub no_hooks: bool,
+    /// Skip checking that the installed Swift toolchain can build this project
+    #[arg(long)]
+    pub no_preflight: bool,
+    /// Skip checking that toolset.json points at an LLVM toolchain which exists
+    #[arg(long, conflicts_with = "host")]
+    pub skip_toolset_check: bool,
+    /// Copy the built program to this directory, relative to the project root, instead of
+    /// `build.out-dir` in v5.toml
+    #[arg(long, value_name = "DIR", conflicts_with = "host")]
+    pub out_dir: Option<PathBuf>,
+    /// Copy the ELF to the output directory along with the binary
+    #[arg(long, conflicts_with = "host")]
+    pub include_elf: bool,
+    /// Fail the build if the program is too large for the V5, instead of warning
+    #[arg(long, conflicts_with = "host")]
+    pub deny_size_limit: bool,
+    /// Write a linker map and a list of symbol sizes next to the binary
+    #[arg(long, conflicts_with = "host")]
+    pub emit_map: bool,
+    /// Arguments forwarded to `swift`.
+    #[clap(flatten)]
+    pub swift_opts: SwiftOpts,
+}
+
+impl BuildOpts {
+    pub fn platform(&self) -> BuildPlatform {
+        if self.host {
+            BuildPlatform::Host
+        } else {
+            BuildPlatform::V5
+        }
+    }
+}
+
+#[derive(Args, Debug, Default)]
 pub struct SwiftOpts {
+    /// A flag for the Swift compiler, like `--Xswiftc -Osize`. Can be repeated
     #[arg(
-        trailing_var_arg = true,
+        long = "Xswiftc",
+        value_name = "FLAG",
         allow_hyphen_values = true,
-        value_name = "SWIFT-OPTIONS"
+        action = ArgAction::Append
     )]
+    xswiftc: Vec<String>,
+    /// A flag for the C compiler, like `--Xcc -DDEBUG`. Can be repeated
+    #[arg(
+        long = "Xcc",
+        value_name = "FLAG",
+        allow_hyphen_values = true,
+        action = ArgAction::Append
+    )]
+    xcc: Vec<String>,
+    /// A flag for the linker, like `--Xlinker --gc-sections`. Can be repeated
+    #[arg(
+        long = "Xlinker",
+        value_name = "FLAG",
+        allow_hyphen_values = true,
+        action = ArgAction::Append
+    )]
+    xlinker: Vec<String>,
+    /// Arguments forwarded to `swift build`
+    #[arg(allow_hyphen_values = true, value_name = "SWIFT-OPTIONS")]
     args: Vec<String>,
+    /// Arguments forwarded to the Swift compiler, after `--`
+    #[arg(last = true, value_name = "SWIFTC-OPTIONS")]
+    swiftc_args: Vec<String>,
 }
 
-pub async fn build(target: &BuildTarget, opts: &SwiftOpts) -> crate::Result<()> {
-    // TODO: allow custom args to be passed thru to the `swift` invocation
-    // resymlink to be safe
-    if !symlink().await? {
+impl SwiftOpts {
+    /// The pass-through options for the `--Xswiftc`, `--Xcc` and `--Xlinker` flags, and the
+    /// `swift build` options they expand to.
+    const NAMED: [(&str, &str); 3] = [
+        ("--Xswiftc", "-Xswiftc"),
+        ("--Xcc", "-Xcc"),
+        ("--Xlinker", "-Xlinker"),
+    ];
+
+    /// Returns the arguments for `swift build`, with the ones after `--` wrapped in `-Xswiftc`.
+    ///
+    /// The `--Xswiftc`, `--Xcc` and `--Xlinker` flags come first, in that order, so the raw
+    /// arguments can override them. Each flag is passed on as a single argument, even if it has
+    /// spaces.
+    ///
+    /// clap only routes arguments to `swiftc_args` when `--` comes first, so a `--` that
+    /// follows other Swift options ends up in `args` and is split here instead. Likewise, named
+    /// flags given after a raw argument end up in `args` and are picked out here, which fails
+    /// if one of them is missing its value.
+    pub fn build_args(&self) -> Result<Vec<String>, BuildError> {
+        let (swift_args, swiftc_args) = match self.args.iter().position(|arg| arg == "--") {
+            Some(separator) => (&self.args[..separator], &self.args[separator + 1..]),
+            None => (&self.args[..], &[][..]),
+        };
+
+        let mut named = [self.xswiftc.clone(), self.xcc.clone(), self.xlinker.clone()];
+        let mut raw = Vec::new();
+        let mut swift_args = swift_args.iter();
+        while let Some(arg) = swift_args.next() {
+            let flag = Self::NAMED
+                .iter()
+                .enumerate()
+                .find_map(|(index, (name, _))| Some((index, arg.strip_prefix(name)?)));
+            match flag {
+                Some((index, "")) => {
+                    let value = swift_args
+                        .next()
+                        .ok_or_else(|| BuildError::FlagValueMissing { flag: arg.clone() })?;
+                    named[index].push(value.clone());
+                }
+                Some((index, value)) if value.starts_with('=') => {
+                    named[index].push(value[1..].to_string());
+                }
+                _ => raw.push(arg.clone()),
+            }
+        }
+
+        let named = Self::NAMED
+            .iter()
+            .zip(named)
+            .flat_map(|((_, option), flags)| {
+                flags
+                    .into_iter()
+                    .flat_map(move |flag| [option.to_string(), flag])
+            });
+        let swiftc_args = swiftc_args
+            .iter()
+            .chain(&self.swiftc_args)
+            .flat_map(|arg| ["-Xswiftc".to_string(), arg.clone()]);
+        Ok(named.chain(raw).chain(swiftc_args).collect())
+    }
+}
+
+pub async fn build(opts: &BuildOpts, config: &Config) -> crate::Result<()> {
+    let platform = opts.platform();
+
+    // resymlink to be safe (host builds don't use the embedded toolchain)
+    let activate = ActivateOpts {
+        keep_existing: true,
+        ..Default::default()
+    };
+    if platform == BuildPlatform::V5 && !symlink(config, &activate).await? {
         return Ok(());
     }
+    // The link may exist but lead to a toolchain which was removed or only partly installed
+    if platform == BuildPlatform::V5 && !ensure_usable(config).await? {
+        return Ok(());
+    }
+
+    let project = Project::find().await?;
+    let config = config.resolve(project.config().await?)?;
+    // A local toolchain is used directly, which is where the link points anyway
+    let toolchain = match &config.llvm_path {
+        Some(llvm_path) => project.path().join(llvm_path),
+        None => link_path(&project, &config),
+    };
+
+    if !opts.no_preflight {
+        preflight(&project, platform).await?;
+    }
+    let toolset = project.path().join(toolset::FILE_NAME);
+    if platform == BuildPlatform::V5 && !opts.skip_toolset_check {
+        toolset::check(&toolset).await?;
+    }
+
+    let (profile_name, source) = BuildProfile::selected_name(opts.target.as_deref(), &config);
+    let profile = BuildProfile::resolve(profile_name, &config.profiles)?;
+    // Size optimization only makes sense on top of SwiftPM's release configuration
+    let target = if opts.size_opt {
+        if profile.base != BuildTarget::Release {
+            eprintln!(
+                "{:>12} --size-opt builds the `{}` profile in release configuration instead of {}",
+                ui::paint("Warning", ui::WARNING),
+                profile.name,
+                profile.base
+            );
+        }
+        BuildTarget::Release
+    } else {
+        profile.base
+    };
+    debug!(?profile, %source, size_opt = opts.size_opt, "Resolved build profile");
+
+    // Arguments from the command line come last so they can override the ones in v5.toml:
+    // first the config, then the `--X*` flags, then the raw arguments.
+    let cli_args = opts.swift_opts.build_args()?;
+    let mut swift_args: Vec<&String> = config
+        .swift_args
+        .iter()
+        .chain(&profile.swift_args)
+        .chain(&cli_args)
+        .collect();
+
+    // Added last, since swiftc uses the last optimization level it's given
+    let size_args;
+    if opts.size_opt {
+        let overridden: Vec<&str> = optimization_flags(&swift_args)
+            .filter(|flag| *flag != SIZE_OPT_FLAG)
+            .collect();
+        if !overridden.is_empty() {
+            eprintln!(
+                "{:>12} --size-opt overrides {}",
+                ui::paint("Warning", ui::WARNING),
+                overridden.join(", ")
+            );
+        }
+        size_args = ["-Xswiftc".to_string(), SIZE_OPT_FLAG.to_string()];
+        swift_args.extend(&size_args);
+    }
+
+    // The products directory isn't known until after the build, so the map is copied there.
+    // The staged map is kept for builds which don't relink, and only while it matches the ELF.
+    let map_args;
+    let staged_map = project.path().join(".build").join("swift-v5-link.map");
+    if opts.emit_map {
+        map_args = [
+            "-Xlinker".to_string(),
+            format!("-Map={}", staged_map.display()),
+        ];
+        swift_args.extend(&map_args);
+    } else {
+        match std::fs::remove_file(&staged_map) {
+            Ok(()) => debug!(
+                ?staged_map,
+                "Removed the linker map, which this build could outdate"
+            ),
+            Err(error) if error.kind() == ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+    debug!(?swift_args, "Merged swift arguments");
 
-    let status = Command::new("swift")
+    let mut command = Command::new("swift");
+    command
         .arg("build")
-        .args(opts.args.clone())
+        .args(&swift_args)
         .arg("-c")
         .arg(target.arg())
-        .arg("--triple")
-        .arg("armv7-none-none-eabi")
-        .arg("--toolset")
-        .arg("toolset.json")
-        .status()?;
-    if !status.success() {
-        std::process::exit(status.code().unwrap_or(1));
-    }
-    let path = Project::output_path(target)?;
-    let name = Project::executable_name()?;
-    let elf = path.join(name.clone());
-    let bin = path.join(format!("{}.bin", name.clone()));
-    let status = Command::new("llvm-objcopy")
-        .arg("-O")
-        .arg("binary")
-        .arg(elf)
-        .arg(&bin)
-        .status()?;
+        .args(platform.args());
+    if let Some(jobs) = opts.jobs.or(config.jobs) {
+        command.arg("-j").arg(jobs.to_string());
+    }
+    if platform == BuildPlatform::V5 {
+        command.arg("--toolset").arg(&toolset);
+    }
+
+    debug!(?command, "Running swift build");
+    compile(&mut command)?;
+    let context = BuildContext::new(&project, target, platform, &swift_args);
+    let path = context.bin_path()?;
+    let name = context.executable_name()?;
+
+    if platform == BuildPlatform::Host {
+        let executable = path.join(name);
+        crate::msg!(
+            format!("Successfully built to {}", executable.display()),
+            ""
+        );
+        return Ok(());
+    }
+
+    let elf = path.join(name);
+    let bin = path.join(format!("{name}.bin"));
+
+    if let Some(debug_path) = &opts.split_debug {
+        split_debug(&toolchain, &elf, debug_path)?;
+        msg!("Split", "debug info to {}", debug_path.display());
+    }
+
+    if opts.strip {
+        let size_before = std::fs::metadata(&elf)?.len();
+        strip(&toolchain, &elf, opts.split_debug.as_deref())?;
+        let size_after = std::fs::metadata(&elf)?.len();
+        msg!(
+            "Stripped",
+            "debug info (saved {})",
+            HumanBytes(size_before.saturating_sub(size_after))
+        );
+    }
+
+    if opts.use_objcopy {
+        objcopy_to_bin(&toolchain, &elf, &bin)?;
+    } else {
+        binary::elf_to_bin(&elf, &bin)?;
+    }
+
+    check_size_limits(&toolchain, &elf, &bin, opts.deny_size_limit)?;
+
+    let out_dir = opts.out_dir.as_ref().or(config.out_dir.as_ref());
+    let bin = match out_dir {
+        Some(out_dir) => {
+            let out_dir = project.path().join(out_dir);
+            if opts.include_elf {
+                copy_artifact(&elf, &out_dir)?;
+            }
+            copy_artifact(&bin, &out_dir)?
+        }
+        None => bin,
+    };
+
+    let size = HumanBytes(std::fs::metadata(&bin)?.len());
+    let details = if opts.size_opt {
+        format!("{size}, optimized for size")
+    } else {
+        size.to_string()
+    };
+    crate::msg!(
+        format!("Successfully built to {} ({details})", &bin.display()),
+        ""
+    );
+
+    if opts.emit_map {
+        emit_map(
+            &toolchain,
+            &elf,
+            &staged_map,
+            &path.join(format!("{name}.map")),
+        );
+    }
+
+    if !opts.no_hooks {
+        let env = [
+            ("SWIFT_V5_BIN", bin.as_os_str()),
+            ("SWIFT_V5_ELF", elf.as_os_str()),
+            ("SWIFT_V5_TARGET", OsStr::new(&profile.name)),
+        ];
+        run_hooks(&config.post_build_hooks, &project, &env)?;
+    }
+
+    Ok(())
+}
+
+/// Runs hook commands in order from the project root with the system shell, stopping at the
+/// first one which fails.
+fn run_hooks(hooks: &[String], project: &Project, env: &[(&str, &OsStr)]) -> crate::Result<()> {
+    for hook in hooks {
+        msg!("Running", "{hook}");
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        let mut command = Command::new(shell);
+        command
+            .arg(flag)
+            .arg(hook)
+            .current_dir(project.path())
+            .envs(env.iter().copied());
+
+        debug!(?command, "Running hook");
+        let status = command
+            .status()
+            .map_err(|source| BuildError::HookSpawnFailed {
+                hook: hook.clone(),
+                shell,
+                source,
+            })?;
+        if !status.success() {
+            return Err(BuildError::HookFailed {
+                hook: hook.clone(),
+                status,
+            }
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// The Swift compiler's flag for optimizing for size.
+const SIZE_OPT_FLAG: &str = "-Osize";
 
+/// Finds the optimization levels, like `-Onone`, passed to the Swift compiler in `swift build`
+/// arguments.
+fn optimization_flags<'a>(args: &[&'a String]) -> impl Iterator<Item = &'a str> {
+    args.windows(2)
+        .filter(|pair| pair[0] == "-Xswiftc" && pair[1].starts_with("-O"))
+        .map(|pair| pair[1].as_str())
+}
+
+/// Runs `swift build` and reports the compiler's diagnostics, failing if the build did.
+fn compile(command: &mut Command) -> crate::Result<()> {
+    let (status, diagnostics) = run_swift_build(command)?;
+    for diagnostic in &diagnostics {
+        eprintln!("{:?}", diagnostic.report());
+    }
     if !status.success() {
-        std::process::exit(status.code().unwrap_or(1));
+        if !diagnostics.is_empty() {
+            eprintln!("{}", ui::paint(Summary::new(&diagnostics), ui::EMPHASIS));
+        }
+        return Err(BuildError::SwiftFailed { status }.into());
+    }
+    Ok(())
+}
+
+/// Runs `swift build`, passing its output through while collecting the compiler's diagnostics.
+///
+/// The raw diagnostics are left out of the output since they're reported separately, unless
+/// verbose logging is on.
+fn run_swift_build(command: &mut Command) -> crate::Result<(ExitStatus, Vec<CompilerDiagnostic>)> {
+    let verbose = tracing::enabled!(Level::DEBUG);
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(BuildError::from_swift_spawn)?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    let (from_stdout, from_stderr) = std::thread::scope(|scope| {
+        let stdout = scope.spawn(|| forward_output(stdout, io::stdout(), verbose));
+        let stderr = scope.spawn(|| forward_output(stderr, io::stderr(), verbose));
+        (stdout.join().unwrap(), stderr.join().unwrap())
+    });
+    let status = child.wait()?;
+
+    let mut diagnostics = from_stdout?;
+    for diagnostic in from_stderr? {
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    Ok((status, diagnostics))
+}
+
+/// Copies the output of `swift build` line by line, returning the diagnostics found in it.
+fn forward_output(
+    output: impl Read,
+    mut destination: impl Write,
+    verbose: bool,
+) -> io::Result<Vec<CompilerDiagnostic>> {
+    let mut parser = DiagnosticParser::default();
+    for line in BufReader::new(output).split(b'\n') {
+        let line = line?;
+        let line = String::from_utf8_lossy(&line);
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        if !parser.push_line(line) || verbose {
+            writeln!(destination, "{line}")?;
+        }
+    }
+    Ok(parser.into_diagnostics())
+}
+
+/// Warns, or fails if `deny` is set, when the program won't fit in the V5's memory.
+fn check_size_limits(toolchain: &Path, elf: &Path, bin: &Path, deny: bool) -> crate::Result<()> {
+    let binary_size = std::fs::metadata(bin)?.len();
+    // The binary alone is still worth checking if the footprint can't be measured
+    let footprint = MemoryFootprint::of(toolchain, elf)
+        .inspect_err(|error| debug!(%error, "Couldn't measure memory footprint"))
+        .ok();
+    debug!(binary_size, ?footprint, "Checking size limits");
+
+    let exceeded = limits::check(binary_size, footprint);
+    if exceeded.is_empty() {
+        return Ok(());
+    }
+    if deny {
+        return Err(BuildError::SizeLimitExceeded { exceeded }.into());
+    }
+
+    for limit in exceeded {
+        eprintln!(
+            "{:>12} {}",
+            ui::paint("Warning", ui::WARNING),
+            ui::paint(format!("The program won't run: {limit}"), ui::WARNING)
+        );
+    }
+    Ok(())
+}
+
+/// Copies a build artifact into `out_dir`, replacing any earlier copy atomically, and returns
+/// the copy's path.
+fn copy_artifact(artifact: &Path, out_dir: &Path) -> crate::Result<PathBuf> {
+    std::fs::create_dir_all(out_dir)?;
+    let file_name = artifact.file_name().expect("artifacts are files");
+    let destination = out_dir.join(file_name);
+
+    // Copy next to the destination first, so it's never left half-written
+    let mut temp_name = OsStr::new(".").to_owned();
+    temp_name.push(file_name);
+    temp_name.push(".tmp");
+    let temp = out_dir.join(temp_name);
+    std::fs::copy(artifact, &temp)?;
+    std::fs::rename(&temp, &destination)?;
+
+    debug!(?artifact, ?destination, "Copied build artifact");
+    Ok(destination)
+}
+
+/// How many of the largest symbols are printed by `--emit-map`.
+const TOP_SYMBOLS: usize = 15;
+
+/// Copies the linker map next to the ELF and lists its largest symbols.
+///
+/// These are only for investigating the binary's size, so failures are warnings.
+fn emit_map(toolchain: &Path, elf: &Path, staged_map: &Path, map: &Path) {
+    let warn = |message: String| {
+        eprintln!("{:>12} {message}", ui::paint("Warning", ui::WARNING));
+    };
+
+    match std::fs::copy(staged_map, map) {
+        Ok(_) => msg!("Wrote", "linker map to {}", map.display()),
+        // The ELF was last linked by a build without `--emit-map`
+        Err(error) if error.kind() == ErrorKind::NotFound => warn(format!(
+            "No linker map was written, since the binary was already up to date; run {} and \
+             build again to write one",
+            ui::paint("`swift package clean`", ui::EMPHASIS)
+        )),
+        Err(error) => warn(format!("Couldn't write the linker map: {error}")),
+    }
+
+    let symbols_path = elf.with_file_name("symbols.txt");
+    match symbols::write_symbol_sizes(toolchain, elf, &symbols_path) {
+        Ok(symbols) => {
+            msg!("Wrote", "symbol sizes to {}", symbols_path.display());
+            for symbol in symbols.iter().take(TOP_SYMBOLS) {
+                eprintln!(
+                    "{:>12} {}",
+                    HumanBytes(symbol.size).to_string(),
+                    symbol.name
+                );
+            }
+        }
+        Err(error) => warn(format!("Couldn't list symbol sizes: {error}")),
+    }
+}
+
+/// Converts `elf` to a binary at `bin` with the toolchain's `llvm-objcopy`.
+fn objcopy_to_bin(toolchain: &Path, elf: &Path, bin: &Path) -> crate::Result<()> {
+    let args = [
+        OsStr::new("-O"),
+        OsStr::new("binary"),
+        elf.as_os_str(),
+        bin.as_os_str(),
+    ];
+    let output = run_objcopy(toolchain, args)?;
+    if !output.status.success() {
+        return Err(BuildError::ObjcopyFailed {
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Copies the debug info in `elf` to a separate file.
+fn split_debug(toolchain: &Path, elf: &Path, debug_path: &Path) -> crate::Result<()> {
+    let output = run_objcopy(
+        toolchain,
+        [
+            OsStr::new("--only-keep-debug"),
+            elf.as_os_str(),
+            debug_path.as_os_str(),
+        ],
+    )?;
+
+    if !output.status.success() {
+        return Err(BuildError::SplitDebugFailed {
+            path: debug_path.to_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Removes the debug info from `elf` in-place, optionally linking it to a split debug info file.
+fn strip(toolchain: &Path, elf: &Path, debug_path: Option<&Path>) -> crate::Result<()> {
+    let mut args = vec![OsStr::new("--strip-debug").to_owned()];
+    if let Some(debug_path) = debug_path {
+        let mut arg = OsStr::new("--add-gnu-debuglink=").to_owned();
+        arg.push(debug_path);
+        args.push(arg);
     }
+    args.push(elf.as_os_str().to_owned());
 
-    crate::msg!(format!("Successfully built to {}", &bin.display()), "");
+    let output = run_objcopy(toolchain, args)?;
+    if !output.status.success() {
+        return Err(BuildError::StripFailed {
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
 
     Ok(())
 }
+
+fn run_objcopy(
+    toolchain: &Path,
+    args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+) -> crate::Result<std::process::Output> {
+    let objcopy = find_tool(toolchain, "llvm-objcopy")?;
+    let mut command = Command::new(&objcopy);
+    command.args(args);
+    debug!(?command, "Running objcopy");
+
+    command
+        .output()
+        .map_err(|error| BuildError::from_objcopy_spawn(&objcopy, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::project::ProjectConfig;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        swift_opts: SwiftOpts,
+    }
+
+    /// Parses `args` like the `build` command would, and returns the `swift build` arguments.
+    fn build_args(args: &[&str]) -> Result<Vec<String>, BuildError> {
+        let cli = Cli::try_parse_from(["build"].iter().chain(args)).unwrap();
+        cli.swift_opts.build_args()
+    }
+
+    #[test]
+    fn named_flags_keep_spaces_and_dashes() {
+        assert_eq!(
+            build_args(&[
+                "--Xlinker",
+                "--gc-sections",
+                "--Xswiftc",
+                "-module-name My App",
+                "--Xcc=-DNAME=\"a b\"",
+            ])
+            .unwrap(),
+            [
+                "-Xswiftc",
+                "-module-name My App",
+                "-Xcc",
+                "-DNAME=\"a b\"",
+                "-Xlinker",
+                "--gc-sections",
+            ]
+        );
+    }
+
+    #[test]
+    fn named_flags_come_before_raw_arguments() {
+        assert_eq!(
+            build_args(&["--Xcc", "-DA", "-v", "--Xswiftc", "-g", "--Xcc=-DB"]).unwrap(),
+            ["-Xswiftc", "-g", "-Xcc", "-DA", "-Xcc", "-DB", "-v"]
+        );
+    }
+
+    #[test]
+    fn trailing_named_flag_without_value() {
+        assert!(matches!(
+            build_args(&["-v", "--Xswiftc"]),
+            Err(BuildError::FlagValueMissing { flag }) if flag == "--Xswiftc"
+        ));
+    }
+
+    #[test]
+    fn arguments_after_separator_go_to_swiftc() {
+        assert_eq!(
+            build_args(&["--", "-Osize", "-g"]).unwrap(),
+            ["-Xswiftc", "-Osize", "-Xswiftc", "-g"]
+        );
+    }
+
+    #[test]
+    fn separator_after_swift_options() {
+        assert_eq!(
+            build_args(&["-v", "--product", "App", "--", "-Osize"]).unwrap(),
+            ["-v", "--product", "App", "-Xswiftc", "-Osize"]
+        );
+    }
+
+    #[test]
+    fn no_arguments() {
+        assert!(build_args(&[]).unwrap().is_empty());
+    }
+
+    /// Resolves the settings with a `v5.toml` which pins a toolchain and has the given `[build]`
+    /// table.
+    fn effective_config(build: &str) -> EffectiveConfig {
+        let contents = format!("llvm-version = \"21.1.1\"\n[build]\n{build}");
+        let project = ProjectConfig::parse(Path::new("v5.toml"), contents).unwrap();
+        Config::default().resolve(Some(&project)).unwrap()
+    }
+
+    #[test]
+    fn profile_from_flag() {
+        let config = effective_config("default-target = \"debug\"\n");
+        assert_eq!(
+            BuildProfile::selected_name(Some("competition"), &config),
+            ("competition", ConfigSource::Flag)
+        );
+    }
+
+    #[test]
+    fn profile_from_config() {
+        let config = effective_config("default-target = \"debug\"\n");
+        assert_eq!(
+            BuildProfile::selected_name(None, &config),
+            ("debug", ConfigSource::Project)
+        );
+    }
+
+    #[test]
+    fn default_profile() {
+        let config = effective_config("");
+        assert_eq!(
+            BuildProfile::selected_name(None, &config),
+            ("release", ConfigSource::Default)
+        );
+    }
+
+    #[test]
+    fn hook_which_cant_start() {
+        let dir = tempfile::tempdir().unwrap();
+        // Spawning fails, since the hook runs in the project directory
+        let project = Project::new(dir.path().join("missing"));
+        let result = run_hooks(&["true".to_string()], &project, &[]);
+
+        assert!(matches!(
+            result,
+            Err(crate::Error::Build(BuildError::HookSpawnFailed { hook, .. })) if hook == "true"
+        ));
+    }
+
+    #[test]
+    fn missing_swift() {
+        let error = Command::new("swift-v5-test-no-such-command")
+            .output()
+            .map_err(BuildError::from_swift_spawn)
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            crate::Error::Build(BuildError::SwiftNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn other_spawn_errors_pass_through() {
+        let error = BuildError::from_swift_spawn(io::Error::from(ErrorKind::PermissionDenied));
+        assert!(matches!(error, crate::Error::Io(_)));
+    }
+
+    /// The exit code of a failed build, which swift-v5 exits with too.
+    fn exit_code(result: crate::Result<()>) -> Option<i32> {
+        match result {
+            Err(crate::Error::Build(error)) => error.exit_code(),
+            _ => None,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_compile() {
+        // Stands in for `swift build` on a package which doesn't compile
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("echo 'Sources/App/main.swift:3:5: error: cannot find 'x' in scope' >&2; exit 2");
+        let result = compile(&mut command);
+
+        assert!(matches!(
+            result,
+            Err(crate::Error::Build(BuildError::SwiftFailed { .. }))
+        ));
+        assert_eq!(exit_code(result), Some(2));
+    }
+
+    #[test]
+    fn map_is_kept_for_builds_which_dont_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let staged_map = dir.path().join("swift-v5-link.map");
+        let map = dir.path().join("App.map");
+        let elf = dir.path().join("App");
+        std::fs::write(&staged_map, "map").unwrap();
+
+        emit_map(dir.path(), &elf, &staged_map, &map);
+        std::fs::remove_file(&map).unwrap();
+        // A no-op build doesn't run the linker, so the staged map is the same one
+        emit_map(dir.path(), &elf, &staged_map, &map);
+
+        assert_eq!(std::fs::read_to_string(&map).unwrap(), "map");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_objcopy() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let toolchain = tempfile::tempdir().unwrap();
+        let objcopy = toolchain.path().join("bin/llvm-objcopy");
+        std::fs::create_dir_all(objcopy.parent().unwrap()).unwrap();
+        std::fs::write(&objcopy, "#!/bin/sh\necho 'not an ELF file' >&2\nexit 3\n").unwrap();
+        std::fs::set_permissions(&objcopy, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let elf = toolchain.path().join("App");
+        let result = objcopy_to_bin(toolchain.path(), &elf, &elf.with_extension("bin"));
+
+        assert!(matches!(
+            &result,
+            Err(crate::Error::Build(BuildError::ObjcopyFailed { stderr, .. }))
+                if stderr.contains("not an ELF file")
+        ));
+        assert_eq!(exit_code(result), Some(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_hook() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = Project::new(dir.path().to_owned());
+        let result = run_hooks(&["exit 4".to_string()], &project, &[]);
+
+        assert_eq!(exit_code(result), Some(4));
+    }
+
+    #[test]
+    fn other_errors_have_no_exit_code() {
+        assert_eq!(BuildError::OutputFolderInvalid.exit_code(), None);
+    }
+}
@@ -0,0 +1,361 @@
+//! Converting the built ELF into the flat binary which is uploaded to the V5.
+//!
+//! This does what `llvm-objcopy -O binary` does, without depending on a toolchain binary: the
+//! contents of every allocated section are laid out at their load (physical) addresses, starting
+//! from the lowest one, with the gaps between them filled with zeros. Sections which take no
+//! space in the file, like `.bss`, are left out, so nothing follows the last section with data.
+
+use std::{error::Error, ops::Range, path::Path};
+
+use object::{
+    FileKind,
+    elf::{FileHeader32, FileHeader64, SHF_ALLOC, SHT_NOBITS},
+    read::elf::{FileHeader, ProgramHeader, SectionHeader},
+};
+
+use crate::build::BuildError;
+
+/// Converts the ELF file at `elf` into a flat binary at `bin`.
+pub fn elf_to_bin(elf: &Path, bin: &Path) -> crate::Result<()> {
+    let data = std::fs::read(elf)?;
+    let image = image_of(&data).map_err(|reason| BuildError::ElfConversionFailed {
+        path: elf.to_owned(),
+        reason,
+    })?;
+    std::fs::write(bin, image)?;
+    Ok(())
+}
+
+/// Lays out the ELF file in `data` as a flat binary image.
+pub fn image_of(data: &[u8]) -> Result<Vec<u8>, String> {
+    let kind = FileKind::parse(data).map_err(|error| error.to_string())?;
+    let image = match kind {
+        FileKind::Elf32 => image::<FileHeader32<object::Endianness>>(data),
+        FileKind::Elf64 => image::<FileHeader64<object::Endianness>>(data),
+        _ => return Err("it isn't an ELF file".to_string()),
+    };
+    image.map_err(|error| error.to_string())
+}
+
+/// The error for offsets and addresses which don't fit in 64 bits, which only a malformed ELF
+/// file has.
+const OVERFLOW: &str = "a section or segment extends past the end of the address space";
+
+/// A section's contents and where they're loaded.
+struct Placed<'data> {
+    address: u64,
+    contents: &'data [u8],
+}
+
+fn image<Elf: FileHeader<Endian = object::Endianness>>(
+    data: &[u8],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let header = Elf::parse(data)?;
+    let endian = header.endian()?;
+    let sections = header.section_headers(endian, data)?;
+
+    // The file offsets each segment spans, and its load address
+    let segments = header
+        .program_headers(endian, data)?
+        .iter()
+        .map(|segment| {
+            let start: u64 = segment.p_offset(endian).into();
+            let end = start
+                .checked_add(segment.p_filesz(endian).into())
+                .ok_or(OVERFLOW)?;
+            Ok((start..end, segment.p_paddr(endian).into()))
+        })
+        .collect::<Result<Vec<(Range<u64>, u64)>, &str>>()?;
+
+    let mut placed = Vec::new();
+    for section in sections {
+        let flags: u64 = section.sh_flags(endian).into();
+        let size: u64 = section.sh_size(endian).into();
+        if flags & u64::from(SHF_ALLOC) == 0 || section.sh_type(endian) == SHT_NOBITS || size == 0 {
+            continue;
+        }
+
+        // Like objcopy, a section is loaded relative to the outermost segment which contains it
+        // in the file, or at its own address if it isn't in one
+        let offset: u64 = section.sh_offset(endian).into();
+        let end = offset.checked_add(size).ok_or(OVERFLOW)?;
+        let parent = segments
+            .iter()
+            .filter(|(range, _)| range.start <= offset && end <= range.end)
+            .min_by_key(|(range, _)| range.start);
+        let address = match parent {
+            Some((range, paddr)) => (offset - range.start).checked_add(*paddr).ok_or(OVERFLOW)?,
+            None => section.sh_addr(endian).into(),
+        };
+
+        placed.push(Placed {
+            address,
+            contents: section.data(endian, data)?,
+        });
+    }
+
+    let Some(base) = placed.iter().map(|section| section.address).min() else {
+        return Ok(Vec::new());
+    };
+    let mut len = 0;
+    for section in &placed {
+        let end = (section.address - base)
+            .checked_add(section.contents.len() as u64)
+            .ok_or(OVERFLOW)?;
+        len = len.max(end);
+    }
+
+    let mut image = vec![0; usize::try_from(len).map_err(|_| OVERFLOW)?];
+    for section in placed {
+        let start = (section.address - base) as usize;
+        image[start..start + section.contents.len()].copy_from_slice(section.contents);
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use object::elf::{PT_LOAD, SHF_EXECINSTR, SHF_WRITE, SHT_PROGBITS, SHT_STRTAB};
+
+    use super::*;
+
+    struct Section {
+        name: &'static str,
+        kind: u32,
+        flags: u32,
+        address: u64,
+        data: Vec<u8>,
+    }
+
+    /// A segment covering the sections in `sections`, loaded at `paddr`.
+    struct Segment {
+        sections: Range<usize>,
+        paddr: u64,
+        /// Overrides the size the segment takes in the file.
+        filesz: Option<u64>,
+    }
+
+    /// Writes a little-endian ELF64 file with the given sections and segments.
+    fn elf64(sections: &[Section], segments: &[Segment]) -> Vec<u8> {
+        const HEADER_SIZE: usize = 64;
+        const SEGMENT_SIZE: usize = 56;
+        const SECTION_SIZE: usize = 64;
+
+        let data_start = HEADER_SIZE + SEGMENT_SIZE * segments.len();
+        let mut body: Vec<u8> = Vec::new();
+        let mut offsets = Vec::new();
+        for section in sections {
+            offsets.push((data_start + body.len()) as u64);
+            if section.kind != SHT_NOBITS {
+                body.extend(&section.data);
+            }
+        }
+        let file_end = |index: usize| {
+            let section = &sections[index];
+            let len = if section.kind == SHT_NOBITS {
+                0
+            } else {
+                section.data.len() as u64
+            };
+            offsets[index] + len
+        };
+
+        let mut names = vec![0];
+        let mut name_offsets = Vec::new();
+        for name in sections
+            .iter()
+            .map(|section| section.name)
+            .chain([".shstrtab"])
+        {
+            name_offsets.push(names.len() as u32);
+            names.extend(name.as_bytes());
+            names.push(0);
+        }
+        let names_offset = (data_start + body.len()) as u64;
+        body.extend(&names);
+        let section_headers = (data_start + body.len()) as u64;
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend(2u16.to_le_bytes()); // ET_EXEC
+        elf.extend(183u16.to_le_bytes()); // EM_AARCH64
+        elf.extend(1u32.to_le_bytes());
+        elf.extend(0u64.to_le_bytes());
+        elf.extend((HEADER_SIZE as u64).to_le_bytes());
+        elf.extend(section_headers.to_le_bytes());
+        elf.extend(0u32.to_le_bytes());
+        elf.extend((HEADER_SIZE as u16).to_le_bytes());
+        elf.extend((SEGMENT_SIZE as u16).to_le_bytes());
+        elf.extend((segments.len() as u16).to_le_bytes());
+        elf.extend((SECTION_SIZE as u16).to_le_bytes());
+        elf.extend((sections.len() as u16 + 2).to_le_bytes());
+        elf.extend((sections.len() as u16 + 1).to_le_bytes());
+
+        for segment in segments {
+            let offset = offsets[segment.sections.start];
+            let filesz = segment
+                .filesz
+                .unwrap_or_else(|| file_end(segment.sections.end - 1) - offset);
+            elf.extend(PT_LOAD.to_le_bytes());
+            elf.extend(5u32.to_le_bytes()); // PF_R | PF_X
+            elf.extend(offset.to_le_bytes());
+            elf.extend(sections[segment.sections.start].address.to_le_bytes());
+            elf.extend(segment.paddr.to_le_bytes());
+            elf.extend(filesz.to_le_bytes());
+            elf.extend(filesz.to_le_bytes());
+            elf.extend(1u64.to_le_bytes());
+        }
+
+        elf.extend(body);
+
+        elf.extend([0; SECTION_SIZE]);
+        let headers = sections
+            .iter()
+            .zip(&offsets)
+            .map(|(section, &offset)| {
+                let flags = u64::from(section.flags);
+                (
+                    section.kind,
+                    flags,
+                    section.address,
+                    offset,
+                    section.data.len(),
+                )
+            })
+            .chain([(SHT_STRTAB, 0, 0, names_offset, names.len())]);
+        for ((kind, flags, address, offset, size), name) in headers.zip(name_offsets) {
+            elf.extend(name.to_le_bytes());
+            elf.extend(kind.to_le_bytes());
+            elf.extend(flags.to_le_bytes());
+            elf.extend(address.to_le_bytes());
+            elf.extend(offset.to_le_bytes());
+            elf.extend((size as u64).to_le_bytes());
+            elf.extend(0u32.to_le_bytes());
+            elf.extend(0u32.to_le_bytes());
+            elf.extend(1u64.to_le_bytes());
+            elf.extend(0u64.to_le_bytes());
+        }
+
+        elf
+    }
+
+    /// A program whose initialized data is loaded right after its code, but copied to RAM at
+    /// startup, like the V5's.
+    fn program() -> Vec<u8> {
+        elf64(
+            &[
+                Section {
+                    name: ".text",
+                    kind: SHT_PROGBITS,
+                    flags: SHF_ALLOC | SHF_EXECINSTR,
+                    address: 0x380_0000,
+                    data: vec![1, 2, 3, 4, 5, 6],
+                },
+                Section {
+                    name: ".data",
+                    kind: SHT_PROGBITS,
+                    flags: SHF_ALLOC | SHF_WRITE,
+                    address: 0x700_0000,
+                    data: vec![7, 8],
+                },
+                Section {
+                    name: ".bss",
+                    kind: SHT_NOBITS,
+                    flags: SHF_ALLOC | SHF_WRITE,
+                    address: 0x700_0002,
+                    data: vec![0; 16],
+                },
+                Section {
+                    name: ".comment",
+                    kind: SHT_PROGBITS,
+                    flags: 0,
+                    address: 0,
+                    data: b"not loaded".to_vec(),
+                },
+            ],
+            &[
+                Segment {
+                    sections: 0..1,
+                    paddr: 0x380_0000,
+                    filesz: None,
+                },
+                Segment {
+                    sections: 1..2,
+                    paddr: 0x380_0008,
+                    filesz: None,
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn places_sections_at_load_addresses() {
+        assert_eq!(
+            image_of(&program()).unwrap(),
+            [1, 2, 3, 4, 5, 6, 0, 0, 7, 8]
+        );
+    }
+
+    #[test]
+    fn rejects_overflowing_segment() {
+        let elf = elf64(
+            &[Section {
+                name: ".text",
+                kind: SHT_PROGBITS,
+                flags: SHF_ALLOC | SHF_EXECINSTR,
+                address: 0x380_0000,
+                data: vec![1, 2, 3, 4],
+            }],
+            &[Segment {
+                sections: 0..1,
+                paddr: 0x380_0000,
+                filesz: Some(u64::MAX),
+            }],
+        );
+
+        assert_eq!(image_of(&elf), Err(OVERFLOW.to_string()));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(image_of(b"#!/bin/sh\n").is_err());
+    }
+
+    /// Checks that the image matches what `llvm-objcopy -O binary` makes, if it's installed.
+    #[test]
+    fn matches_llvm_objcopy() {
+        if Command::new("llvm-objcopy")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            eprintln!("Skipping, since llvm-objcopy isn't installed");
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut elfs = vec![program()];
+        // The test binary itself is a much larger ELF file on Linux
+        let this_exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        if this_exe.starts_with(b"\x7fELF") {
+            elfs.push(this_exe);
+        }
+
+        for (index, elf) in elfs.iter().enumerate() {
+            let elf_path = dir.path().join(format!("{index}.elf"));
+            let bin_path = dir.path().join(format!("{index}.bin"));
+            std::fs::write(&elf_path, elf).unwrap();
+            let status = Command::new("llvm-objcopy")
+                .args(["-O", "binary"])
+                .arg(&elf_path)
+                .arg(&bin_path)
+                .status()
+                .unwrap();
+            assert!(status.success());
+
+            let expected = std::fs::read(&bin_path).unwrap();
+            assert!(image_of(elf).unwrap() == expected, "ELF {index} differs");
+        }
+    }
+}
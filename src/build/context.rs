@@ -0,0 +1,165 @@
+//! Per-build information about the package, which is computed once and shared between steps.
+//!
+//! Asking SwiftPM about the package resolves the package graph each time, which takes a few
+//! seconds, so each question is answered at most once per `build()` call.
+
+use std::{
+    cell::OnceCell,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use tracing::debug;
+
+use crate::{
+    Error, Result,
+    build::{BuildError, BuildPlatform, BuildTarget},
+    project::{PackageDescription, Project},
+};
+
+/// Swift arguments which move the build directory away from `.build`.
+const BUILD_PATH_ARGS: &[&str] = &["--scratch-path", "--build-path"];
+
+#[derive(Debug)]
+pub struct BuildContext<'a> {
+    project: &'a Project,
+    target: BuildTarget,
+    platform: BuildPlatform,
+    custom_build_path: bool,
+    bin_path: OnceCell<PathBuf>,
+    package: OnceCell<PackageDescription>,
+    executables: OnceCell<Vec<String>>,
+}
+
+impl<'a> BuildContext<'a> {
+    pub fn new(
+        project: &'a Project,
+        target: BuildTarget,
+        platform: BuildPlatform,
+        swift_args: &[impl AsRef<str>],
+    ) -> Self {
+        let custom_build_path = swift_args.iter().any(|arg| {
+            let arg = arg.as_ref();
+            let option = arg.split_once('=').map_or(arg, |(option, _)| option);
+            BUILD_PATH_ARGS.contains(&option)
+        });
+
+        Self {
+            project,
+            target,
+            platform,
+            custom_build_path,
+            bin_path: OnceCell::new(),
+            package: OnceCell::new(),
+            executables: OnceCell::new(),
+        }
+    }
+
+    /// The directory `swift build` writes its products to.
+    ///
+    /// This is derived from SwiftPM's build directory layout when possible, and only asks
+    /// SwiftPM when the expected directory doesn't exist.
+    pub fn bin_path(&self) -> Result<&Path> {
+        if let Some(path) = self.bin_path.get() {
+            return Ok(path);
+        }
+
+        let mut path = self.project.path().join(".build");
+        if self.platform == BuildPlatform::V5 {
+            path.push(BuildPlatform::V5_TRIPLE);
+        }
+        path.push(self.target.arg());
+
+        if self.custom_build_path || !path.is_dir() {
+            debug!(?path, "Bin path not in the expected place, asking SwiftPM");
+            path = Self::show_bin_path(&self.target, self.platform)?;
+        }
+
+        Ok(self.bin_path.get_or_init(|| path))
+    }
+
+    /// The package's manifest, from `swift package describe`.
+    pub fn package(&self) -> Result<&PackageDescription> {
+        if let Some(package) = self.package.get() {
+            return Ok(package);
+        }
+
+        let package = Project::describe()?;
+        Ok(self.package.get_or_init(|| package))
+    }
+
+    /// The names of the package's executable products.
+    pub fn executables(&self) -> Result<&[String]> {
+        if let Some(executables) = self.executables.get() {
+            return Ok(executables);
+        }
+
+        let executables = Self::executables_from(self.package())?;
+        Ok(self.executables.get_or_init(|| executables))
+    }
+
+    /// The name of the package's only executable product.
+    pub fn executable_name(&self) -> Result<&str> {
+        Self::only_executable(self.executables()?)
+    }
+
+    /// Asks SwiftPM where `swift build` writes its products to.
+    pub(crate) fn show_bin_path(target: &BuildTarget, platform: BuildPlatform) -> Result<PathBuf> {
+        let output = Command::new("swift")
+            .arg("build")
+            .arg("-c")
+            .arg(target.arg())
+            .args(platform.args())
+            .arg("--show-bin-path")
+            .output()
+            .map_err(BuildError::from_swift_spawn)?;
+        let path = String::from_utf8(output.stdout).map_err(|_| BuildError::OutputFolderInvalid)?;
+
+        Ok(PathBuf::from(path.trim()))
+    }
+
+    /// Lists the executables in a package description, falling back to parsing the output of
+    /// `swift package show-executables` for toolchains which can't describe packages as JSON.
+    pub(crate) fn executables_from(package: Result<&PackageDescription>) -> Result<Vec<String>> {
+        match package {
+            Ok(package) => {
+                return Ok(package
+                    .executables()
+                    .map(|product| product.name.clone())
+                    .collect());
+            }
+            Err(error @ Error::Build(BuildError::SwiftNotFound { .. })) => return Err(error),
+            Err(error) => {
+                debug!(%error, "Couldn't describe package, falling back to show-executables");
+            }
+        }
+
+        let output = Command::new("swift")
+            .arg("package")
+            .arg("show-executables")
+            .output()
+            .map_err(BuildError::from_swift_spawn)?;
+        let stdout =
+            String::from_utf8(output.stdout).map_err(|_| BuildError::ExecutableNameInvalid)?;
+
+        // Executables from dependencies are listed as `name (package)`.
+        Ok(stdout
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("warning:"))
+            .filter(|line| !line.contains(' '))
+            .map(str::to_string)
+            .collect())
+    }
+
+    pub(crate) fn only_executable(names: &[String]) -> Result<&str> {
+        match names {
+            [] => Err(BuildError::ExecutableNameInvalid.into()),
+            [name] => Ok(name),
+            names => Err(BuildError::MultipleExecutables {
+                names: names.to_vec(),
+            }
+            .into()),
+        }
+    }
+}
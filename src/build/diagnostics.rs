@@ -0,0 +1,346 @@
+//! Picking the compiler's diagnostics out of `swift build`'s output, so they can be shown as
+//! miette reports.
+//!
+//! The compiler prints each diagnostic as a `file:line:column: severity: message` line,
+//! usually followed by an excerpt of the source. Both the older caret style and the newer
+//! `N | code` style of excerpt are recognized, so that they can be hidden from the raw output
+//! once the diagnostic has been parsed.
+
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display},
+    path::PathBuf,
+};
+
+use miette::{LabeledSpan, MietteDiagnostic, NamedSource, Report, SourceSpan};
+
+/// How serious a compiler diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Remark,
+}
+
+impl Severity {
+    const ALL: [Self; 4] = [Self::Error, Self::Warning, Self::Note, Self::Remark];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Note => "note",
+            Self::Remark => "remark",
+        }
+    }
+}
+
+impl From<Severity> for miette::Severity {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Error => Self::Error,
+            Severity::Warning => Self::Warning,
+            Severity::Note | Severity::Remark => Self::Advice,
+        }
+    }
+}
+
+/// A diagnostic printed by the Swift compiler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompilerDiagnostic {
+    pub path: PathBuf,
+    /// The 1-based line the diagnostic points at.
+    pub line: usize,
+    /// The 1-based column the diagnostic points at, in bytes.
+    pub column: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl CompilerDiagnostic {
+    /// Parses a `file:line:column: severity: message` line.
+    pub fn parse(line: &str) -> Option<Self> {
+        let (location, severity, message) = Severity::ALL.into_iter().find_map(|severity| {
+            let (location, message) = line.split_once(&format!(": {}: ", severity.as_str()))?;
+            Some((location, severity, message))
+        })?;
+
+        // The path may contain colons itself, like Windows drive letters
+        let mut parts = location.rsplitn(3, ':');
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let path = parts.next().filter(|path| !path.is_empty())?;
+
+        Some(Self {
+            path: PathBuf::from(path),
+            line,
+            column,
+            severity,
+            message: message.trim_end().to_string(),
+        })
+    }
+
+    /// Converts the diagnostic into a miette report, including an excerpt of the source file
+    /// if it can still be read.
+    pub fn report(&self) -> Report {
+        let mut diagnostic = MietteDiagnostic::new(self.message.clone())
+            .with_severity(self.severity.into())
+            .with_code(format!("swift::{}", self.severity.as_str()));
+
+        let Ok(source) = std::fs::read_to_string(&self.path) else {
+            return Report::new(diagnostic.with_help(format!(
+                "at {}:{}:{}",
+                self.path.display(),
+                self.line,
+                self.column
+            )));
+        };
+
+        if let Some(span) = self.span_in(&source) {
+            diagnostic = diagnostic.with_label(LabeledSpan::new_with_span(None, span));
+        }
+        Report::new(diagnostic).with_source_code(
+            NamedSource::new(self.path.display().to_string(), source).with_language("Swift"),
+        )
+    }
+
+    /// Finds the word the diagnostic points at in the source file.
+    fn span_in(&self, source: &str) -> Option<SourceSpan> {
+        let line_start: usize = source
+            .split_inclusive('\n')
+            .take(self.line.checked_sub(1)?)
+            .map(str::len)
+            .sum();
+        let line = source[line_start..].lines().next().unwrap_or_default();
+        let column = (self.column.checked_sub(1)?).min(line.len());
+        if !line.is_char_boundary(column) {
+            return None;
+        }
+
+        let word_len = line[column..]
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(line.len() - column);
+        Some(SourceSpan::new(
+            (line_start + column).into(),
+            word_len.max(1),
+        ))
+    }
+}
+
+/// Collects compiler diagnostics from `swift build`'s output, one line at a time.
+#[derive(Debug, Default)]
+pub struct DiagnosticParser {
+    diagnostics: Vec<CompilerDiagnostic>,
+    /// Whether the previous line was part of a diagnostic, so a source excerpt may follow.
+    in_diagnostic: bool,
+}
+
+impl DiagnosticParser {
+    /// Parses a line of output, returning whether it was part of a diagnostic.
+    pub fn push_line(&mut self, line: &str) -> bool {
+        if let Some(diagnostic) = CompilerDiagnostic::parse(line) {
+            // The same diagnostic is printed again when a file is compiled more than once
+            if !self.diagnostics.contains(&diagnostic) {
+                self.diagnostics.push(diagnostic);
+            }
+            self.in_diagnostic = true;
+        } else {
+            self.in_diagnostic &= is_excerpt(line);
+        }
+
+        self.in_diagnostic
+    }
+
+    pub fn into_diagnostics(self) -> Vec<CompilerDiagnostic> {
+        self.diagnostics
+    }
+}
+
+/// Whether a line is part of the source excerpt printed after a diagnostic.
+fn is_excerpt(line: &str) -> bool {
+    if line.trim().is_empty() {
+        return false;
+    }
+
+    // Indented code and carets in the older style, or `  | ` continuation lines
+    if line.starts_with(char::is_whitespace) {
+        return true;
+    }
+
+    // `12 | code` lines in the newer style
+    line.split_once(" |")
+        .is_some_and(|(number, _)| number.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Counts the errors and warnings from a build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub errors: usize,
+    pub warnings: usize,
+    /// The number of files with an error or warning.
+    pub files: usize,
+}
+
+impl Summary {
+    pub fn new(diagnostics: &[CompilerDiagnostic]) -> Self {
+        let count = |severity| {
+            diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == severity)
+                .count()
+        };
+        let files = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity <= Severity::Warning)
+            .map(|diagnostic| &diagnostic.path)
+            .collect::<BTreeSet<_>>()
+            .len();
+
+        Self {
+            errors: count(Severity::Error),
+            warnings: count(Severity::Warning),
+            files,
+        }
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |count: usize, noun: &str| {
+            format!("{count} {noun}{}", if count == 1 { "" } else { "s" })
+        };
+        write!(
+            f,
+            "{}, {} in {}",
+            plural(self.errors, "error"),
+            plural(self.warnings, "warning"),
+            plural(self.files, "file")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output from a failed `swift build`, with excerpts in the newer style.
+    const OUTPUT: &str = "\
+Building for debugging...
+/src/Sources/main.swift:3:5: error: cannot find 'prnt' in scope
+1 | import V5
+2 |
+3 |     prnt(\"hi\")
+  |     `- error: cannot find 'prnt' in scope
+4 |
+/src/Sources/main.swift:7:9: warning: initialization of variable 'x' was never used
+7 |     var x = 1
+  |         `- warning: initialization of variable 'x' was never used
+/src/Sources/Motor.swift:2:1: note: did you mean 'print'?
+[3/4] Compiling Robot main.swift
+/src/Sources/main.swift:3:5: error: cannot find 'prnt' in scope
+error: fatalError
+";
+
+    fn parse(output: &str) -> (Vec<bool>, Vec<CompilerDiagnostic>) {
+        let mut parser = DiagnosticParser::default();
+        let hidden = output.lines().map(|line| parser.push_line(line)).collect();
+        (hidden, parser.into_diagnostics())
+    }
+
+    #[test]
+    fn parses_diagnostic_lines() {
+        assert_eq!(
+            CompilerDiagnostic::parse("/src/main.swift:12:34: warning: unused value 'x' "),
+            Some(CompilerDiagnostic {
+                path: PathBuf::from("/src/main.swift"),
+                line: 12,
+                column: 34,
+                severity: Severity::Warning,
+                message: "unused value 'x'".to_string(),
+            })
+        );
+
+        let windows = CompilerDiagnostic::parse(r"C:\src\main.swift:1:2: note: here").unwrap();
+        assert_eq!(windows.path, PathBuf::from(r"C:\src\main.swift"));
+        assert_eq!(windows.severity, Severity::Note);
+    }
+
+    #[test]
+    fn ignores_other_lines() {
+        for line in [
+            "Building for debugging...",
+            "error: fatalError",
+            "main.swift: error: no line",
+            ":1:2: error: no path",
+            "/src/main.swift:x:2: error: bad line",
+        ] {
+            assert_eq!(CompilerDiagnostic::parse(line), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn hides_excerpts_and_repeats() {
+        let (hidden, diagnostics) = parse(OUTPUT);
+        let shown: Vec<&str> = OUTPUT
+            .lines()
+            .zip(hidden)
+            .filter(|(_, hidden)| !hidden)
+            .map(|(line, _)| line)
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                "Building for debugging...",
+                "[3/4] Compiling Robot main.swift",
+                "error: fatalError",
+            ]
+        );
+
+        let severities: Vec<Severity> = diagnostics.iter().map(|d| d.severity).collect();
+        assert_eq!(
+            severities,
+            [Severity::Error, Severity::Warning, Severity::Note]
+        );
+    }
+
+    #[test]
+    fn hides_caret_excerpts() {
+        let (hidden, _) = parse(
+            "/src/main.swift:1:1: error: expected expression\n    let = 1\n        ^\nCompiling",
+        );
+        assert_eq!(hidden, [true, true, true, false]);
+    }
+
+    #[test]
+    fn summarizes_errors_and_warnings() {
+        let (_, diagnostics) = parse(OUTPUT);
+        let summary = Summary::new(&diagnostics);
+        assert_eq!(
+            summary,
+            Summary {
+                errors: 1,
+                warnings: 1,
+                // The note's file has no errors or warnings
+                files: 1,
+            }
+        );
+        assert_eq!(summary.to_string(), "1 error, 1 warning in 1 file");
+        assert_eq!(
+            Summary::new(&[]).to_string(),
+            "0 errors, 0 warnings in 0 files"
+        );
+    }
+
+    #[test]
+    fn spans_the_word_at_the_column() {
+        let diagnostic = CompilerDiagnostic::parse("main.swift:2:5: error: x").unwrap();
+        let span = diagnostic.span_in("import V5\nlet motor = 1\n").unwrap();
+        assert_eq!((span.offset(), span.len()), (14, 5));
+
+        // Past the end of the file
+        let diagnostic = CompilerDiagnostic::parse("main.swift:9:1: error: x").unwrap();
+        let span = diagnostic.span_in("import V5\n").unwrap();
+        assert_eq!((span.offset(), span.len()), (10, 1));
+    }
+}
@@ -0,0 +1,125 @@
+//! Running the toolchain's inspection tools, like `llvm-size`, on the last build.
+
+use std::{
+    io::{self, IsTerminal},
+    path::PathBuf,
+    process::Command,
+};
+
+use clap::Args;
+use tracing::debug;
+
+use crate::{
+    Error,
+    build::{BuildContext, BuildError, BuildOpts, BuildPlatform, BuildProfile, build},
+    config::Config,
+    confirm, invocation,
+    project::Project,
+    toolchain::which::active_toolchain,
+};
+
+#[derive(Args, Debug)]
+pub struct InspectOpts {
+    /// The build profile whose program to inspect: `release`, `debug`, or one from v5.toml.
+    /// Defaults to `build.default-target` in v5.toml, or `release`
+    #[arg(long, value_name = "PROFILE")]
+    pub target: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ObjdumpOpts {
+    #[clap(flatten)]
+    pub inspect: InspectOpts,
+    /// Arguments forwarded to `llvm-objdump`, like `-d`
+    #[arg(
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        value_name = "OBJDUMP-OPTIONS"
+    )]
+    pub args: Vec<String>,
+}
+
+/// Prints the section sizes of the last build's ELF with `llvm-size`.
+pub async fn size(opts: &InspectOpts, config: &Config) -> crate::Result<()> {
+    run_tool("llvm-size", &[], opts, config).await
+}
+
+/// Runs `llvm-objdump` on the last build's ELF.
+pub async fn objdump(opts: &ObjdumpOpts, config: &Config) -> crate::Result<()> {
+    run_tool("llvm-objdump", &opts.args, &opts.inspect, config).await
+}
+
+/// Runs a tool from the project's toolchain with the ELF as its last argument.
+///
+/// The tool's output goes straight to stdout, so it can be piped like the tool itself.
+async fn run_tool(
+    tool: &str,
+    args: &[String],
+    opts: &InspectOpts,
+    config: &Config,
+) -> crate::Result<()> {
+    let elf = find_elf(opts, config).await?;
+    let program = active_toolchain(config).await?.tool_path(tool)?;
+
+    let mut command = Command::new(program);
+    command.args(args).arg(&elf);
+    debug!(?command, "Running {tool}");
+    let status = command
+        .status()
+        .map_err(|error| BuildError::from_objcopy_spawn(&PathBuf::from(tool), error))?;
+    if !status.success() {
+        return Err(BuildError::ToolFailed {
+            tool: tool.to_string(),
+            status,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Finds the ELF built with the given profile, offering to build it if there isn't one.
+async fn find_elf(opts: &InspectOpts, config: &Config) -> crate::Result<PathBuf> {
+    let project = Project::find().await?;
+    let effective = config.resolve(project.config().await?)?;
+    let (profile_name, source) = BuildProfile::selected_name(opts.target.as_deref(), &effective);
+    let profile = BuildProfile::resolve(profile_name, &effective.profiles)?;
+    debug!(?profile, %source, "Resolved build profile");
+    let swift_args: Vec<&String> = effective
+        .swift_args
+        .iter()
+        .chain(&profile.swift_args)
+        .collect();
+
+    let context = BuildContext::new(&project, profile.base, BuildPlatform::V5, &swift_args);
+    let elf = match context.bin_path() {
+        Ok(path) => path.join(context.executable_name()?),
+        // SwiftPM can't be asked where the products are if nothing has been built
+        Err(Error::Build(BuildError::OutputFolderInvalid)) => PathBuf::new(),
+        Err(err) => return Err(err),
+    };
+    if elf.is_file() {
+        return Ok(elf);
+    }
+
+    let command = invocation::command(&format!("build --target {profile_name}"));
+    let can_ask = effective.assume_yes || (io::stdin().is_terminal() && io::stderr().is_terminal());
+    if !can_ask
+        || !confirm(
+            &format!("The `{profile_name}` profile hasn't been built yet. Build it now?"),
+            &format!("Runs `{command}`."),
+            effective.assume_yes,
+        )?
+    {
+        return Err(BuildError::NotBuilt { command }.into());
+    }
+
+    let build_opts = BuildOpts {
+        target: Some(profile_name.to_string()),
+        ..Default::default()
+    };
+    build(&build_opts, config).await?;
+
+    let context = BuildContext::new(&project, profile.base, BuildPlatform::V5, &swift_args);
+    Ok(context.bin_path()?.join(context.executable_name()?))
+}
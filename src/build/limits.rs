@@ -0,0 +1,196 @@
+//! Checking a built program against the memory the V5 brain gives user programs.
+//!
+//! A program which is too large still uploads, but crashes as soon as it's started, so it's
+//! caught here instead.
+
+use std::{
+    fmt::{self, Display},
+    path::Path,
+    process::Command,
+};
+
+use indicatif::HumanBytes;
+use tracing::debug;
+
+use crate::{build::BuildError, toolchain::find_tool};
+
+/// Where user programs are loaded in the brain's memory.
+///
+/// VEXos loads the program's binary to this address and runs it from there. See the linker
+/// scripts of the V5 runtimes, like vexide's `v5.ld` (`__user_ram_start`).
+pub const USER_MEMORY_START: u64 = 0x0380_0000;
+
+/// How much memory user programs get, from [`USER_MEMORY_START`] to the end of the brain's
+/// 128 MiB of RAM.
+///
+/// The program's code, data and zero-initialized data must fit in here, and whatever's left
+/// is used for the heap. See `__user_ram_end` in vexide's `v5.ld`.
+pub const USER_MEMORY_SIZE: u64 = 0x0800_0000 - USER_MEMORY_START;
+
+/// The largest binary which can be loaded, since it's copied to [`USER_MEMORY_START`] as-is.
+pub const MAX_BINARY_SIZE: u64 = USER_MEMORY_SIZE;
+
+/// How much memory a program uses once loaded, from `llvm-size`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryFootprint {
+    pub text: u64,
+    pub data: u64,
+    pub bss: u64,
+}
+
+impl MemoryFootprint {
+    /// Reads the section sizes of an ELF with the toolchain's `llvm-size`.
+    pub fn of(toolchain: &Path, elf: &Path) -> crate::Result<Self> {
+        let llvm_size = find_tool(toolchain, "llvm-size")?;
+        let mut command = Command::new(&llvm_size);
+        command.arg(elf);
+        debug!(?command, "Running llvm-size");
+        let output = command
+            .output()
+            .map_err(|error| BuildError::from_objcopy_spawn(&llvm_size, error))?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        match Self::parse(&stdout) {
+            Some(footprint) if output.status.success() => Ok(footprint),
+            _ => Err(BuildError::SizeFailed {
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            }
+            .into()),
+        }
+    }
+
+    /// Parses the Berkeley-style output of `llvm-size`, which looks like
+    ///
+    /// ```text
+    ///    text    data     bss     dec     hex filename
+    ///   12345     678     910   13933    366d program
+    /// ```
+    fn parse(output: &str) -> Option<Self> {
+        let mut fields = output.lines().nth(1)?.split_whitespace();
+        let mut next = || fields.next()?.parse().ok();
+        Some(Self {
+            text: next()?,
+            data: next()?,
+            bss: next()?,
+        })
+    }
+
+    /// The memory the program takes up before it allocates anything.
+    pub fn total(&self) -> u64 {
+        self.text + self.data + self.bss
+    }
+}
+
+/// A limit which a program exceeds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitExceeded {
+    pub what: &'static str,
+    pub size: u64,
+    pub limit: u64,
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the {} is {}, which is {} over the V5's limit of {}",
+            self.what,
+            HumanBytes(self.size),
+            HumanBytes(self.size - self.limit),
+            HumanBytes(self.limit)
+        )
+    }
+}
+
+/// Compares a program's sizes against the V5's limits, returning the ones it exceeds.
+pub fn check(binary_size: u64, footprint: Option<MemoryFootprint>) -> Vec<LimitExceeded> {
+    let mut exceeded = Vec::new();
+    if binary_size > MAX_BINARY_SIZE {
+        exceeded.push(LimitExceeded {
+            what: "binary",
+            size: binary_size,
+            limit: MAX_BINARY_SIZE,
+        });
+    }
+    if let Some(footprint) = footprint
+        && footprint.total() > USER_MEMORY_SIZE
+    {
+        exceeded.push(LimitExceeded {
+            what: "program's memory use (code, data and bss)",
+            size: footprint.total(),
+            limit: USER_MEMORY_SIZE,
+        });
+    }
+    exceeded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn footprint(total: u64) -> Option<MemoryFootprint> {
+        Some(MemoryFootprint {
+            text: total / 2,
+            data: total / 4,
+            bss: total - total / 2 - total / 4,
+        })
+    }
+
+    #[test]
+    fn parses_llvm_size_output() {
+        let output = "   text    data     bss     dec     hex filename\n  12345     678     910   13933    366d program\n";
+        assert_eq!(
+            MemoryFootprint::parse(output),
+            Some(MemoryFootprint {
+                text: 12345,
+                data: 678,
+                bss: 910,
+            })
+        );
+        assert_eq!(MemoryFootprint::parse("error: no such file\n"), None);
+    }
+
+    #[test]
+    fn within_limits() {
+        assert_eq!(check(0, None), []);
+        assert_eq!(
+            check(MAX_BINARY_SIZE - 1, footprint(USER_MEMORY_SIZE - 1)),
+            []
+        );
+        assert_eq!(check(MAX_BINARY_SIZE, footprint(USER_MEMORY_SIZE)), []);
+    }
+
+    #[test]
+    fn binary_too_large() {
+        assert_eq!(
+            check(MAX_BINARY_SIZE + 1, None),
+            [LimitExceeded {
+                what: "binary",
+                size: MAX_BINARY_SIZE + 1,
+                limit: MAX_BINARY_SIZE,
+            }]
+        );
+    }
+
+    #[test]
+    fn memory_use_too_large() {
+        let exceeded = check(1024, footprint(USER_MEMORY_SIZE + 1));
+        assert_eq!(exceeded.len(), 1);
+        assert_eq!(exceeded[0].size, USER_MEMORY_SIZE + 1);
+        assert_eq!(exceeded[0].limit, USER_MEMORY_SIZE);
+    }
+
+    #[test]
+    fn both_too_large() {
+        let exceeded = check(MAX_BINARY_SIZE + 1, footprint(USER_MEMORY_SIZE + 1));
+        let what: Vec<&str> = exceeded.iter().map(|limit| limit.what).collect();
+        assert_eq!(
+            what,
+            ["binary", "program's memory use (code, data and bss)"]
+        );
+        assert_eq!(
+            exceeded[0].to_string(),
+            "the binary is 72.00 MiB, which is 1 B over the V5's limit of 72.00 MiB"
+        );
+    }
+}
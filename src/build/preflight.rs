@@ -0,0 +1,245 @@
+//! Checks that the installed Swift toolchain can build the project before the compiler runs.
+//!
+//! Problems like a missing swiftly toolchain or a Swift without Embedded support otherwise
+//! surface as pages of compiler output, so they're diagnosed here up-front. The checks only
+//! spawn `swift --version`, and its output is cached for the rest of the invocation.
+
+use std::{
+    env::{self, consts::EXE_SUFFIX},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+};
+
+use tracing::debug;
+
+use crate::{
+    build::{BuildError, BuildPlatform},
+    fs,
+    project::Project,
+};
+
+/// The oldest Swift release which supports Embedded Swift.
+const MIN_EMBEDDED_VERSION: (u32, u32) = (6, 0);
+
+static SWIFT_INFO: OnceLock<SwiftInfo> = OnceLock::new();
+
+/// What we know about the `swift` on PATH.
+#[derive(Debug, Clone)]
+pub struct SwiftInfo {
+    /// The full output of `swift --version`.
+    pub version_output: String,
+    /// The version number reported by `swift --version`, like `6.1` or `6.2-dev`.
+    pub version: Option<String>,
+    /// Whether this is the toolchain bundled with Xcode, which can't build Embedded Swift.
+    pub is_xcode: bool,
+    /// Whether `swift` is a swiftly proxy rather than a toolchain binary.
+    pub is_swiftly: bool,
+}
+
+impl SwiftInfo {
+    fn parse(version_output: String, is_swiftly: bool) -> Self {
+        // Every toolchain built for macOS calls itself "Apple Swift", but only Xcode's are tagged
+        // with a `swiftlang-` build rather than a swift.org release like `swift-6.1-RELEASE`
+        let is_xcode = version_output.contains("(swiftlang-");
+        let version = version_output
+            .split_once("Swift version ")
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .map(str::to_string);
+
+        Self {
+            version_output,
+            version,
+            is_xcode,
+            is_swiftly,
+        }
+    }
+
+    /// The major and minor components of the version number.
+    fn major_minor(&self) -> Option<(u32, u32)> {
+        let mut parts = numeric_components(self.version.as_deref()?);
+        Some((parts.next()?, parts.next().unwrap_or(0)))
+    }
+}
+
+/// Checks that the Swift toolchain on PATH can build `project` for `platform`.
+pub async fn preflight(
+    project: &Project,
+    platform: BuildPlatform,
+) -> crate::Result<&'static SwiftInfo> {
+    let pinned_version = read_swift_version(project).await?;
+    let info = swift_info(pinned_version.as_deref())?;
+    debug!(?info, ?pinned_version, "Checked Swift toolchain");
+
+    if let Some(pinned) = &pinned_version
+        && let Some(found) = &info.version
+        && !versions_match(pinned, found)
+    {
+        return Err(BuildError::SwiftVersionMismatch {
+            expected: pinned.clone(),
+            found: found.clone(),
+        }
+        .into());
+    }
+
+    if platform == BuildPlatform::V5 {
+        let too_old = info
+            .major_minor()
+            .is_some_and(|version| version < MIN_EMBEDDED_VERSION);
+        if info.is_xcode || too_old {
+            return Err(BuildError::EmbeddedUnsupported {
+                version: info
+                    .version
+                    .clone()
+                    .unwrap_or_else(|| "unknown".to_string()),
+                is_xcode: info.is_xcode,
+            }
+            .into());
+        }
+    }
+
+    Ok(info)
+}
+
+/// Runs `swift --version`, or returns its result from earlier in this invocation.
+pub fn swift_info(pinned_version: Option<&str>) -> crate::Result<&'static SwiftInfo> {
+    if let Some(info) = SWIFT_INFO.get() {
+        return Ok(info);
+    }
+
+    let is_swiftly = find_on_path("swift").is_some_and(|path| is_swiftly_proxy(&path));
+    let output = Command::new("swift")
+        .arg("--version")
+        .output()
+        .map_err(BuildError::from_swift_spawn)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        // swiftly's proxy fails when the selected toolchain isn't installed
+        if is_swiftly {
+            return Err(BuildError::SwiftlyToolchainMissing {
+                version: pinned_version.map(str::to_string),
+                stderr,
+            }
+            .into());
+        }
+
+        return Err(BuildError::SwiftVersionFailed { stderr }.into());
+    }
+
+    // Some toolchains print the version to stderr
+    let mut version_output = String::from_utf8_lossy(&output.stdout).into_owned();
+    version_output.push_str(&String::from_utf8_lossy(&output.stderr));
+
+    Ok(SWIFT_INFO.get_or_init(|| SwiftInfo::parse(version_output, is_swiftly)))
+}
+
+/// Reads the Swift version pinned in the project's `.swift-version` file, if it has one.
+async fn read_swift_version(project: &Project) -> crate::Result<Option<String>> {
+    let path = project.path().join(".swift-version");
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(&path).await?;
+    let version = contents.trim();
+    Ok((!version.is_empty()).then(|| version.to_string()))
+}
+
+/// Checks whether a pinned version like `6.1.0` refers to a found version like `6.1`.
+///
+/// Versions that aren't release numbers (e.g. `main-snapshot`) are assumed to match, since
+/// `swift --version` doesn't report snapshot names in a comparable form.
+fn versions_match(pinned: &str, found: &str) -> bool {
+    if !pinned.starts_with(|c: char| c.is_ascii_digit()) {
+        return true;
+    }
+
+    let trim_zeros = |version: &str| {
+        let mut parts: Vec<u32> = numeric_components(version).collect();
+        while parts.len() > 1 && parts.last() == Some(&0) {
+            parts.pop();
+        }
+        parts
+    };
+
+    trim_zeros(pinned) == trim_zeros(found)
+}
+
+/// Iterates over the leading numeric components of a version, stopping at suffixes like `-dev`.
+fn numeric_components(version: &str) -> impl Iterator<Item = u32> {
+    version
+        .split(['-', ' '])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map_while(|part| part.parse().ok())
+}
+
+/// Searches the directories in the PATH environment variable for an executable.
+pub(crate) fn find_on_path(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(format!("{name}{EXE_SUFFIX}")))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Checks whether a `swift` executable is one of swiftly's proxies.
+///
+/// swiftly's proxies are links to the `swiftly` binary, kept in its bin directory.
+fn is_swiftly_proxy(swift: &Path) -> bool {
+    let points_to_swiftly = swift.canonicalize().is_ok_and(|target| {
+        target
+            .file_stem()
+            .is_some_and(|stem| stem.eq_ignore_ascii_case("swiftly"))
+    });
+
+    let in_swiftly_bin_dir = env::var_os("SWIFTLY_BIN_DIR")
+        .is_some_and(|bin_dir| swift.parent() == Some(Path::new(&bin_dir)));
+
+    points_to_swiftly || in_swiftly_bin_dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XCODE: &str = "swift-driver version: 1.120.5 Apple Swift version 6.1.2 \
+        (swiftlang-6.1.2.1.2 clang-1700.0.13.5)\nTarget: arm64-apple-macosx15.0\n";
+    const SWIFT_ORG_MACOS: &str =
+        "Apple Swift version 6.1.2 (swift-6.1.2-RELEASE)\nTarget: arm64-apple-macosx15.0\n";
+    const SWIFTLY_LINUX: &str =
+        "Swift version 6.1.2 (swift-6.1.2-RELEASE)\nTarget: x86_64-unknown-linux-gnu\n";
+    const SNAPSHOT_MACOS: &str = "Apple Swift version 6.2-dev (LLVM 0ea2fbf0a5a4, Swift \
+        a2fc2f0c8f2d)\nTarget: arm64-apple-macosx15.0\n";
+
+    #[test]
+    fn detects_xcode() {
+        let info = SwiftInfo::parse(XCODE.to_string(), false);
+        assert!(info.is_xcode);
+        assert_eq!(info.version.as_deref(), Some("6.1.2"));
+    }
+
+    #[test]
+    fn swift_org_toolchain_on_macos_isnt_xcode() {
+        let info = SwiftInfo::parse(SWIFT_ORG_MACOS.to_string(), false);
+        assert!(!info.is_xcode);
+        assert_eq!(info.version.as_deref(), Some("6.1.2"));
+    }
+
+    #[test]
+    fn swiftly_toolchain_isnt_xcode() {
+        let info = SwiftInfo::parse(SWIFTLY_LINUX.to_string(), true);
+        assert!(!info.is_xcode);
+        assert!(info.is_swiftly);
+        assert_eq!(info.major_minor(), Some((6, 1)));
+    }
+
+    #[test]
+    fn snapshot_isnt_xcode() {
+        let info = SwiftInfo::parse(SNAPSHOT_MACOS.to_string(), true);
+        assert!(!info.is_xcode);
+        assert_eq!(info.version.as_deref(), Some("6.2-dev"));
+        assert_eq!(info.major_minor(), Some((6, 2)));
+    }
+}
@@ -0,0 +1,110 @@
+//! Listing the largest symbols in a build, to find out what's taking up space in the binary.
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use tracing::debug;
+
+use crate::{build::BuildError, toolchain::find_tool};
+
+/// A symbol in the ELF, as listed by `llvm-nm`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub size: u64,
+    /// The symbol's name, demangled if it's a Swift symbol and `swift demangle` works.
+    pub name: String,
+}
+
+/// Writes every sized symbol in `elf` to `output`, largest first, and returns them.
+pub fn write_symbol_sizes(
+    toolchain: &Path,
+    elf: &Path,
+    output: &Path,
+) -> crate::Result<Vec<Symbol>> {
+    let nm = find_tool(toolchain, "llvm-nm")?;
+    let mut command = Command::new(&nm);
+    command
+        .arg("--print-size")
+        .arg("--size-sort")
+        .arg("--reverse-sort")
+        .arg(elf);
+    debug!(?command, "Running nm");
+    let nm_output = command
+        .output()
+        .map_err(|error| BuildError::from_objcopy_spawn(&nm, error))?;
+    if !nm_output.status.success() {
+        return Err(BuildError::SymbolsFailed {
+            stderr: String::from_utf8_lossy(&nm_output.stderr).into_owned(),
+        }
+        .into());
+    }
+
+    // Lines look like `00001234 00000040 T $s4main3fooyyF`
+    let listing = String::from_utf8_lossy(&nm_output.stdout);
+    let mut symbols: Vec<Symbol> = listing
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _address = fields.next()?;
+            let size = u64::from_str_radix(fields.next()?, 16).ok()?;
+            let _kind = fields.next()?;
+            let name = fields.next()?;
+            Some(Symbol {
+                size,
+                name: name.to_string(),
+            })
+        })
+        .collect();
+
+    match demangle(symbols.iter().map(|symbol| symbol.name.as_str())) {
+        Some(names) if names.len() == symbols.len() => {
+            for (symbol, name) in symbols.iter_mut().zip(names) {
+                symbol.name = name;
+            }
+        }
+        _ => debug!("Couldn't demangle symbol names"),
+    }
+
+    let mut contents = String::new();
+    for symbol in &symbols {
+        contents.push_str(&format!("{:>10} {}\n", symbol.size, symbol.name));
+    }
+    std::fs::write(output, contents)?;
+
+    Ok(symbols)
+}
+
+/// Demangles Swift symbol names with `swift demangle`, leaving other names as they are.
+///
+/// Returns `None` if `swift demangle` couldn't be run.
+fn demangle<'a>(names: impl Iterator<Item = &'a str>) -> Option<Vec<String>> {
+    let mut child = Command::new("swift")
+        .args(["demangle", "--simplified", "--compact"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let mut input = String::new();
+    for name in names {
+        input.push_str(name);
+        input.push('\n');
+    }
+
+    // Written from another thread so a full stdout pipe can't deadlock the two processes
+    let mut stdin = child.stdin.take()?;
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output().ok()?;
+    writer.join().ok()?.ok()?;
+
+    output.status.success().then(|| {
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect()
+    })
+}
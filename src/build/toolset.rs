@@ -0,0 +1,148 @@
+//! Reading the project's SwiftPM toolset, which tells `swift build` where the LLVM toolchain is.
+//!
+//! A toolset which points at a missing toolchain otherwise fails deep inside the build with
+//! pages of compiler and linker errors, so [`check`] looks for problems before swift runs.
+//! See SwiftPM's `Toolset.swift` for the schema.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::{build::BuildError, fs};
+
+/// The name of the toolset file in the project root.
+pub const FILE_NAME: &str = "toolset.json";
+
+/// A SwiftPM toolset file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Toolset {
+    pub schema_version: String,
+    /// Directories to search for the tools, relative to the toolset file. SwiftPM accepts a
+    /// single path or a list.
+    #[serde(default, deserialize_with = "deserialize_root_paths")]
+    pub root_path: Vec<PathBuf>,
+    pub swift_compiler: Option<ToolProperties>,
+    pub c_compiler: Option<ToolProperties>,
+    pub cxx_compiler: Option<ToolProperties>,
+    pub linker: Option<ToolProperties>,
+    pub librarian: Option<ToolProperties>,
+    pub debugger: Option<ToolProperties>,
+    pub test_runner: Option<ToolProperties>,
+}
+
+/// The settings for one tool in a toolset.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolProperties {
+    /// The tool's executable, relative to the toolset file.
+    pub path: Option<PathBuf>,
+    #[serde(rename = "extraCLIOptions")]
+    pub extra_cli_options: Option<Vec<String>>,
+}
+
+impl Toolset {
+    /// The tools which are configured, by their key in the file.
+    pub fn tools(&self) -> impl Iterator<Item = (&'static str, &ToolProperties)> {
+        [
+            ("swiftCompiler", &self.swift_compiler),
+            ("cCompiler", &self.c_compiler),
+            ("cxxCompiler", &self.cxx_compiler),
+            ("linker", &self.linker),
+            ("librarian", &self.librarian),
+            ("debugger", &self.debugger),
+            ("testRunner", &self.test_runner),
+        ]
+        .into_iter()
+        .filter_map(|(key, tool)| Some((key, tool.as_ref()?)))
+    }
+
+    /// Reads and parses the toolset at `path`.
+    pub async fn read(path: &Path) -> crate::Result<Self> {
+        let contents = match fs::read_to_string(path).await {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Err(BuildError::ToolsetMissing {
+                    path: path.to_owned(),
+                }
+                .into());
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        serde_json::from_str(&contents).map_err(|error| {
+            BuildError::ToolsetInvalid {
+                path: path.to_owned(),
+                reason: error.to_string(),
+            }
+            .into()
+        })
+    }
+
+    /// Lists the paths in the toolset which don't exist, with the key they're under.
+    ///
+    /// Relative paths are resolved against `dir`, the directory the toolset is in. A tool given
+    /// by name alone is also looked for in the root paths.
+    pub fn missing_paths(&self, dir: &Path) -> Vec<(String, PathBuf)> {
+        let root_paths: Vec<PathBuf> = self.root_path.iter().map(|root| dir.join(root)).collect();
+        let mut missing: Vec<(String, PathBuf)> = root_paths
+            .iter()
+            .filter(|root| !root.is_dir())
+            .map(|root| ("rootPath".to_string(), root.clone()))
+            .collect();
+
+        for (key, tool) in self.tools() {
+            let Some(path) = &tool.path else {
+                continue;
+            };
+            let resolved = dir.join(path);
+            let is_bare_name = path.components().count() == 1 && path.is_relative();
+            let found = resolved.exists()
+                || (is_bare_name && root_paths.iter().any(|root| root.join(path).exists()));
+            if !found {
+                // Where it was most likely meant to be
+                let expected = match root_paths.first() {
+                    Some(root) if is_bare_name => root.join(path),
+                    _ => resolved,
+                };
+                missing.push((format!("{key}.path"), expected));
+            }
+        }
+
+        missing
+    }
+}
+
+/// Checks that the toolset at `path` can be read and that everything it points at exists.
+pub async fn check(path: &Path) -> crate::Result<Toolset> {
+    let toolset = Toolset::read(path).await?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let missing = toolset.missing_paths(dir);
+    debug!(?path, ?missing, "Checked toolset");
+    if !missing.is_empty() {
+        return Err(BuildError::ToolsetPathsMissing {
+            path: path.to_owned(),
+            missing,
+        }
+        .into());
+    }
+
+    Ok(toolset)
+}
+
+fn deserialize_root_paths<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<PathBuf>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RootPaths {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+
+    Ok(match RootPaths::deserialize(deserializer)? {
+        RootPaths::One(path) => vec![path],
+        RootPaths::Many(paths) => paths,
+    })
+}
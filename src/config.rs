@@ -0,0 +1,917 @@
+//! Configuration shared between projects, and the logic for merging it with project settings.
+//!
+//! Settings are resolved with the following precedence (highest first):
+//!
+//! 1. Command line flags
+//! 2. `SWIFT_V5_*` environment variables
+//! 3. The project's `v5.toml`
+//! 4. The user's global `config.toml`
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Display},
+    io::ErrorKind,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use octocrab::Octocrab;
+use serde::{Deserialize, de::IntoDeserializer};
+use thiserror::Error;
+use tracing::debug;
+use url::Url;
+
+use crate::{
+    DIRS, Result,
+    build::{BuildProfile, BuildTarget},
+    fs,
+    project::{AfterUpload, ProjectConfig, UploadConfig},
+    toolchain::{
+        DeleteMode, ToolchainClient, ToolchainError, ToolchainRepo,
+        install::{InstallReporter, TerminalReporter},
+    },
+};
+
+pub mod edit;
+
+/// Help text describing how settings are resolved, shown in `--help`.
+pub const PRECEDENCE_HELP: &str = "\
+Settings are taken from command line flags first, then environment variables, then the
+project's v5.toml, then the global config.toml.
+
+Environment variables:
+  SWIFT_V5_LLVM_VERSION    Overrides `llvm-version` and `llvm-path`
+  SWIFT_V5_SLOT            Overrides `upload.slot`
+  SWIFT_V5_PORT            Overrides `upload.port`
+  SWIFT_V5_AFTER_UPLOAD    Overrides `upload.after-upload`
+  SWIFT_V5_ASSUME_YES      Overrides `assume-yes`
+  SWIFT_V5_MIRROR          Overrides `mirror`
+  SWIFT_V5_TOOLCHAINS_DIR  Overrides `toolchains-dir`
+  SWIFT_V5_NO_UPDATE_CHECK Disables `update-check`";
+
+/// Where the value of a setting came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigSource {
+    Flag,
+    Env(&'static str),
+    Project,
+    Global,
+    Default,
+}
+
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flag => write!(f, "command line flag"),
+            Self::Env(name) => write!(f, "environment variable {name}"),
+            Self::Project => write!(f, "v5.toml"),
+            Self::Global => write!(f, "global {}", GlobalConfig::FILE_NAME),
+            Self::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// A TOML parse error, rendered with a snippet of the offending file.
+#[derive(Debug, Error, Diagnostic)]
+#[error("{message}")]
+pub struct TomlError {
+    #[source_code]
+    source_code: NamedSource<String>,
+    #[label("{message}")]
+    span: Option<SourceSpan>,
+    message: String,
+    #[help]
+    help: String,
+}
+
+impl TomlError {
+    pub fn new(path: &Path, contents: String, error: &toml::de::Error) -> Self {
+        Self::from_parts(path, contents, error.message(), error.span())
+    }
+
+    pub fn from_edit(path: &Path, contents: String, error: &toml_edit::TomlError) -> Self {
+        Self::from_parts(path, contents, error.message(), error.span())
+    }
+
+    pub fn from_parts(
+        path: &Path,
+        contents: String,
+        message: &str,
+        span: Option<Range<usize>>,
+    ) -> Self {
+        let help = span
+            .clone()
+            .and_then(|span| suggest_key(message, &contents[span]))
+            .map(|key| format!("did you mean `{key}`?"))
+            .unwrap_or_else(|| {
+                let file_name = path.file_name().unwrap_or(path.as_os_str());
+                format!("fix the errors in `{}`", file_name.display())
+            });
+
+        Self {
+            source_code: NamedSource::new(path.display().to_string(), contents),
+            span: span.map(SourceSpan::from),
+            message: message.trim().to_string(),
+            help,
+        }
+    }
+}
+
+/// Finds the closest valid name for a misspelled key or value.
+///
+/// Serde reports unknown keys as ``unknown field `foo`, expected `bar` or `baz` `` (and
+/// unknown enum values similarly), so the valid names are recovered from the error message.
+fn suggest_key(message: &str, key: &str) -> Option<String> {
+    const MIN_SIMILARITY: f64 = 0.7;
+
+    let expected = message
+        .strip_prefix("unknown field")
+        .or_else(|| message.strip_prefix("unknown variant"))?
+        .split_once("expected")?
+        .1;
+    let key = key.trim_matches(['"', '\'']);
+
+    expected
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|candidate| (strsim::jaro_winkler(key, candidate), candidate))
+        .filter(|(similarity, _)| *similarity >= MIN_SIMILARITY)
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// A setting like an access token, which is left out of `Debug` output so it's never logged.
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    /// The secret's value, for handing to whatever needs it.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+/// Per-user settings, stored in `config.toml` in the platform's config directory.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct GlobalConfig {
+    /// A GitHub personal access token, used to avoid API rate limits.
+    pub github_token: Option<Secret>,
+    /// A URL to download toolchain archives from instead of GitHub, which may be a `file://` URL.
+    pub mirror: Option<Url>,
+    /// Answer "yes" to all confirmation prompts.
+    pub assume_yes: Option<bool>,
+    /// The serial port of the V5 device, if it shouldn't be detected automatically.
+    pub port: Option<String>,
+    /// Where toolchains should be installed, instead of the platform's data directory.
+    pub toolchains_dir: Option<PathBuf>,
+    /// Check for new swift-v5 releases once a day.
+    pub update_check: Option<bool>,
+    /// Move replaced toolchains to the system trash instead of deleting them permanently.
+    pub trash: Option<bool>,
+    /// Where toolchains are released.
+    pub toolchain: Option<GlobalToolchainConfig>,
+}
+
+/// The `[toolchain]` table of the global config.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct GlobalToolchainConfig {
+    /// The toolchain version to use when a project doesn't pin one.
+    pub default: Option<String>,
+    /// The GitHub repository to install toolchains from, like `arm/arm-toolchain`.
+    pub repo: Option<ToolchainRepo>,
+    /// The text before the version in release tags, like `release-`.
+    pub tag_prefix: Option<String>,
+    /// The text after the version in release tags, like `-ATfE`.
+    pub tag_suffix: Option<String>,
+}
+
+impl GlobalConfig {
+    const FILE_NAME: &str = "config.toml";
+
+    pub fn path() -> PathBuf {
+        DIRS.config_dir().join(Self::FILE_NAME)
+    }
+
+    /// Reads the global config file, or returns the default config if it doesn't exist.
+    pub async fn load() -> Result<Self> {
+        let config_path = Self::path();
+        debug!(?config_path, "Attempting to read global config");
+
+        match fs::read_to_string(&config_path).await {
+            Ok(contents) => toml::from_str(&contents).map_err(|error| {
+                crate::Error::InvalidGlobalConfig(Box::new(TomlError::new(
+                    &config_path,
+                    contents,
+                    &error,
+                )))
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                debug!("No global config file found");
+                Ok(Self::default())
+            }
+            Err(e) => Err(crate::Error::from(e)),
+        }
+    }
+}
+
+/// Settings given as command line flags.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigFlags {
+    pub assume_yes: bool,
+}
+
+/// The configuration sources which don't depend on the current project.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub flags: ConfigFlags,
+    pub global: GlobalConfig,
+}
+
+impl Config {
+    pub async fn load(flags: ConfigFlags) -> Result<Self> {
+        Ok(Self {
+            flags,
+            global: GlobalConfig::load().await?,
+        })
+    }
+
+    /// Merges these settings with a project's config.
+    pub fn resolve(&self, project: Option<&ProjectConfig>) -> Result<EffectiveConfig> {
+        EffectiveConfig::resolve(&self.flags, project, &self.global)
+    }
+}
+
+/// Upload settings after defaults have been applied.
+#[derive(Debug, Clone)]
+pub struct UploadSettings {
+    pub slot: u8,
+    pub port: Option<String>,
+    pub after_upload: AfterUpload,
+}
+
+/// The settings in effect after merging every configuration source.
+#[derive(Debug, Clone)]
+pub struct EffectiveConfig {
+    pub llvm_version: Option<String>,
+    /// A local toolchain to use instead of an installed version, relative to the project root.
+    pub llvm_path: Option<PathBuf>,
+    pub upload: UploadSettings,
+    pub swift_args: Vec<String>,
+    /// Shell commands to run after a successful V5 build.
+    pub post_build_hooks: Vec<String>,
+    /// Where to copy the built program, relative to the project root.
+    pub out_dir: Option<PathBuf>,
+    /// How many jobs `swift build` may run in parallel, or SwiftPM's default if unset.
+    pub jobs: Option<u32>,
+    /// The build profile to use when none is given on the command line.
+    pub default_target: String,
+    pub assume_yes: bool,
+    pub mirror: Option<Url>,
+    pub toolchains_dir: Option<PathBuf>,
+    /// Whether to mention new swift-v5 releases after running a command.
+    pub update_check: bool,
+    /// How replaced toolchains are deleted.
+    pub delete_mode: DeleteMode,
+    /// The GitHub repository toolchains are installed from.
+    pub toolchain_repo: ToolchainRepo,
+    /// Whether `./llvm-toolchain` should be a relative link.
+    pub relative_symlink: bool,
+    /// Where the toolchain is linked, relative to the project root.
+    pub toolchain_link: Option<PathBuf>,
+    /// The build profiles defined in the project, by name.
+    pub profiles: BTreeMap<String, BuildProfile>,
+    /// A GitHub personal access token, used to avoid API rate limits.
+    pub github_token: Option<Secret>,
+    sources: BTreeMap<&'static str, ConfigSource>,
+}
+
+impl EffectiveConfig {
+    pub fn resolve(
+        flags: &ConfigFlags,
+        project: Option<&ProjectConfig>,
+        global: &GlobalConfig,
+    ) -> Result<Self> {
+        let mut sources = BTreeMap::new();
+        let project_upload = project.and_then(|p| p.upload.as_ref());
+
+        // Resolved first, since versions are parsed against the repository's tags
+        let global_toolchain = global.toolchain.clone().unwrap_or_default();
+        let mut toolchain_repo = layer(
+            &mut sources,
+            "toolchain.repo",
+            [
+                (ConfigSource::Global, global_toolchain.repo),
+                (ConfigSource::Default, Some(ToolchainRepo::default())),
+            ],
+        )
+        .unwrap_or_default();
+        if let Some(prefix) = global_toolchain.tag_prefix {
+            toolchain_repo.tag_prefix = prefix;
+        }
+        if let Some(suffix) = global_toolchain.tag_suffix {
+            toolchain_repo.tag_suffix = suffix;
+        }
+
+        let project_llvm_path = project
+            .and_then(|p| p.llvm_path.as_ref())
+            .map(|path| path.get_ref().clone());
+        let llvm_version = layer(
+            &mut sources,
+            "llvm-version",
+            [
+                env_override("SWIFT_V5_LLVM_VERSION", |v| {
+                    toolchain_repo
+                        .parse_version(v)
+                        .map(|_| v.to_string())
+                        .map_err(|e| e.to_string())
+                })?,
+                (
+                    ConfigSource::Project,
+                    project
+                        .and_then(|p| p.llvm_version.as_ref())
+                        .map(|version| version.get_ref().clone()),
+                ),
+                // A project's local toolchain takes the place of the global default
+                (
+                    ConfigSource::Global,
+                    global
+                        .toolchain
+                        .as_ref()
+                        .and_then(|t| t.default.clone())
+                        .filter(|_| project_llvm_path.is_none()),
+                ),
+            ],
+        );
+        // Overriding the version from the environment also overrides a local toolchain
+        let version_overridden = matches!(sources.get("llvm-version"), Some(ConfigSource::Env(_)));
+        let llvm_path = layer(
+            &mut sources,
+            "llvm-path",
+            [(
+                ConfigSource::Project,
+                project_llvm_path.filter(|_| !version_overridden),
+            )],
+        );
+        let slot = layer(
+            &mut sources,
+            "upload.slot",
+            [
+                env_override("SWIFT_V5_SLOT", parse_slot)?,
+                (ConfigSource::Project, project_upload.and_then(|u| u.slot)),
+                (ConfigSource::Default, Some(UploadConfig::DEFAULT_SLOT)),
+            ],
+        );
+        let port = layer(
+            &mut sources,
+            "upload.port",
+            [
+                env_override("SWIFT_V5_PORT", |v| Ok(v.to_string()))?,
+                (
+                    ConfigSource::Project,
+                    project_upload.and_then(|u| u.port.clone()),
+                ),
+                (ConfigSource::Global, global.port.clone()),
+            ],
+        );
+        let after_upload = layer(
+            &mut sources,
+            "upload.after-upload",
+            [
+                env_override("SWIFT_V5_AFTER_UPLOAD", |v| {
+                    AfterUpload::deserialize(v.into_deserializer())
+                        .map_err(|e: serde::de::value::Error| e.to_string())
+                })?,
+                (
+                    ConfigSource::Project,
+                    project_upload.and_then(|u| u.after_upload),
+                ),
+                (ConfigSource::Default, Some(AfterUpload::default())),
+            ],
+        );
+        let swift_args = layer(
+            &mut sources,
+            "build.swift-args",
+            [(
+                ConfigSource::Project,
+                project
+                    .and_then(|p| p.build.as_ref())
+                    .and_then(|b| b.swift_args.as_ref())
+                    .map(|args| args.iter().map(|arg| arg.get_ref().clone()).collect()),
+            )],
+        );
+        let out_dir = layer(
+            &mut sources,
+            "build.out-dir",
+            [(
+                ConfigSource::Project,
+                project
+                    .and_then(|p| p.build.as_ref())
+                    .and_then(|b| b.out_dir.clone()),
+            )],
+        );
+        let jobs = layer(
+            &mut sources,
+            "build.jobs",
+            [(
+                ConfigSource::Project,
+                project.and_then(|p| p.build.as_ref()).and_then(|b| b.jobs),
+            )],
+        );
+        let default_target = layer(
+            &mut sources,
+            "build.default-target",
+            [
+                (
+                    ConfigSource::Project,
+                    project
+                        .and_then(|p| p.build.as_ref())
+                        .and_then(|b| b.default_target.clone()),
+                ),
+                (ConfigSource::Default, Some(BuildTarget::Release.arg())),
+            ],
+        );
+        let post_build_hooks = layer(
+            &mut sources,
+            "hooks.post-build",
+            [(
+                ConfigSource::Project,
+                project
+                    .and_then(|p| p.hooks.as_ref())
+                    .and_then(|h| h.post_build.clone()),
+            )],
+        );
+        let assume_yes = layer(
+            &mut sources,
+            "assume-yes",
+            [
+                (ConfigSource::Flag, flags.assume_yes.then_some(true)),
+                env_override("SWIFT_V5_ASSUME_YES", parse_bool)?,
+                (ConfigSource::Global, global.assume_yes),
+                (ConfigSource::Default, Some(false)),
+            ],
+        );
+        let mirror = layer(
+            &mut sources,
+            "mirror",
+            [
+                env_override("SWIFT_V5_MIRROR", |v| {
+                    Url::parse(v).map_err(|e| e.to_string())
+                })?,
+                (ConfigSource::Global, global.mirror.clone()),
+            ],
+        );
+        let toolchains_dir = layer(
+            &mut sources,
+            "toolchains-dir",
+            [
+                env_override("SWIFT_V5_TOOLCHAINS_DIR", |v| Ok(PathBuf::from(v)))?,
+                (ConfigSource::Global, global.toolchains_dir.clone()),
+            ],
+        );
+        let update_check = layer(
+            &mut sources,
+            "update-check",
+            [
+                env_override("SWIFT_V5_NO_UPDATE_CHECK", |v| parse_bool(v).map(|no| !no))?,
+                (ConfigSource::Global, global.update_check),
+                (ConfigSource::Default, Some(true)),
+            ],
+        );
+        let trash = layer(
+            &mut sources,
+            "trash",
+            [
+                (ConfigSource::Global, global.trash),
+                (ConfigSource::Default, Some(true)),
+            ],
+        );
+
+        let relative_symlink = layer(
+            &mut sources,
+            "toolchain.relative",
+            [
+                (
+                    ConfigSource::Project,
+                    project
+                        .and_then(|p| p.toolchain.as_ref())
+                        .and_then(|t| t.relative),
+                ),
+                (ConfigSource::Default, Some(false)),
+            ],
+        );
+
+        let toolchain_link = layer(
+            &mut sources,
+            "toolchain.link",
+            [(
+                ConfigSource::Project,
+                project
+                    .and_then(|p| p.toolchain.as_ref())
+                    .and_then(|t| t.link.clone()),
+            )],
+        );
+
+        let profiles = project
+            .and_then(|p| p.profiles.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|(name, profile)| {
+                let profile = BuildProfile {
+                    name: name.clone(),
+                    base: profile.base,
+                    swift_args: profile
+                        .swift_args
+                        .iter()
+                        .flatten()
+                        .map(|arg| arg.get_ref().clone())
+                        .collect(),
+                };
+                (name.clone(), profile)
+            })
+            .collect();
+
+        for (key, source) in &sources {
+            debug!(key, %source, "Resolved config value");
+        }
+
+        Ok(Self {
+            llvm_version,
+            llvm_path,
+            upload: UploadSettings {
+                slot: slot.unwrap_or(UploadConfig::DEFAULT_SLOT),
+                port,
+                after_upload: after_upload.unwrap_or_default(),
+            },
+            swift_args: swift_args.unwrap_or_default(),
+            post_build_hooks: post_build_hooks.unwrap_or_default(),
+            out_dir,
+            jobs,
+            default_target: default_target.unwrap_or_else(|| BuildTarget::Release.arg()),
+            assume_yes: assume_yes.unwrap_or_default(),
+            mirror,
+            toolchains_dir,
+            update_check: update_check.unwrap_or(true),
+            delete_mode: if trash.unwrap_or(true) {
+                DeleteMode::Trash
+            } else {
+                DeleteMode::Permanent
+            },
+            toolchain_repo,
+            relative_symlink: relative_symlink.unwrap_or_default(),
+            toolchain_link,
+            profiles,
+            github_token: global.github_token.clone(),
+            sources,
+        })
+    }
+
+    /// Every setting which can be resolved, as `(key, value)` pairs.
+    pub fn entries(&self) -> Vec<(&'static str, Option<toml_edit::Value>)> {
+        vec![
+            ("llvm-version", self.llvm_version.as_deref().map(Into::into)),
+            (
+                "llvm-path",
+                self.llvm_path
+                    .as_ref()
+                    .map(|path| path.display().to_string().into()),
+            ),
+            ("upload.slot", Some(i64::from(self.upload.slot).into())),
+            ("upload.port", self.upload.port.as_deref().map(Into::into)),
+            (
+                "upload.after-upload",
+                Some(self.upload.after_upload.as_ref().into()),
+            ),
+            (
+                "build.swift-args",
+                (!self.swift_args.is_empty())
+                    .then(|| self.swift_args.iter().collect::<toml_edit::Array>().into()),
+            ),
+            (
+                "build.out-dir",
+                self.out_dir
+                    .as_ref()
+                    .map(|dir| dir.display().to_string().into()),
+            ),
+            ("build.jobs", self.jobs.map(|jobs| i64::from(jobs).into())),
+            (
+                "build.default-target",
+                Some(self.default_target.as_str().into()),
+            ),
+            (
+                "hooks.post-build",
+                (!self.post_build_hooks.is_empty()).then(|| {
+                    self.post_build_hooks
+                        .iter()
+                        .collect::<toml_edit::Array>()
+                        .into()
+                }),
+            ),
+            ("assume-yes", Some(self.assume_yes.into())),
+            (
+                "mirror",
+                self.mirror.as_ref().map(|url| url.as_str().into()),
+            ),
+            (
+                "toolchains-dir",
+                self.toolchains_dir
+                    .as_ref()
+                    .map(|dir| dir.display().to_string().into()),
+            ),
+            ("update-check", Some(self.update_check.into())),
+            (
+                "trash",
+                Some((self.delete_mode == DeleteMode::Trash).into()),
+            ),
+            (
+                "toolchain.repo",
+                Some(self.toolchain_repo.to_string().into()),
+            ),
+            ("toolchain.relative", Some(self.relative_symlink.into())),
+            (
+                "toolchain.link",
+                self.toolchain_link
+                    .as_ref()
+                    .map(|link| link.display().to_string().into()),
+            ),
+        ]
+    }
+
+    /// Returns where the value of the given setting came from, if it is set.
+    pub fn source(&self, key: &str) -> Option<ConfigSource> {
+        self.sources.get(key).copied()
+    }
+
+    /// Creates a toolchain client which respects the configured install location, mirror and
+    /// repository.
+    pub async fn toolchain_client(&self) -> Result<ToolchainClient> {
+        let reporter: Arc<dyn InstallReporter> = Arc::new(TerminalReporter::default());
+        let mut client = match &self.toolchains_dir {
+            Some(toolchains_dir) => {
+                ToolchainClient::new(toolchains_dir, ToolchainClient::default_cache_path())
+                    .await?
+                    .with_reporter(reporter)
+            }
+            None => ToolchainClient::using_data_dir(Some(reporter)).await?,
+        };
+
+        if let Some(mirror) = &self.mirror {
+            client = client.with_mirror(mirror.clone());
+        }
+        if let Some(token) = &self.github_token {
+            let github = Octocrab::builder()
+                .personal_token(token.expose().to_string())
+                .build()
+                .map_err(ToolchainError::from)?;
+            client = client.with_github_client(Arc::new(github));
+            client = client.with_github_token(token.clone());
+        }
+
+        Ok(client
+            .with_repo(self.toolchain_repo.clone())
+            .with_delete_mode(self.delete_mode))
+    }
+}
+
+/// Picks the first value which is set, recording where it came from.
+fn layer<T, const N: usize>(
+    sources: &mut BTreeMap<&'static str, ConfigSource>,
+    key: &'static str,
+    candidates: [(ConfigSource, Option<T>); N],
+) -> Option<T> {
+    let (source, value) = candidates
+        .into_iter()
+        .find_map(|(source, value)| Some((source, value?)))?;
+    sources.insert(key, source);
+    Some(value)
+}
+
+/// Reads and parses an environment variable which overrides a setting.
+///
+/// Unset and empty variables are treated as absent.
+fn env_override<T>(
+    name: &'static str,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<(ConfigSource, Option<T>)> {
+    let value = match env::var(name) {
+        Ok(value) if !value.is_empty() => value,
+        _ => return Ok((ConfigSource::Env(name), None)),
+    };
+
+    let parsed = parse(&value).map_err(|reason| crate::Error::InvalidEnvVar {
+        name,
+        value: value.clone(),
+        reason,
+    })?;
+    Ok((ConfigSource::Env(name), Some(parsed)))
+}
+
+fn parse_slot(value: &str) -> Result<u8, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|slot| UploadConfig::SLOTS.contains(slot))
+        .ok_or_else(|| {
+            format!(
+                "expected a slot from {} to {}",
+                UploadConfig::SLOTS.start(),
+                UploadConfig::SLOTS.end()
+            )
+        })
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => Ok(true),
+        "0" | "false" | "no" => Ok(false),
+        _ => Err("expected `true` or `false`".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::*;
+
+    /// Held by tests which set environment variables or check settings they override, since
+    /// the environment is shared by every test.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    fn lock_env() -> MutexGuard<'static, ()> {
+        // A failed test doesn't leave anything behind, see `with_env`
+        ENV_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn global(contents: &str) -> GlobalConfig {
+        toml::from_str(contents).unwrap()
+    }
+
+    fn project(contents: &str) -> ProjectConfig {
+        ProjectConfig::parse(Path::new("v5.toml"), contents.to_string()).unwrap()
+    }
+
+    #[test]
+    fn project_over_global() {
+        let _env = lock_env();
+        let global = global(
+            "port = \"/dev/global\"\nassume-yes = true\ntrash = false\n[toolchain]\ndefault = \"20.1.0\"\n",
+        );
+        let project = project("llvm-version = \"21.1.1\"\n[upload]\nport = \"/dev/project\"\n");
+        let effective =
+            EffectiveConfig::resolve(&ConfigFlags::default(), Some(&project), &global).unwrap();
+
+        assert_eq!(effective.llvm_version.as_deref(), Some("21.1.1"));
+        assert_eq!(
+            effective.source("llvm-version"),
+            Some(ConfigSource::Project)
+        );
+        assert_eq!(effective.upload.port.as_deref(), Some("/dev/project"));
+        assert_eq!(effective.source("upload.port"), Some(ConfigSource::Project));
+
+        // Settings the project doesn't have come from the global config, then the defaults
+        assert!(effective.assume_yes);
+        assert_eq!(effective.source("assume-yes"), Some(ConfigSource::Global));
+        assert_eq!(effective.delete_mode, DeleteMode::Permanent);
+        assert_eq!(effective.upload.slot, UploadConfig::DEFAULT_SLOT);
+        assert_eq!(effective.source("upload.slot"), Some(ConfigSource::Default));
+        assert_eq!(effective.source("mirror"), None);
+    }
+
+    #[test]
+    fn global_fills_in_for_project() {
+        let _env = lock_env();
+        let global = global("port = \"/dev/global\"\n[toolchain]\ndefault = \"20.1.0\"\n");
+
+        let effective = EffectiveConfig::resolve(&ConfigFlags::default(), None, &global).unwrap();
+        assert_eq!(effective.llvm_version.as_deref(), Some("20.1.0"));
+        assert_eq!(effective.source("llvm-version"), Some(ConfigSource::Global));
+        assert_eq!(effective.upload.port.as_deref(), Some("/dev/global"));
+        assert_eq!(effective.source("upload.port"), Some(ConfigSource::Global));
+
+        // A local toolchain takes the place of the global default version
+        let project = project("llvm-path = \"../llvm\"\n");
+        let effective =
+            EffectiveConfig::resolve(&ConfigFlags::default(), Some(&project), &global).unwrap();
+        assert_eq!(effective.llvm_version, None);
+        assert_eq!(effective.llvm_path, Some(PathBuf::from("../llvm")));
+    }
+
+    #[test]
+    fn flags_over_global() {
+        let _env = lock_env();
+        let flags = ConfigFlags { assume_yes: true };
+        let effective =
+            EffectiveConfig::resolve(&flags, None, &global("assume-yes = false\n")).unwrap();
+        assert!(effective.assume_yes);
+        assert_eq!(effective.source("assume-yes"), Some(ConfigSource::Flag));
+    }
+
+    /// Sets environment variables while `f` runs, removing them afterwards even if it panics.
+    fn with_env(vars: &[(&str, &str)], f: impl FnOnce()) {
+        let _env = lock_env();
+        for (name, value) in vars {
+            // SAFETY: the standard library serializes its own reads and writes of the
+            // environment, and nothing in the tests reads it any other way
+            unsafe { env::set_var(name, value) };
+        }
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+        for (name, _) in vars {
+            // SAFETY: as above
+            unsafe { env::remove_var(name) };
+        }
+        if let Err(panic) = result {
+            std::panic::resume_unwind(panic);
+        }
+    }
+
+    #[test]
+    fn env_over_project() {
+        let project = project("llvm-path = \"../llvm\"\n[upload]\nport = \"/dev/project\"\n");
+        with_env(
+            &[
+                ("SWIFT_V5_LLVM_VERSION", "21.1.1"),
+                ("SWIFT_V5_PORT", "/dev/env"),
+                ("SWIFT_V5_AFTER_UPLOAD", "run"),
+            ],
+            || {
+                let effective = EffectiveConfig::resolve(
+                    &ConfigFlags::default(),
+                    Some(&project),
+                    &GlobalConfig::default(),
+                )
+                .unwrap();
+
+                assert_eq!(effective.upload.port.as_deref(), Some("/dev/env"));
+                assert_eq!(
+                    effective.source("upload.port"),
+                    Some(ConfigSource::Env("SWIFT_V5_PORT"))
+                );
+                assert_eq!(effective.upload.after_upload, AfterUpload::Run);
+                // Overriding the version also overrides the project's local toolchain
+                assert_eq!(effective.llvm_version.as_deref(), Some("21.1.1"));
+                assert_eq!(
+                    effective.source("llvm-version"),
+                    Some(ConfigSource::Env("SWIFT_V5_LLVM_VERSION"))
+                );
+                assert_eq!(effective.llvm_path, None);
+                assert_eq!(effective.source("llvm-path"), None);
+            },
+        );
+    }
+
+    #[test]
+    fn flags_over_env() {
+        with_env(&[("SWIFT_V5_ASSUME_YES", "false")], || {
+            let flags = ConfigFlags { assume_yes: true };
+            let effective =
+                EffectiveConfig::resolve(&flags, None, &GlobalConfig::default()).unwrap();
+            assert_eq!(effective.source("assume-yes"), Some(ConfigSource::Flag));
+        });
+    }
+
+    #[test]
+    fn empty_env_is_unset() {
+        let project = project("llvm-version = \"21.1.1\"\n[upload]\nport = \"/dev/project\"\n");
+        with_env(&[("SWIFT_V5_PORT", "")], || {
+            let effective = EffectiveConfig::resolve(
+                &ConfigFlags::default(),
+                Some(&project),
+                &GlobalConfig::default(),
+            )
+            .unwrap();
+            assert_eq!(effective.source("upload.port"), Some(ConfigSource::Project));
+        });
+    }
+
+    #[test]
+    fn invalid_env() {
+        // A variable of its own, so other tests resolving their config don't see it
+        const NAME: &str = "SWIFT_V5_TEST_INVALID_SLOT";
+        with_env(&[(NAME, "9")], || {
+            let error = env_override(NAME, parse_slot).unwrap_err();
+            assert!(matches!(
+                error,
+                crate::Error::InvalidEnvVar { name: NAME, ref value, .. } if value == "9"
+            ));
+        });
+        with_env(&[(NAME, "8")], || {
+            let (source, slot) = env_override(NAME, parse_slot).unwrap();
+            assert_eq!((source, slot), (ConfigSource::Env(NAME), Some(8)));
+        });
+    }
+}
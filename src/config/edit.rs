@@ -0,0 +1,231 @@
+//! Reading and editing the project's `v5.toml` from the command line.
+//!
+//! Edits are made with `toml_edit` so that comments and formatting in the file survive, and
+//! the edited file is validated before it is written so that it's never left in an invalid state.
+
+use std::io::ErrorKind;
+
+use toml_edit::{DocumentMut, Item, Table, TableLike, Value};
+
+use crate::{
+    Error, Result,
+    config::{Config, ConfigSource, GlobalConfig, TomlError},
+    fs, msg,
+    project::{Project, ProjectConfig},
+    ui,
+};
+
+/// Prints the resolved value of `key`, or every setting and where it came from if no key is given.
+pub async fn get(key: Option<&str>, config: &Config) -> Result<()> {
+    let project = match Project::find().await {
+        Ok(project) => Some(project),
+        Err(Error::CannotFindProject { .. }) => None,
+        Err(error) => return Err(error),
+    };
+    let project_config = match &project {
+        Some(project) => project.config().await?,
+        None => None,
+    };
+
+    let effective = config.resolve(project_config)?;
+    let entries = effective.entries();
+
+    let Some(key) = key else {
+        for (key, value) in entries {
+            match value {
+                Some(value) => {
+                    let source = match (effective.source(key), &project) {
+                        // Name the file, since it may be in a parent directory
+                        (Some(ConfigSource::Project), Some(project)) => {
+                            project.config_path().display().to_string()
+                        }
+                        (source, _) => source.expect("set values have a source").to_string(),
+                    };
+                    println!(
+                        "{key} = {value} {}",
+                        ui::paint(format!("# {source}"), ui::DIMMED)
+                    );
+                }
+                None => println!("{}", ui::paint(format!("# {key} is not set"), ui::DIMMED)),
+            }
+        }
+
+        return Ok(());
+    };
+
+    let (_, value) = entries
+        .into_iter()
+        .find(|(name, _)| *name == key)
+        .ok_or_else(|| Error::InvalidConfigKey {
+            key: key.to_string(),
+            valid_keys: effective.entries().into_iter().map(|(k, _)| k).collect(),
+        })?;
+
+    match value {
+        Some(Value::String(value)) => println!("{}", value.value()),
+        Some(value) => println!("{value}"),
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Sets `key` to `value` in the project's `v5.toml`, creating the file if it doesn't exist.
+///
+/// The value is interpreted as a TOML value if possible (e.g. `8` or `true`), and as a string otherwise.
+pub async fn set(key: &str, value: &str) -> Result<()> {
+    let project = Project::find().await?;
+    let document = read_document(&project).await?;
+
+    // Prefer a typed value, but fall back to a string so that versions like `20.1` aren't
+    // interpreted as floats.
+    let mut candidates = Vec::with_capacity(2);
+    if let Ok(parsed) = value.parse::<Value>() {
+        candidates.push(parsed.decorated(" ", ""));
+    }
+    candidates.push(Value::from(value).decorated(" ", ""));
+
+    let mut first_error = None;
+    for candidate in candidates {
+        let mut document = document.clone();
+        let (table, last) = table_for(&mut document, key, true)?;
+        let table = table.expect("tables are created when missing");
+        match table.get_mut(last).and_then(Item::as_value_mut) {
+            // Replace the value in-place to keep any comments around it
+            Some(existing) => {
+                let decor = existing.decor().clone();
+                *existing = candidate.clone();
+                *existing.decor_mut() = decor;
+            }
+            None => {
+                table.insert(last, Item::Value(candidate.clone()));
+            }
+        }
+
+        match write_validated(&project, document).await {
+            Ok(()) => {
+                msg!("Updated", "{key} = {}", candidate.to_string().trim());
+                return Ok(());
+            }
+            Err(error) => {
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+
+    Err(first_error.expect("at least one candidate value"))
+}
+
+/// Removes `key` from the project's `v5.toml`.
+pub async fn unset(key: &str) -> Result<()> {
+    let project = Project::find().await?;
+    let mut document = read_document(&project).await?;
+
+    let (table, last) = table_for(&mut document, key, false)?;
+    if table.and_then(|table| table.remove(last)).is_none() {
+        eprintln!("{key} is not set in {}", project.config_path().display());
+        return Ok(());
+    }
+
+    write_validated(&project, document).await?;
+    msg!("Removed", "{key}");
+
+    Ok(())
+}
+
+/// Sets or removes (if `value` is `None`) `key` in the global `config.toml`, creating the file
+/// if it doesn't exist.
+///
+/// Returns whether anything changed.
+pub async fn set_global(key: &str, value: Option<Value>) -> Result<bool> {
+    let path = GlobalConfig::path();
+    let contents = match fs::read_to_string(&path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut document: DocumentMut = contents.parse().map_err(|error| {
+        Error::InvalidGlobalConfig(Box::new(TomlError::from_edit(&path, contents, &error)))
+    })?;
+
+    let (table, last) = table_for(&mut document, key, value.is_some())?;
+    match (table, value) {
+        (Some(table), Some(value)) => {
+            table.insert(last, Item::Value(value.decorated(" ", "")));
+        }
+        (Some(table), None) => {
+            if table.remove(last).is_none() {
+                return Ok(false);
+            }
+        }
+        (None, _) => return Ok(false),
+    }
+
+    let contents = document.to_string();
+    toml::from_str::<GlobalConfig>(&contents).map_err(|error| {
+        Error::InvalidGlobalConfig(Box::new(TomlError::new(&path, contents.clone(), &error)))
+    })?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(&path, contents).await?;
+
+    Ok(true)
+}
+
+async fn read_document(project: &Project) -> Result<DocumentMut> {
+    let path = project.config_path();
+    let contents = match fs::read_to_string(&path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    contents.parse().map_err(|error| {
+        Error::InvalidConfig(Box::new(TomlError::from_edit(&path, contents, &error)))
+    })
+}
+
+/// Checks that the edited document is a valid config before writing it to disk.
+async fn write_validated(project: &Project, document: DocumentMut) -> Result<()> {
+    let path = project.config_path();
+    let contents = document.to_string();
+    ProjectConfig::parse(&path, contents.clone())?;
+    fs::write(&path, contents).await?;
+    Ok(())
+}
+
+/// Finds the table containing the last component of a dotted key path like `upload.slot`.
+///
+/// Missing tables are created if `create` is true, otherwise `None` is returned for them.
+fn table_for<'a, 'k>(
+    document: &'a mut DocumentMut,
+    key: &'k str,
+    create: bool,
+) -> Result<(Option<&'a mut dyn TableLike>, &'k str)> {
+    let invalid_key = || Error::InvalidConfigKey {
+        key: key.to_string(),
+        valid_keys: Vec::new(),
+    };
+
+    let mut parts: Vec<&str> = key.split('.').collect();
+    if parts.iter().any(|part| part.is_empty()) {
+        return Err(invalid_key());
+    }
+
+    let last = parts.pop().expect("split yields at least one part");
+    let mut table: &mut dyn TableLike = document.as_table_mut();
+    for part in parts {
+        if !create && !table.contains_key(part) {
+            return Ok((None, last));
+        }
+
+        table = table
+            .entry(part)
+            .or_insert_with(|| Item::Table(Table::new()))
+            .as_table_like_mut()
+            .ok_or_else(invalid_key)?;
+    }
+
+    Ok((Some(table), last))
+}
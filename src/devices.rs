@@ -0,0 +1,421 @@
+//! Finding V5 brains and controllers connected over USB.
+//!
+//! The brain exposes two serial ports: the system port, used for uploads and other
+//! management, and the user port, which carries the program's stdio. A controller connected
+//! over USB exposes a single port which is forwarded to the brain it's paired with.
+
+use std::io::{self, IsTerminal};
+
+use clap::{Args, ValueEnum};
+use inquire::Select;
+use miette::Diagnostic;
+use serialport::{SerialPortInfo, SerialPortType, UsbPortInfo};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::ui;
+
+/// VEX Robotics' USB vendor ID.
+pub const VEX_VID: u16 = 0x2888;
+/// The USB product ID of the V5 brain.
+pub const BRAIN_PID: u16 = 0x0501;
+/// The USB product ID of the V5 controller.
+pub const CONTROLLER_PID: u16 = 0x0503;
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum DeviceError {
+    #[error("Failed to list serial ports")]
+    #[diagnostic(code(swift_v5::devices::enumeration_failed))]
+    Enumeration(#[from] serialport::Error),
+    #[error(
+        "No V5 brain or controller is connected.\n{}",
+        if seen.is_empty() {
+            "No serial ports were found.".to_string()
+        } else {
+            format!(
+                "Serial ports:\n{}",
+                seen.iter().map(|port| format!(" • {port}")).collect::<Vec<_>>().join("\n")
+            )
+        }
+    )]
+    #[diagnostic(code(swift_v5::devices::not_found))]
+    #[diagnostic(help("check the USB cable, and that the brain or controller is turned on"))]
+    NotFound { seen: Vec<String> },
+    #[error(
+        "More than one V5 device is connected:\n{}",
+        ports.iter().map(|port| format!(" • {port}")).collect::<Vec<_>>().join("\n")
+    )]
+    #[diagnostic(code(swift_v5::devices::ambiguous))]
+    #[diagnostic(help("choose one with `--port`"))]
+    Ambiguous { ports: Vec<V5Port> },
+}
+
+/// The role of a serial port exposed by V5 hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortKind {
+    /// The brain's system port, used for uploading programs.
+    BrainSystem,
+    /// The brain's user port, which carries the program's stdio.
+    BrainUser,
+    /// A controller, which forwards to the brain it's paired with.
+    Controller,
+}
+
+impl std::fmt::Display for PortKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PortKind::BrainSystem => "Brain (system port)",
+            PortKind::BrainUser => "Brain (user port)",
+            PortKind::Controller => "Controller",
+        })
+    }
+}
+
+/// A serial port belonging to V5 hardware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct V5Port {
+    pub path: String,
+    pub kind: PortKind,
+    pub serial_number: Option<String>,
+}
+
+impl std::fmt::Display for V5Port {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} — {}", self.path, self.kind)?;
+        if let Some(serial_number) = &self.serial_number {
+            write!(f, " ({serial_number})")?;
+        }
+        Ok(())
+    }
+}
+
+/// What a port is going to be used for, which decides the kind of port to pick.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortPurpose {
+    /// Uploading programs, which needs the brain's system port
+    Upload,
+    /// Talking to a running program, which uses the brain's user port
+    Terminal,
+}
+
+impl PortPurpose {
+    /// The kinds of port which work for this purpose, best first.
+    ///
+    /// A controller works for both, since it forwards everything to its brain, but a brain
+    /// connected directly is faster.
+    pub const fn preference(self) -> &'static [PortKind] {
+        match self {
+            PortPurpose::Upload => &[PortKind::BrainSystem, PortKind::Controller],
+            PortPurpose::Terminal => &[PortKind::BrainUser, PortKind::Controller],
+        }
+    }
+}
+
+/// Describes every serial port for error messages, like `/dev/ttyACM0 (USB 2888:0501)`.
+fn describe_ports(ports: &[SerialPortInfo]) -> Vec<String> {
+    ports
+        .iter()
+        .map(|port| match &port.port_type {
+            SerialPortType::UsbPort(info) => {
+                format!("{} (USB {:04x}:{:04x})", port.port_name, info.vid, info.pid)
+            }
+            _ => format!("{} (not USB)", port.port_name),
+        })
+        .collect()
+}
+
+/// Ranks the V5 ports which can be used for `purpose`, dropping the rest.
+///
+/// Only ports of the best kind available are kept, so a controller is ignored when a brain
+/// is connected directly.
+pub fn rank_ports(ports: Vec<V5Port>, purpose: PortPurpose) -> Vec<V5Port> {
+    let rank = |port: &V5Port| {
+        purpose
+            .preference()
+            .iter()
+            .position(|&kind| kind == port.kind)
+    };
+    let Some(best) = ports.iter().filter_map(rank).min() else {
+        return Vec::new();
+    };
+
+    ports
+        .into_iter()
+        .filter(|port| rank(port) == Some(best))
+        .collect()
+}
+
+/// Picks the port to use for `purpose`, or the one given with `--port`.
+///
+/// If several devices are connected, the user is asked to choose one, which fails without a
+/// terminal to ask in.
+pub fn select_port(purpose: PortPurpose, port: Option<&str>) -> crate::Result<V5Port> {
+    let seen = serialport::available_ports().map_err(DeviceError::from)?;
+    debug!(?seen, "Found serial ports");
+    let v5_ports = classify_ports(seen.clone());
+
+    if let Some(path) = port {
+        // The port may not look like V5 hardware, e.g. if it's behind a USB adapter
+        return Ok(v5_ports
+            .into_iter()
+            .find(|candidate| candidate.path == path)
+            .unwrap_or_else(|| V5Port {
+                path: path.to_string(),
+                kind: purpose.preference()[0],
+                serial_number: None,
+            }));
+    }
+
+    let mut candidates = rank_ports(v5_ports, purpose);
+    debug!(?candidates, ?purpose, "Ranked ports");
+    match candidates.len() {
+        0 => Err(DeviceError::NotFound {
+            seen: describe_ports(&seen),
+        }
+        .into()),
+        1 => Ok(candidates.remove(0)),
+        _ if io::stdin().is_terminal() && io::stderr().is_terminal() => {
+            Ok(Select::new("Which device?", candidates).prompt()?)
+        }
+        _ => Err(DeviceError::Ambiguous { ports: candidates }.into()),
+    }
+}
+
+/// Lists the serial ports of every connected V5 brain and controller.
+pub fn find_ports() -> Result<Vec<V5Port>, DeviceError> {
+    let ports = serialport::available_ports()?;
+    debug!(?ports, "Found serial ports");
+    Ok(classify_ports(ports))
+}
+
+/// Picks out the ports belonging to V5 hardware and works out what each of them is for.
+pub fn classify_ports(mut ports: Vec<SerialPortInfo>) -> Vec<V5Port> {
+    // Sorting by name keeps each brain's ports in interface order when the OS
+    // doesn't report interface numbers.
+    ports.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+
+    let mut v5_ports = Vec::new();
+    let mut brain_ports_without_interface = 0;
+    for port in ports {
+        let SerialPortType::UsbPort(info) = &port.port_type else {
+            continue;
+        };
+        if info.vid != VEX_VID {
+            continue;
+        }
+
+        let kind = match info.pid {
+            CONTROLLER_PID => PortKind::Controller,
+            BRAIN_PID => match brain_port_kind(info) {
+                Some(kind) => kind,
+                None => {
+                    brain_ports_without_interface += 1;
+                    if brain_ports_without_interface % 2 == 1 {
+                        PortKind::BrainSystem
+                    } else {
+                        PortKind::BrainUser
+                    }
+                }
+            },
+            pid => {
+                debug!(port = port.port_name, pid, "Skipping unknown VEX device");
+                continue;
+            }
+        };
+
+        v5_ports.push(V5Port {
+            kind,
+            serial_number: info.serial_number.clone(),
+            path: port.port_name,
+        });
+    }
+
+    v5_ports
+}
+
+/// Classifies a brain port by its USB interface number.
+///
+/// Windows and Linux report the communication interface (0 or 2), while macOS reports the
+/// data interface (1 or 3).
+fn brain_port_kind(info: &UsbPortInfo) -> Option<PortKind> {
+    match info.interface? {
+        0 | 1 => Some(PortKind::BrainSystem),
+        2 | 3 => Some(PortKind::BrainUser),
+        _ => None,
+    }
+}
+
+#[derive(Args, Debug, Default)]
+pub struct DevicesOpts {
+    /// Print the path of the port to use for this, instead of listing every device
+    #[arg(long, value_enum, value_name = "PURPOSE")]
+    pub select: Option<PortPurpose>,
+    /// The port to use, instead of detecting it
+    #[arg(long, requires = "select")]
+    pub port: Option<String>,
+}
+
+/// Lists the connected V5 hardware, or prints the port picked for a purpose.
+pub fn devices(opts: &DevicesOpts) -> crate::Result<()> {
+    match opts.select {
+        Some(purpose) => {
+            let port = select_port(purpose, opts.port.as_deref())?;
+            println!("{}", port.path);
+            Ok(())
+        }
+        None => list(),
+    }
+}
+
+/// Prints a table of the connected V5 hardware.
+pub fn list() -> crate::Result<()> {
+    let ports = find_ports()?;
+    if ports.is_empty() {
+        eprintln!("No V5 brains or controllers found.");
+        return Ok(());
+    }
+
+    let width = ports
+        .iter()
+        .map(|port| port.path.len())
+        .max()
+        .unwrap_or_default()
+        .max("PORT".len());
+
+    println!(
+        "{:width$}  {:20}  {}",
+        ui::paint("PORT", ui::EMPHASIS),
+        ui::paint("TYPE", ui::EMPHASIS),
+        ui::paint("SERIAL", ui::EMPHASIS)
+    );
+    for port in ports {
+        println!(
+            "{:width$}  {:20}  {}",
+            port.path,
+            port.kind.to_string(),
+            port.serial_number.as_deref().unwrap_or("-")
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(path: &str, kind: PortKind) -> V5Port {
+        V5Port {
+            path: path.to_string(),
+            kind,
+            serial_number: None,
+        }
+    }
+
+    fn paths(ports: &[V5Port]) -> Vec<&str> {
+        ports.iter().map(|port| port.path.as_str()).collect()
+    }
+
+    #[test]
+    fn brain_over_controller() {
+        let ports = vec![
+            port("controller", PortKind::Controller),
+            port("system", PortKind::BrainSystem),
+            port("user", PortKind::BrainUser),
+        ];
+        assert_eq!(
+            paths(&rank_ports(ports.clone(), PortPurpose::Upload)),
+            ["system"]
+        );
+        assert_eq!(paths(&rank_ports(ports, PortPurpose::Terminal)), ["user"]);
+    }
+
+    #[test]
+    fn controller_alone() {
+        let ports = vec![port("controller", PortKind::Controller)];
+        assert_eq!(
+            paths(&rank_ports(ports.clone(), PortPurpose::Upload)),
+            ["controller"]
+        );
+        assert_eq!(
+            paths(&rank_ports(ports, PortPurpose::Terminal)),
+            ["controller"]
+        );
+    }
+
+    #[test]
+    fn several_brains() {
+        let ports = vec![
+            port("system-1", PortKind::BrainSystem),
+            port("user-1", PortKind::BrainUser),
+            port("system-2", PortKind::BrainSystem),
+            port("user-2", PortKind::BrainUser),
+            port("controller", PortKind::Controller),
+        ];
+        assert_eq!(
+            paths(&rank_ports(ports, PortPurpose::Upload)),
+            ["system-1", "system-2"]
+        );
+    }
+
+    #[test]
+    fn no_usable_ports() {
+        assert!(rank_ports(Vec::new(), PortPurpose::Upload).is_empty());
+        // A user port can't be uploaded to
+        let ports = vec![port("user", PortKind::BrainUser)];
+        assert!(rank_ports(ports, PortPurpose::Upload).is_empty());
+    }
+
+    fn usb(name: &str, pid: u16, interface: Option<u8>) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: name.to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid: VEX_VID,
+                pid,
+                serial_number: None,
+                manufacturer: None,
+                product: None,
+                interface,
+            }),
+        }
+    }
+
+    #[test]
+    fn classifies_ports() {
+        let ports = classify_ports(vec![
+            usb("/dev/ttyACM1", BRAIN_PID, Some(2)),
+            usb("/dev/ttyACM0", BRAIN_PID, Some(0)),
+            usb("/dev/cu.usbmodem3", BRAIN_PID, Some(3)),
+            usb("/dev/ttyACM2", CONTROLLER_PID, None),
+            usb("/dev/ttyACM3", 0x0999, None),
+            SerialPortInfo {
+                port_name: "/dev/ttyS0".to_string(),
+                port_type: SerialPortType::Unknown,
+            },
+        ]);
+        let kinds: Vec<(&str, PortKind)> = ports
+            .iter()
+            .map(|port| (port.path.as_str(), port.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("/dev/cu.usbmodem3", PortKind::BrainUser),
+                ("/dev/ttyACM0", PortKind::BrainSystem),
+                ("/dev/ttyACM1", PortKind::BrainUser),
+                ("/dev/ttyACM2", PortKind::Controller),
+            ]
+        );
+    }
+
+    #[test]
+    fn pairs_ports_without_interfaces() {
+        let ports = classify_ports(vec![
+            usb("COM4", BRAIN_PID, None),
+            usb("COM3", BRAIN_PID, None),
+        ]);
+        let kinds: Vec<PortKind> = ports.iter().map(|port| port.kind).collect();
+        assert_eq!(kinds, [PortKind::BrainSystem, PortKind::BrainUser]);
+        assert_eq!(paths(&ports), ["COM3", "COM4"]);
+    }
+}
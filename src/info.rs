@@ -0,0 +1,325 @@
+//! Summarizing what swift-v5 knows about the current project and environment.
+//!
+//! Each part of the summary is probed separately, so that one failing (like `swift` not being
+//! installed) is reported in its place instead of hiding the rest.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use serde::Serialize;
+use toml_edit::Value;
+
+use crate::{
+    build::swift_info,
+    config::{Config, ConfigSource, EffectiveConfig},
+    project::Project,
+    symlink::{check_toolchain, link_path, resolve_link},
+    toolchain::{InstallationState, ToolchainError, is_toolchain_dir},
+    ui::{self, OutputFormat},
+};
+
+#[derive(Args, Debug, Default)]
+pub struct InfoOpts {
+    /// How to print the summary
+    #[arg(long, value_enum, default_value_t)]
+    pub output: OutputFormat,
+}
+
+/// The result of looking something up, which is shown in place of the value if it failed.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Probe<T> {
+    Found(T),
+    Failed { error: String },
+}
+
+impl<T> From<crate::Result<T>> for Probe<T> {
+    fn from(result: crate::Result<T>) -> Self {
+        match result {
+            Ok(value) => Self::Found(value),
+            Err(error) => Self::Failed {
+                error: error.to_string(),
+            },
+        }
+    }
+}
+
+impl<T> Probe<T> {
+    /// A probe which wasn't run, since something it depends on failed.
+    fn skipped(reason: &str) -> Self {
+        Self::Failed {
+            error: reason.to_string(),
+        }
+    }
+
+    /// Formats the value with `f`, or the error if the probe failed.
+    fn display(&self, f: impl FnOnce(&T) -> String) -> String {
+        match self {
+            Self::Found(value) => f(value),
+            // Errors like "couldn't find the project" list details on later lines
+            Self::Failed { error } => {
+                let summary = error.lines().next().unwrap_or_default();
+                ui::paint(summary, ui::WARNING).to_string()
+            }
+        }
+    }
+}
+
+/// Everything `swift v5 info` reports.
+#[derive(Debug, Serialize)]
+struct Info {
+    /// The project's root directory.
+    project: Probe<PathBuf>,
+    /// The settings in `v5.toml`, or `None` if the project doesn't have one.
+    config: Probe<Option<BTreeMap<&'static str, String>>>,
+    toolchain: Probe<ToolchainInfo>,
+    link: Probe<LinkInfo>,
+    /// The version reported by `swift --version`.
+    swift: Probe<String>,
+    /// The package's executable products.
+    executables: Probe<Vec<String>>,
+}
+
+/// The toolchain the project builds with.
+#[derive(Debug, Serialize)]
+struct ToolchainInfo {
+    /// The version, or `None` for a local toolchain.
+    version: Option<String>,
+    path: PathBuf,
+    /// One of `installed`, `partial`, `absent`, or `local` for a local toolchain.
+    state: &'static str,
+}
+
+/// Where the project's toolchain link points.
+#[derive(Debug, Serialize)]
+struct LinkInfo {
+    path: PathBuf,
+    /// Where the link points, or `None` if there's no link.
+    target: Option<PathBuf>,
+    /// Whether the link leads to the project's toolchain.
+    matches: bool,
+    /// Why the linked toolchain can't be built with, if it can't.
+    problem: Option<String>,
+}
+
+/// Prints a summary of the project, its toolchain and the Swift installation.
+pub async fn info(opts: &InfoOpts, config: &Config) -> crate::Result<()> {
+    let project = Project::find().await;
+    // Settings which can't be resolved, like an invalid environment variable, are reported in
+    // place of the toolchain like any other failure
+    let (settings, effective) = match &project {
+        Ok(project) => match project.config().await {
+            Ok(project_config) => {
+                let effective = config.resolve(project_config);
+                let settings = match &effective {
+                    Ok(effective) => {
+                        Probe::Found(project_config.map(|_| project_settings(effective)))
+                    }
+                    Err(error) => Probe::Failed {
+                        error: error.to_string(),
+                    },
+                };
+                (settings, effective)
+            }
+            Err(error) => (Err(error).into(), config.resolve(None)),
+        },
+        Err(_) => (Probe::skipped("no project"), config.resolve(None)),
+    };
+
+    let toolchain = match &effective {
+        Ok(effective) => resolve_toolchain(project.as_ref().ok(), effective)
+            .await
+            .into(),
+        Err(error) => Probe::Failed {
+            error: error.to_string(),
+        },
+    };
+    let link = match (&project, &effective, &toolchain) {
+        (Err(_), _, _) => Probe::skipped("no project"),
+        (Ok(project), Ok(effective), Probe::Found(toolchain)) => {
+            Probe::Found(link_info(project, effective, &toolchain.path))
+        }
+        (_, Err(_), _) => Probe::skipped("invalid configuration"),
+        (_, _, Probe::Failed { .. }) => Probe::skipped("no toolchain"),
+    };
+    let executables = match &project {
+        Ok(_) => Project::executable_names().into(),
+        Err(_) => Probe::skipped("no project"),
+    };
+
+    let info = Info {
+        project: project.map(|project| project.path().to_owned()).into(),
+        config: settings,
+        toolchain,
+        link,
+        swift: swift_info(None)
+            .map(|info| {
+                info.version
+                    .clone()
+                    .unwrap_or_else(|| "unknown".to_string())
+            })
+            .into(),
+        executables,
+    };
+
+    if opts.output == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&info).expect("info is serializable")
+        );
+        return Ok(());
+    }
+
+    row(
+        "Project",
+        info.project.display(|path| path.display().to_string()),
+    );
+    row(
+        "Config",
+        info.config.display(|settings| match settings {
+            None => "none".to_string(),
+            Some(settings) if settings.is_empty() => "none set".to_string(),
+            Some(settings) => settings
+                .iter()
+                .map(|(key, value)| format!("{key} = {value}"))
+                .collect::<Vec<_>>()
+                .join(", "),
+        }),
+    );
+    row(
+        "Toolchain",
+        info.toolchain.display(|toolchain| {
+            let name = match &toolchain.version {
+                Some(version) => format!("v{version}"),
+                None => "local".to_string(),
+            };
+            format!(
+                "{name} ({}) at {}",
+                toolchain.state,
+                ui::paint(toolchain.path.display(), ui::LOCATION)
+            )
+        }),
+    );
+    row(
+        "Link",
+        info.link.display(|link| match &link.target {
+            None => format!("{} doesn't exist", link.path.display()),
+            Some(target) => format!(
+                "{} → {} {}",
+                link.path.display(),
+                ui::paint(target.display(), ui::LOCATION),
+                match &link.problem {
+                    Some(problem) => ui::paint(format!("({problem})"), ui::WARNING).to_string(),
+                    None if link.matches => ui::paint("(matches)", ui::DIMMED).to_string(),
+                    None => ui::paint("(doesn't match)", ui::WARNING).to_string(),
+                }
+            ),
+        }),
+    );
+    row("Swift", info.swift.display(Clone::clone));
+    row(
+        "Executables",
+        info.executables.display(|names| {
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        }),
+    );
+
+    Ok(())
+}
+
+/// Prints a labelled row of the summary, styled like a status line.
+fn row(label: &str, value: impl Display) {
+    println!("{:>12} {value}", ui::paint(label, ui::LABEL));
+}
+
+/// Lists the settings which come from the project's `v5.toml`.
+fn project_settings(effective: &EffectiveConfig) -> BTreeMap<&'static str, String> {
+    effective
+        .entries()
+        .into_iter()
+        .filter(|(key, _)| effective.source(key) == Some(ConfigSource::Project))
+        .filter_map(|(key, value)| {
+            let value = match value? {
+                Value::String(value) => value.into_value(),
+                value => value.to_string().trim().to_string(),
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// Works out which toolchain the project builds with, without using the network.
+///
+/// Like `which`, the newest installed toolchain stands in if no version is pinned.
+async fn resolve_toolchain(
+    project: Option<&Project>,
+    effective: &EffectiveConfig,
+) -> crate::Result<ToolchainInfo> {
+    if let (Some(project), Some(llvm_path)) = (project, &effective.llvm_path) {
+        let path = project.path().join(llvm_path);
+        return Ok(ToolchainInfo {
+            version: None,
+            state: if is_toolchain_dir(&path) {
+                "local"
+            } else {
+                "absent"
+            },
+            path,
+        });
+    }
+
+    let toolchain = effective.toolchain_client().await?;
+    let version = match &effective.llvm_version {
+        Some(llvm_version) => effective.toolchain_repo.parse_version(llvm_version)?,
+        None => toolchain
+            .installed_versions()
+            .await?
+            .pop()
+            .ok_or(ToolchainError::NoneInstalled)?,
+    };
+
+    Ok(ToolchainInfo {
+        path: toolchain.install_path_for(&version)?,
+        state: match toolchain.installation_state(&version) {
+            InstallationState::Installed => "installed",
+            InstallationState::Partial => "partial",
+            InstallationState::Absent => "absent",
+        },
+        version: Some(version.name),
+    })
+}
+
+/// Reads where the project's toolchain link points, and whether that's `expected`.
+fn link_info(project: &Project, effective: &EffectiveConfig, expected: &Path) -> LinkInfo {
+    let path = link_path(project, effective);
+    let target = match resolve_link(&path) {
+        Ok(target) => Some(target),
+        Err(error) if error.kind() == ErrorKind::NotFound => None,
+        // Not a link, like a directory in the way
+        Err(_) => Some(path.clone()),
+    };
+    let matches = match (path.canonicalize(), expected.canonicalize()) {
+        (Ok(resolved), Ok(expected)) => resolved == expected,
+        _ => false,
+    };
+
+    let problem = check_toolchain(&path)
+        .err()
+        .map(|problem| problem.to_string());
+
+    LinkInfo {
+        path,
+        target,
+        matches,
+        problem,
+    }
+}
@@ -0,0 +1,128 @@
+//! Working out what the user typed to run swift-v5, so usage and hints can repeat it.
+//!
+//! swift-v5 is usually run as `swift v5`, which `swift` forwards to the `swift-v5` binary on
+//! PATH. It can also be run directly as `swift-v5`. `swift` doesn't tell subcommands how they
+//! were run, so a wrapper which runs swift-v5 as a Swift plugin should set [`PLUGIN_VAR`].
+//! Otherwise the name is taken from the executable, which also covers copies that were renamed.
+
+use std::{env, ffi::OsStr, sync::OnceLock};
+
+/// The name used when swift-v5 is run through `swift`.
+pub const PLUGIN_NAME: &str = "swift v5";
+/// Set to a non-empty value by the Swift plugin wrapper to say swift-v5 was run as `swift v5`.
+pub const PLUGIN_VAR: &str = "SWIFT_V5_PLUGIN";
+
+static BIN_NAME: OnceLock<String> = OnceLock::new();
+
+/// Works out the name swift-v5 was run as from the process's arguments and environment.
+///
+/// Only the first call has an effect, and it should happen before any help or errors are
+/// printed.
+pub fn init() {
+    let plugin = is_plugin(env::var_os(PLUGIN_VAR).as_deref());
+    let argv0 = env::args_os().next();
+    _ = BIN_NAME.set(detect(argv0.as_deref(), plugin));
+}
+
+/// Whether [`PLUGIN_VAR`], with the given value, says swift-v5 was run as a Swift plugin.
+fn is_plugin(value: Option<&OsStr>) -> bool {
+    value.is_some_and(|value| !value.is_empty())
+}
+
+/// The command to show in usage and hints, like `swift v5` or `swift-v5`.
+///
+/// Defaults to `swift v5` if [`init`] wasn't called.
+pub fn bin_name() -> &'static str {
+    BIN_NAME.get().map_or(PLUGIN_NAME, String::as_str)
+}
+
+/// Formats a swift-v5 command for a hint, like `swift v5 install`.
+pub fn command(args: &str) -> String {
+    format!("{} {args}", bin_name())
+}
+
+/// Picks the name to show for an executable run as `argv0`.
+///
+/// Both `/` and `\` separate directories and a trailing `.exe` is dropped regardless of the
+/// host, so paths from any platform give the same name.
+pub fn detect(argv0: Option<&OsStr>, plugin: bool) -> String {
+    if plugin {
+        return PLUGIN_NAME.to_string();
+    }
+
+    let Some(argv0) = argv0 else {
+        return PLUGIN_NAME.to_string();
+    };
+    let argv0 = argv0.to_string_lossy();
+    let file_name = argv0.rsplit(['/', '\\']).next().unwrap_or_default();
+    let stem = match file_name.len().checked_sub(".exe".len()) {
+        Some(end)
+            if file_name.is_char_boundary(end) && file_name[end..].eq_ignore_ascii_case(".exe") =>
+        {
+            &file_name[..end]
+        }
+        _ => file_name,
+    };
+
+    if stem.is_empty() {
+        PLUGIN_NAME.to_string()
+    } else {
+        stem.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_from(argv0: &str) -> String {
+        detect(Some(OsStr::new(argv0)), false)
+    }
+
+    #[test]
+    fn unix_paths() {
+        assert_eq!(detect_from("swift-v5"), "swift-v5");
+        assert_eq!(detect_from("/usr/local/bin/swift-v5"), "swift-v5");
+        assert_eq!(detect_from("./target/debug/swift-v5"), "swift-v5");
+    }
+
+    #[test]
+    fn windows_paths() {
+        assert_eq!(detect_from(r"C:\Tools\swift-v5.exe"), "swift-v5");
+        assert_eq!(detect_from("swift-v5.EXE"), "swift-v5");
+        assert_eq!(detect_from(r"C:\Tools/bin\swift-v5"), "swift-v5");
+        // Only a trailing `.exe` is dropped
+        assert_eq!(detect_from("swift-v5.exe.bak"), "swift-v5.exe.bak");
+        assert_eq!(detect_from("v5-é.exe"), "v5-é");
+    }
+
+    #[test]
+    fn renamed_executables() {
+        assert_eq!(detect_from("/opt/bin/v5"), "v5");
+        assert_eq!(detect_from(r"D:\v5-nightly.exe"), "v5-nightly");
+    }
+
+    #[test]
+    fn falls_back_to_plugin_name() {
+        assert_eq!(detect(None, false), PLUGIN_NAME);
+        assert_eq!(detect_from(""), PLUGIN_NAME);
+        assert_eq!(detect_from("/usr/bin/"), PLUGIN_NAME);
+        assert_eq!(detect_from(".exe"), PLUGIN_NAME);
+    }
+
+    #[test]
+    fn plugin_wins() {
+        assert_eq!(
+            detect(Some(OsStr::new("/usr/local/bin/swift-v5")), true),
+            PLUGIN_NAME
+        );
+        assert_eq!(detect(None, true), PLUGIN_NAME);
+    }
+
+    #[test]
+    fn plugin_var() {
+        assert!(is_plugin(Some(OsStr::new("1"))));
+        assert!(!is_plugin(Some(OsStr::new(""))));
+        assert!(!is_plugin(None));
+    }
+}
@@ -1,8 +1,13 @@
-use std::{io, sync::LazyLock};
+use std::{
+    io::{self, IsTerminal},
+    path::PathBuf,
+    sync::LazyLock,
+};
 
 use axoupdater::AxoupdateError;
 use directories::ProjectDirs;
 use indicatif::ProgressStyle;
+use inquire::Confirm;
 use miette::Diagnostic;
 use thiserror::Error;
 
@@ -11,28 +16,50 @@ use tokio_util::sync::CancellationToken;
 use trash::TrashContext;
 
 pub mod build;
+pub mod config;
+pub mod devices;
+pub mod info;
+pub mod invocation;
+pub mod logs;
 pub mod project;
 pub mod symlink;
 pub mod toolchain;
+pub mod ui;
+pub mod update_check;
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 const PROGRESS_CHARS: &str = "=> ";
 
 pub static PROGRESS_STYLE: LazyLock<ProgressStyle> = LazyLock::new(|| {
-    ProgressStyle::with_template("{percent:>3.bold}% [{bar:40.blue}] ({bytes}/{total_bytes}, {eta} remaining) {bytes_per_sec}")
-    .expect("progress style valid")
-    .progress_chars(PROGRESS_CHARS)
+    let template = if ui::colors_enabled() {
+        "{percent:>3.bold}% [{bar:40.blue}] ({bytes}/{total_bytes}, {eta} remaining) {bytes_per_sec}"
+    } else {
+        "{percent:>3}% [{bar:40}] ({bytes}/{total_bytes}, {eta} remaining) {bytes_per_sec}"
+    };
+    ProgressStyle::with_template(template)
+        .expect("progress style valid")
+        .progress_chars(PROGRESS_CHARS)
 });
 
 pub static PROGRESS_STYLE_MSG: LazyLock<ProgressStyle> = LazyLock::new(|| {
-    ProgressStyle::with_template("{percent:>3.bold}% [{bar:40.green}] {msg} ({eta} remaining)")
+    let template = if ui::colors_enabled() {
+        "{percent:>3.bold}% [{bar:40.green}] {msg} ({eta} remaining)"
+    } else {
+        "{percent:>3}% [{bar:40}] {msg} ({eta} remaining)"
+    };
+    ProgressStyle::with_template(template)
         .expect("progress style valid")
         .progress_chars(PROGRESS_CHARS)
 });
 
 pub static PROGRESS_STYLE_SPINNER: LazyLock<ProgressStyle> = LazyLock::new(|| {
-    ProgressStyle::with_template("{spinner:.green} {msg}")
+    let template = if ui::colors_enabled() {
+        "{spinner:.green} {msg}"
+    } else {
+        "{spinner} {msg}"
+    };
+    ProgressStyle::with_template(template)
         .expect("progress style valid")
         .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏")
 });
@@ -55,30 +82,146 @@ pub static TRASH: LazyLock<TrashContext> = LazyLock::new(|| {
     ctx
 });
 
+/// Prints a status line with a right-aligned label, like `    Updated llvm-version = "21.1.1"`.
+///
+/// See [`ui::status`].
 #[macro_export]
 macro_rules! msg {
     ($label:expr, $($rest:tt)+) => {
-        eprintln!("{:>12} {}", $label.green().bold(), format_args!($($rest)+))
+        $crate::ui::status($label, format_args!($($rest)+))
     };
 }
 
+/// Formats paths as a bulleted list, leaving out the middle of long lists.
+fn bounded_list(paths: &[PathBuf]) -> String {
+    /// How many paths to show at each end of a long list.
+    const ENDS: usize = 3;
+
+    let bullet = |path: &PathBuf| format!(" • {}", path.display());
+    if paths.len() <= ENDS * 2 + 1 {
+        return paths.iter().map(bullet).collect::<Vec<_>>().join("\n");
+    }
+
+    let mut lines: Vec<String> = paths[..ENDS].iter().map(bullet).collect();
+    lines.push(format!(" … {} more", paths.len() - ENDS * 2));
+    lines.extend(paths[paths.len() - ENDS..].iter().map(bullet));
+    lines.join("\n")
+}
+
+/// Asks the user a yes/no question which defaults to yes, unless `assume_yes` is set.
+///
+/// Prompts can't be answered without a terminal, so this fails rather than waiting forever
+/// when stdin or stderr isn't a TTY.
+pub(crate) fn confirm(message: &str, help: &str, assume_yes: bool) -> Result<bool> {
+    if assume_yes {
+        eprintln!("{message} {}", ui::paint("yes", ui::DIMMED));
+        return Ok(true);
+    }
+
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return Err(Error::PromptUnavailable {
+            message: message.to_string(),
+        });
+    }
+
+    Ok(Confirm::new(message)
+        .with_default(true)
+        .with_help_message(help)
+        .prompt()?)
+}
+
 #[derive(Debug, Error, Diagnostic)]
 pub enum Error {
-    #[error("Cannot determine the root of this project")]
+    #[error(
+        "Cannot determine the root of this project.\nSearched for Package.swift up to {stopped_because}:\n{}",
+        bounded_list(searched)
+    )]
     #[diagnostic(code(swift_v5::cannot_find_project))]
     #[diagnostic(help("navigate to a directory containing Package.swift"))]
-    CannotFindProject,
+    CannotFindProject {
+        searched: Vec<PathBuf>,
+        stopped_because: project::StopReason,
+    },
+    #[error("{} is a {kind}, not a link to the LLVM toolchain", path.display())]
+    #[diagnostic(code(swift_v5::link_occupied))]
+    #[diagnostic(help(
+        "run `{}` to replace it; it will be moved to the trash",
+        crate::invocation::command("activate --force")
+    ))]
+    LinkOccupied { path: PathBuf, kind: &'static str },
+    #[error("The LLVM toolchain at {} {problem}", path.display())]
+    #[diagnostic(code(swift_v5::toolchain_unusable))]
+    #[diagnostic(help(
+        "run `{}` to link it again, or `{}` if the toolchain itself is damaged",
+        crate::invocation::command("activate --force"),
+        crate::invocation::command("install --force")
+    ))]
+    ToolchainUnusable {
+        path: PathBuf,
+        problem: symlink::LinkProblem,
+    },
     #[error("Failed to parse swift-v5 config")]
-    #[diagnostic(code(swift_v5::invalid_config))]
-    #[diagnostic(help("fix the errors in `v5.toml`"))]
-    InvalidConfig {
-        #[from]
-        source: toml::de::Error,
+    #[diagnostic(code(swift_v5::invalid_config), forward(0))]
+    InvalidConfig(Box<config::TomlError>),
+    #[error("Failed to parse the global swift-v5 config")]
+    #[diagnostic(code(swift_v5::invalid_global_config), forward(0))]
+    InvalidGlobalConfig(Box<config::TomlError>),
+    #[error(
+        "`{key}` is not a valid config key.{}",
+        if valid_keys.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "\nValid keys:\n{}",
+                valid_keys.iter().map(|key| format!(" • {key}")).collect::<Vec<_>>().join("\n")
+            )
+        }
+    )]
+    #[diagnostic(code(swift_v5::invalid_config_key))]
+    #[diagnostic(help("keys are written as dotted paths, like `upload.slot`"))]
+    InvalidConfigKey {
+        key: String,
+        valid_keys: Vec<&'static str>,
     },
+    #[error("Invalid value {value:?} for environment variable {name}: {reason}")]
+    #[diagnostic(code(swift_v5::invalid_env_var))]
+    #[diagnostic(help("fix or unset `{name}`"))]
+    InvalidEnvVar {
+        name: &'static str,
+        value: String,
+        reason: String,
+    },
+    #[error("Can't ask \"{message}\" without an interactive terminal")]
+    #[diagnostic(code(swift_v5::prompt_unavailable))]
+    #[diagnostic(help("pass `--yes` or set SWIFT_V5_ASSUME_YES=1 to answer yes automatically"))]
+    PromptUnavailable { message: String },
+    #[error("Can't choose a toolchain version to install because {reason}")]
+    #[diagnostic(code(swift_v5::select_unavailable))]
+    #[diagnostic(help("pass `--toolchain-version <VERSION>` instead of `--select`"))]
+    SelectUnavailable { reason: &'static str },
     #[error("swift-v5's updates are externally managed")]
     #[diagnostic(code(swift_v5::self_update::unavailable))]
-    #[diagnostic(help("update swift-v5 with your package manager or redownload the executable"))]
+    #[diagnostic(help(
+        "self-updates only work for copies installed with the official installer; update copies \
+         from `cargo install` or a package manager the same way they were installed"
+    ))]
     SelfUpdateUnavailable,
+    #[error(
+        "swift-v5 v{version} does not exist.{}",
+        if nearest.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "\nNearest releases:\n{}",
+                nearest.iter().map(|version| format!(" • {version}")).collect::<Vec<_>>().join("\n")
+            )
+        }
+    )]
+    #[diagnostic(code(swift_v5::self_update::version_not_found))]
+    SelfUpdateVersionNotFound {
+        version: axoupdater::Version,
+        nearest: Vec<axoupdater::Version>,
+    },
 
     #[error(transparent)]
     #[diagnostic(transparent)]
@@ -88,15 +231,25 @@ pub enum Error {
     #[diagnostic(transparent)]
     Build(#[from] build::BuildError),
 
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Devices(#[from] devices::DeviceError),
+
     #[error(transparent)]
     #[diagnostic(code(swift_v5::interactive_prompt_failed))]
     Inquire(#[from] inquire::InquireError),
     #[error(transparent)]
     #[diagnostic(code(swift_v5::io_error))]
     Io(#[from] io::Error),
-    #[error("Self-update failed")]
-    #[diagnostic(code(swift_v5::self_update::failed))]
-    AxoUpdate(#[from] AxoupdateError),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    AxoUpdate(Box<AxoupdateError>),
+}
+
+impl From<AxoupdateError> for Error {
+    fn from(error: AxoupdateError) -> Self {
+        Self::AxoUpdate(Box::new(error))
+    }
 }
 
 trait CheckCancellation {
@@ -0,0 +1,98 @@
+//! Keeping a log of recent runs on disk, so there's something to attach to bug reports.
+//!
+//! Every run appends to a file named after the current day (in UTC), regardless of how verbose
+//! the terminal output is. Only the newest [`MAX_FILES`] files are kept.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{NaiveDate, Utc};
+
+use crate::DIRS;
+
+/// How many daily log files to keep.
+pub const MAX_FILES: usize = 10;
+/// What is recorded in the log file, unaffected by `--verbose` and `RUST_LOG`.
+pub const FILTER: &str = "info,swift_v5=debug";
+
+const PREFIX: &str = "swift-v5.";
+const SUFFIX: &str = ".log";
+
+/// The directory log files are kept in.
+pub fn dir() -> PathBuf {
+    DIRS.data_local_dir().join("logs")
+}
+
+/// The log file for `date`.
+fn path_for(dir: &Path, date: NaiveDate) -> PathBuf {
+    dir.join(format!("{PREFIX}{}{SUFFIX}", date.format("%Y-%m-%d")))
+}
+
+/// The log file written to today.
+pub fn current_path() -> PathBuf {
+    path_for(&dir(), Utc::now().date_naive())
+}
+
+/// A log file which moves on to a new file when the day changes.
+#[derive(Debug)]
+pub struct DailyLog {
+    dir: PathBuf,
+    date: NaiveDate,
+    file: File,
+}
+
+impl DailyLog {
+    /// Opens today's log file in [`dir`], creating it if needed, and deletes the oldest files.
+    pub fn open() -> io::Result<Self> {
+        Self::open_in(dir())
+    }
+
+    fn open_in(dir: PathBuf) -> io::Result<Self> {
+        let date = Utc::now().date_naive();
+        fs::create_dir_all(&dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path_for(&dir, date))?;
+        prune(&dir)?;
+        Ok(Self { dir, date, file })
+    }
+}
+
+impl Write for DailyLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if Utc::now().date_naive() != self.date {
+            *self = Self::open_in(self.dir.clone())?;
+        }
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Deletes all but the newest [`MAX_FILES`] log files in `dir`.
+///
+/// The dates in the names sort in order, so the oldest files come first.
+fn prune(dir: &Path) -> io::Result<()> {
+    let mut logs: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(PREFIX) && name.ends_with(SUFFIX))
+        })
+        .collect();
+    logs.sort();
+
+    let excess = logs.len().saturating_sub(MAX_FILES);
+    for path in &logs[..excess] {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
@@ -1,107 +1,496 @@
-use std::sync::LazyLock;
+use std::{
+    env,
+    path::PathBuf,
+    process::exit,
+    sync::{LazyLock, Mutex as StdMutex, OnceLock},
+};
 
-use axoupdater::AxoUpdater;
-use clap::{Parser, Subcommand};
+use axoupdater::{AxoUpdater, AxoupdateError, UpdateRequest, Version};
+use clap::{ArgAction, Command, CommandFactory, FromArgMatches, Parser, Subcommand};
 use human_panic::Metadata;
-use owo_colors::OwoColorize;
 use swift_v5::{
-    build::{BuildTarget, SwiftOpts, build},
-    msg,
-    symlink::symlink,
-    toolchain::install::install,
+    Error,
+    build::{
+        BuildOpts, build,
+        inspect::{InspectOpts, ObjdumpOpts, objdump, size},
+    },
+    config::{self, Config, ConfigFlags, PRECEDENCE_HELP, Secret},
+    devices::{DevicesOpts, devices},
+    info::{InfoOpts, info},
+    invocation, logs, msg,
+    symlink::{ActivateOpts, symlink},
+    toolchain::{
+        ToolchainError,
+        default::{DefaultOpts, default},
+        du::{DuOpts, du},
+        env::{EnvOpts, env as print_env},
+        export::{ExportOpts, export},
+        import::{ImportOpts, import},
+        install::{InstallAction, InstallOpts, install},
+        uninstall::{UninstallOpts, uninstall},
+        which::which,
+    },
+    ui::{self, ColorChoice},
+    update_check,
 };
-use tokio::{sync::Mutex, task::block_in_place};
-use tracing_subscriber::{EnvFilter, util::SubscriberInitExt};
+use tokio::sync::Mutex;
+use tracing::debug;
+use tracing_subscriber::{EnvFilter, Layer, layer::SubscriberExt, util::SubscriberInitExt};
 
 /// Create VEX V5 programs in Swift
 ///
 /// swift-v5 can manage the Arm Toolchain for Embedded version your Swift project uses.
 /// Run `swift v5 install` to download the latest version of the toolchain.
 #[derive(Parser, Debug)]
-#[command(bin_name = "swift v5", version, about, long_about)]
+#[command(version, about, long_about, after_long_help = PRECEDENCE_HELP)]
 struct Args {
     #[command(subcommand)]
     command: Commands,
+    /// Answer "yes" to all confirmation prompts
+    #[arg(short, long, global = true)]
+    yes: bool,
+    /// Log more details; repeat for even more (-vv, -vvv)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Only log errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// When to color the output
+    #[arg(long, global = true, value_enum, default_value_t, value_name = "WHEN")]
+    color: ColorChoice,
+    /// Also write every log message, including trace messages, to this file
+    #[arg(long, global = true, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+}
+
+impl Args {
+    /// Picks which logs to show, unless overridden by the `RUST_LOG` environment variable.
+    fn log_filter(&self) -> EnvFilter {
+        if env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+            return EnvFilter::from_default_env();
+        }
+
+        let directives = match (self.quiet, self.verbose) {
+            (true, _) => "error",
+            (false, 0) => "warn",
+            (false, 1) => "warn,swift_v5=debug",
+            (false, 2) => "warn,swift_v5=trace",
+            (false, _) => "trace",
+        };
+        EnvFilter::new(directives)
+    }
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Install the toolchain for this project
     Install {
-        #[clap(
-            long,
-            help = "Force re-installation of the toolchain, even if it is already installed"
-        )]
-        force: bool,
+        #[clap(flatten)]
+        opts: InstallOpts,
     },
     /// Update swift-v5 to the latest version
     #[clap(hide = !can_update())]
-    Update {},
+    Update {
+        /// Install this version instead of the latest one, e.g. to roll back
+        #[arg(long, value_name = "VERSION", value_parser = parse_self_version)]
+        version: Option<Version>,
+        /// Allow updating to a prerelease
+        #[arg(long, conflicts_with = "version")]
+        prerelease: bool,
+    },
     /// Symlink the project's toolchain to ./llvm-toolchain, needed for swift
     /// builds
-    Activate {},
+    Activate {
+        #[clap(flatten)]
+        opts: ActivateOpts,
+    },
+    /// View or edit the project's configuration (v5.toml)
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Print the path to a binary in the project's toolchain, like `clang`
+    Which {
+        /// The name of the binary, such as `clang` or `llvm-objcopy`
+        tool: String,
+    },
+    /// Print shell commands which put the project's toolchain on PATH, for
+    /// `eval "$(swift v5 env)"`
+    Env {
+        #[clap(flatten)]
+        opts: EnvOpts,
+    },
+    /// Manage installed LLVM toolchains
+    Toolchain {
+        #[command(subcommand)]
+        action: ToolchainAction,
+    },
+    /// List the V5 brains and controllers connected to this computer
+    Devices {
+        #[clap(flatten)]
+        opts: DevicesOpts,
+    },
+    /// Print the section sizes of the built program with `llvm-size`
+    Size {
+        #[clap(flatten)]
+        opts: InspectOpts,
+    },
+    /// Run `llvm-objdump` on the built program, e.g. `swift v5 objdump -d`
+    Objdump {
+        #[clap(flatten)]
+        opts: ObjdumpOpts,
+    },
+    /// Summarize the project, its toolchain and the Swift installation
+    Info {
+        #[clap(flatten)]
+        opts: InfoOpts,
+    },
+    /// Print where the log of recent runs is kept, to attach to bug reports
+    Logs {
+        /// Print the directory with every log file instead of today's file
+        #[arg(long)]
+        dir: bool,
+    },
     /// Builds the project using the Swift compiler. Requires the appropriate
     /// Swift version installed (`swiftly install` in your project) and the
     /// LLVM toolchain properly installed and symlinked (`swift v5 install`).
     Build {
-        #[arg(long, value_enum, default_value_t = BuildTarget::Release)]
-        target: BuildTarget,
-        /// Arguments forwarded to `swift`.
         #[clap(flatten)]
-        swift_opts: SwiftOpts,
+        opts: BuildOpts,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Print the value of a setting, or every setting and where it came from
+    Get {
+        /// The setting to print, such as `llvm-version` or `upload.slot`
+        key: Option<String>,
+    },
+    /// Change a setting in v5.toml
+    Set {
+        /// The setting to change, such as `llvm-version` or `upload.slot`
+        key: String,
+        /// The new value
+        value: String,
+    },
+    /// Remove a setting from v5.toml
+    Unset {
+        /// The setting to remove, such as `llvm-version` or `upload.slot`
+        key: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ToolchainAction {
+    /// Package an installed toolchain into an archive which can be imported elsewhere
+    Export {
+        #[clap(flatten)]
+        opts: ExportOpts,
+    },
+    /// Install a toolchain from an archive made by `toolchain export`
+    Import {
+        #[clap(flatten)]
+        opts: ImportOpts,
+    },
+    /// Delete an installed toolchain
+    Uninstall {
+        #[clap(flatten)]
+        opts: UninstallOpts,
+    },
+    /// Show how much disk space toolchains and downloaded archives use
+    Du {
+        #[clap(flatten)]
+        opts: DuOpts,
+    },
+    /// Print or change the toolchain version used when a project doesn't pin one
+    Default {
+        #[clap(flatten)]
+        opts: DefaultOpts,
     },
 }
 
 #[tokio::main]
 async fn main() -> miette::Result<()> {
-    tracing_subscriber::fmt::fmt()
-        .pretty()
-        .with_env_filter(EnvFilter::from_default_env())
-        .finish()
+    invocation::init();
+    let args = Args::from_arg_matches(&command().get_matches()).unwrap_or_else(|err| err.exit());
+    ui::init(args.color, args.quiet);
+
+    // Failing to open the log file shouldn't stop the command, so it's only mentioned in the
+    // terminal's logs
+    let (file_layer, log_error) = match logs::DailyLog::open() {
+        Ok(log) => {
+            let layer = tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(StdMutex::new(log));
+            (Some(layer), None)
+        }
+        Err(err) => (None, Some(err)),
+    };
+    // Unlike the daily log, a file that was asked for has to be writable
+    let trace_layer = match &args.log_file {
+        Some(path) => {
+            let file = fs_err::File::create(path).map_err(swift_v5::Error::from)?;
+            _ = LOG_FILE.set(path.clone());
+            let layer = tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(StdMutex::new(file));
+            Some(layer)
+        }
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .compact()
+                .with_ansi(ui::colors_enabled())
+                .with_writer(|| ui::StderrWriter)
+                .with_filter(args.log_filter()),
+        )
+        .with(file_layer.with_filter(EnvFilter::new(logs::FILTER)))
+        .with(trace_layer.with_filter(EnvFilter::new("trace")))
         .init();
+    if let Some(err) = log_error {
+        debug!(%err, "Couldn't open the log file");
+    }
+    debug!(
+        version = env!("CARGO_PKG_VERSION"),
+        command = ?args.command,
+        "Starting swift-v5"
+    );
 
     if cfg!(not(debug_assertions)) {
         human_panic::setup_panic!(
             Metadata::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
                 .homepage("https://vexide.dev")
-                .support("https://discord.gg/d4uazRf2Nh")
+                .support(format!(
+                    "- Ask for help at https://discord.gg/d4uazRf2Nh\n\
+                     - Attach the report and the log file at \"{}\"",
+                    logs::current_path().display()
+                ))
         );
     }
 
-    let args = Args::parse();
+    let result = run(args).await;
+    if let Err(err) = result {
+        eprintln!("{err:?}");
+        exit_after_logging(1);
+    }
+    print_log_file();
+    Ok(())
+}
+
+/// Runs the subcommand, once logging is set up.
+async fn run(args: Args) -> miette::Result<()> {
+    let config = Config::load(ConfigFlags {
+        assume_yes: args.yes,
+    })
+    .await?;
+
+    // The library makes its own clients, so this only authenticates the CLI's own requests
+    if let Some(token) = &config.global.github_token {
+        let github = octocrab::Octocrab::builder()
+            .personal_token(token.expose().to_string())
+            .build()
+            .map_err(ToolchainError::from)?;
+        octocrab::initialise(github);
+    }
+
+    // Updating would make the notice redundant
+    let check_for_updates = !matches!(args.command, Commands::Update { .. });
 
     match args.command {
-        Commands::Install { force } => {
-            install(force).await?;
+        Commands::Install { opts } => {
+            // Nothing is installed if the project uses a local toolchain
+            if let Some(outcome) = install(&opts, &config).await? {
+                match outcome.action {
+                    InstallAction::AlreadyInstalled => {
+                        println!(
+                            "Toolchain up-to-date: {} at {}",
+                            ui::paint(&outcome.version, ui::EMPHASIS),
+                            ui::paint(outcome.path.display(), ui::LOCATION)
+                        );
+                    }
+                    InstallAction::Downloaded { .. } => {
+                        msg!("Downloaded", "to {}", outcome.path.display());
+                    }
+                    InstallAction::Cancelled => {
+                        eprintln!("Cancelled.");
+                        exit_after_logging(1);
+                    }
+                }
+
+                if !opts.uses_project() {
+                    eprintln!(
+                        "No project was configured; run {} inside a project to use this toolchain",
+                        ui::paint(
+                            format!("`{}`", invocation::command("activate")),
+                            ui::EMPHASIS
+                        )
+                    );
+                }
+            }
+        }
+        Commands::Update {
+            version,
+            prerelease,
+        } => {
+            update(version, prerelease).await?;
         }
-        Commands::Update {} => {
-            update().await?;
+        Commands::Activate { opts } => {
+            symlink(&config, &opts).await?;
         }
-        Commands::Activate {} => {
-            symlink().await?;
+        Commands::Config { action } => match action {
+            ConfigAction::Get { key } => config::edit::get(key.as_deref(), &config).await?,
+            ConfigAction::Set { key, value } => config::edit::set(&key, &value).await?,
+            ConfigAction::Unset { key } => config::edit::unset(&key).await?,
+        },
+        Commands::Which { tool } => {
+            println!("{}", which(&tool, &config).await?.display());
         }
-        Commands::Build { target, swift_opts } => {
-            build(&target, &swift_opts).await?;
+        Commands::Env { opts } => {
+            print_env(&opts, &config).await?;
         }
+        Commands::Toolchain { action } => match action {
+            ToolchainAction::Export { opts } => _ = export(&opts, &config).await?,
+            ToolchainAction::Import { opts } => import(&opts, &config).await?,
+            ToolchainAction::Uninstall { opts } => uninstall(&opts, &config).await?,
+            ToolchainAction::Du { opts } => du(&opts, &config).await?,
+            ToolchainAction::Default { opts } => default(&opts, &config).await?,
+        },
+        Commands::Devices { opts } => devices(&opts)?,
+        Commands::Info { opts } => info(&opts, &config).await?,
+        Commands::Logs { dir } => {
+            let path = if dir {
+                logs::dir()
+            } else {
+                logs::current_path()
+            };
+            println!("{}", path.display());
+        }
+        Commands::Build { opts } => exit_with_tool(build(&opts, &config).await)?,
+        Commands::Size { opts } => exit_with_tool(size(&opts, &config).await)?,
+        Commands::Objdump { opts } => exit_with_tool(objdump(&opts, &config).await)?,
+    }
+
+    // The command has already succeeded, so settings which don't resolve only skip the check
+    if check_for_updates
+        && config
+            .resolve(None)
+            .is_ok_and(|effective| effective.update_check)
+    {
+        update_check::notify(config.global.github_token.as_ref().map(Secret::expose)).await;
     }
 
     Ok(())
 }
 
+/// The file given with `--log-file`, if any.
+static LOG_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Mentions the file given with `--log-file`, so it's easy to find once the command is done.
+fn print_log_file() {
+    if let Some(path) = LOG_FILE.get() {
+        msg!("Logged", "to {}", path.display());
+    }
+}
+
+/// Exits with `code`, after mentioning the file given with `--log-file`.
+fn exit_after_logging(code: i32) -> ! {
+    print_log_file();
+    exit(code)
+}
+
+/// Builds the command line parser, with usage and help showing the name swift-v5 was run as.
+fn command() -> Command {
+    let bin_name = invocation::bin_name();
+    let command = Args::command().bin_name(bin_name);
+    if bin_name == invocation::PLUGIN_NAME {
+        command
+    } else {
+        rename_in_help(command, &format!("{bin_name} "))
+    }
+}
+
+/// Replaces `swift v5 ` in the help text of `command` and its subcommands.
+fn rename_in_help(mut command: Command, replacement: &str) -> Command {
+    let rename = |text: &clap::builder::StyledStr| {
+        text.to_string()
+            .replace(&format!("{} ", invocation::PLUGIN_NAME), replacement)
+    };
+    if let Some(about) = command.get_about().map(rename) {
+        command = command.about(about);
+    }
+    if let Some(long_about) = command.get_long_about().map(rename) {
+        command = command.long_about(long_about);
+    }
+
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    for name in names {
+        command =
+            command.mut_subcommand(name, |subcommand| rename_in_help(subcommand, replacement));
+    }
+    command
+}
+
 static UPDATER: LazyLock<Mutex<AxoUpdater>> =
     LazyLock::new(|| Mutex::new(AxoUpdater::new_for("swift-v5")));
 
+/// Whether this copy of swift-v5 has an install receipt, which is needed to update itself.
+///
+/// This is checked every time the help text is rendered, so the receipt is only read once.
 fn can_update() -> bool {
-    block_in_place(|| UPDATER.blocking_lock().load_receipt().is_ok())
+    static CAN_UPDATE: LazyLock<bool> =
+        LazyLock::new(|| AxoUpdater::new_for("swift-v5").load_receipt().is_ok());
+    *CAN_UPDATE
+}
+
+fn parse_self_version(version: &str) -> Result<Version, semver::Error> {
+    version.strip_prefix('v').unwrap_or(version).parse()
 }
 
-async fn update() -> swift_v5::Result<()> {
+async fn update(version: Option<Version>, prerelease: bool) -> swift_v5::Result<()> {
     let mut updater = UPDATER.lock().await;
 
     updater
         .load_receipt()
         .map_err(|_| swift_v5::Error::SelfUpdateUnavailable)?;
 
+    updater.configure_version_specifier(match &version {
+        Some(version) => UpdateRequest::SpecificVersion(version.to_string()),
+        None if prerelease => UpdateRequest::LatestMaybePrerelease,
+        None => UpdateRequest::Latest,
+    });
+
+    let current = Version::parse(env!("CARGO_PKG_VERSION")).expect("package version is valid");
+    let selected = match updater.query_new_version().await {
+        Ok(Some(selected)) => selected.clone(),
+        Ok(None) => {
+            eprintln!("No updates available.");
+            return Ok(());
+        }
+        Err(AxoupdateError::VersionNotFound { .. }) if let Some(version) = version => {
+            return Err(swift_v5::Error::SelfUpdateVersionNotFound {
+                nearest: nearest_releases(&version).await,
+                version,
+            });
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    if selected == current {
+        eprintln!("swift-v5 v{current} is already installed.");
+        return Ok(());
+    }
+    if version.is_none() && selected < current {
+        eprintln!("No updates available.");
+        return Ok(());
+    }
+
+    msg!("Selected", "swift-v5 v{selected}");
     eprintln!("Running self-update...");
     if let Some(update) = updater.run().await? {
         msg!(
@@ -118,3 +507,51 @@ async fn update() -> swift_v5::Result<()> {
     }
     Ok(())
 }
+
+/// Finds the published swift-v5 versions closest to `version`, for suggesting alternatives.
+///
+/// Failing to list the releases isn't fatal, since this is only used to improve an error.
+async fn nearest_releases(version: &Version) -> Vec<Version> {
+    let releases = octocrab::instance()
+        .repos("vexide", "swift-v5")
+        .releases()
+        .list()
+        .per_page(100)
+        .send()
+        .await;
+    let releases = match releases {
+        Ok(page) => page.items,
+        Err(err) => {
+            debug!(?err, "Failed to list swift-v5 releases");
+            return Vec::new();
+        }
+    };
+
+    let distance = |other: &Version| {
+        (
+            version.major.abs_diff(other.major),
+            version.minor.abs_diff(other.minor),
+            version.patch.abs_diff(other.patch),
+        )
+    };
+    let mut versions: Vec<Version> = releases
+        .iter()
+        .filter_map(|release| parse_self_version(&release.tag_name).ok())
+        .collect();
+    versions.sort_by_key(distance);
+    versions.truncate(5);
+    versions
+}
+
+/// Exits with the status of a tool which failed, after reporting it, so scripts can tell what
+/// went wrong.
+fn exit_with_tool(result: swift_v5::Result<()>) -> miette::Result<()> {
+    match result {
+        Err(Error::Build(err)) if err.exit_code().is_some() => {
+            let code = err.exit_code().unwrap_or(1);
+            eprintln!("{:?}", miette::Report::new(err));
+            exit_after_logging(code);
+        }
+        result => Ok(result?),
+    }
+}
@@ -1,81 +1,147 @@
-use std::{cell::OnceCell, env, io::ErrorKind, path::PathBuf, process::Command, str::FromStr};
+use std::{
+    cell::OnceCell,
+    collections::BTreeMap,
+    env,
+    fmt::{self, Display},
+    io::{self, ErrorKind},
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+    process::Command,
+};
 
-use serde::Deserialize;
+use directories::BaseDirs;
+use serde::{Deserialize, Deserializer, de};
+use strum::AsRefStr;
+use toml::Spanned;
 use tracing::{debug, trace};
 
+mod package;
+
+pub use package::{PackageDescription, Product, ProductType, Target};
+
 use crate::{
     Error, Result,
-    build::{BuildError, BuildTarget},
+    build::{BuildContext, BuildError, BuildPlatform, BuildTarget},
+    config::TomlError,
     fs,
+    toolchain::ToolchainVersion,
 };
 
 #[derive(Debug)]
 pub struct Project {
     path: PathBuf,
+    config_path: PathBuf,
     config: OnceCell<ProjectConfig>,
 }
 
 impl Project {
+    /// Creates a project rooted at `path`, with its config beside `Package.swift`.
     pub fn new(path: PathBuf) -> Self {
+        let config_path = path.join(ProjectConfig::FILE_NAME);
+        Self::with_config_path(path, config_path)
+    }
+
+    /// Creates a project rooted at `path` which uses the config file at `config_path`.
+    pub fn with_config_path(path: PathBuf, config_path: PathBuf) -> Self {
         Self {
             path,
+            config_path,
             config: OnceCell::new(),
         }
     }
 
+    /// Searches the current directory and its ancestors for a `Package.swift` file.
+    ///
+    /// If there's no `v5.toml` beside it, the search continues upwards for one, so that several
+    /// packages in one repository can share a config. The search won't leave the user's home
+    /// directory, cross into a different filesystem, or continue past the root of a git
+    /// repository.
     pub async fn find() -> Result<Self> {
-        let mut candidate = env::current_dir()?;
-        loop {
-            trace!(?candidate, "Searching for project root (Package.swift)");
-
-            let mut read_dir = fs::read_dir(&candidate).await?;
-            while let Some(entry) = read_dir.next_entry().await? {
-                if entry.file_name().eq_ignore_ascii_case("Package.swift") {
-                    debug!(path = ?candidate, "Found project root");
-                    return Ok(Self::new(candidate));
-                }
-            }
+        let home_dir = BaseDirs::new().map(|dirs| dirs.home_dir().to_owned());
+        let mut log = SearchLog::default();
+        let Some(manifest) = search_upwards(
+            env::current_dir()?,
+            "Package.swift",
+            home_dir.as_deref(),
+            Some(&mut log),
+        )
+        .await?
+        else {
+            return Err(Error::CannotFindProject {
+                searched: log.searched,
+                stopped_because: log.stopped_because.unwrap_or(StopReason::FilesystemRoot),
+            });
+        };
+        let path = manifest
+            .parent()
+            .expect("a found file has a parent")
+            .to_owned();
+        debug!(?path, "Found project root");
+
+        let config_path = search_upwards(
+            path.clone(),
+            ProjectConfig::FILE_NAME,
+            home_dir.as_deref(),
+            None,
+        )
+        .await?
+        .unwrap_or_else(|| path.join(ProjectConfig::FILE_NAME));
+        debug!(?config_path, "Using project config");
+
+        Ok(Self::with_config_path(path, config_path))
+    }
+
+    /// The project's root directory, which contains `Package.swift`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the project's `v5.toml` is, or would be created if it doesn't exist yet.
+    ///
+    /// This is beside `Package.swift` unless one was found in a parent directory.
+    pub fn config_path(&self) -> PathBuf {
+        self.config_path.clone()
+    }
+
+    pub fn output_path(target: &BuildTarget, platform: BuildPlatform) -> crate::Result<PathBuf> {
+        BuildContext::show_bin_path(target, platform)
+    }
 
-            if let Some(parent) = candidate.parent() {
-                candidate = parent.to_owned();
-            } else {
-                return Err(Error::CannotFindProject);
+    /// Describes the package in the current directory using `swift package describe`.
+    pub fn describe() -> crate::Result<PackageDescription> {
+        let output = Command::new("swift")
+            .args(["package", "describe", "--type", "json"])
+            .output()
+            .map_err(BuildError::from_swift_spawn)?;
+        if !output.status.success() {
+            return Err(BuildError::DescribeFailed {
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
             }
+            .into());
         }
+
+        // Skip anything SwiftPM printed before the JSON, like warnings from older versions.
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let json = stdout.find('{').map_or("", |start| &stdout[start..]);
+        serde_json::from_str(json).map_err(|error| {
+            BuildError::DescribeInvalid {
+                reason: error.to_string(),
+            }
+            .into()
+        })
     }
 
-    pub fn config_path(&self) -> PathBuf {
-        self.path.join(ProjectConfig::FILE_NAME)
-    }
-
-    pub fn output_path(target: &BuildTarget) -> crate::Result<PathBuf> {
-        let path = Command::new("swift")
-            .arg("build")
-            .arg("-c")
-            .arg(target.arg())
-            .arg("--triple")
-            .arg("armv7-none-none-eabi")
-            .arg("--show-bin-path")
-            .output()?;
-        let path = PathBuf::from_str(
-            String::from_utf8(path.stdout)
-                .map_err(|_| BuildError::OutputFolderInvalid)?
-                .trim(),
-        )
-        .map_err(|_| BuildError::OutputFolderInvalid)?;
-        Ok(path)
+    /// Lists the names of the package's executable products.
+    pub fn executable_names() -> crate::Result<Vec<String>> {
+        match Self::describe() {
+            Ok(package) => BuildContext::executables_from(Ok(&package)),
+            Err(error) => BuildContext::executables_from(Err(error)),
+        }
     }
+
+    /// Returns the name of the package's only executable product.
     pub fn executable_name() -> crate::Result<String> {
-        let name = Command::new("swift")
-            .arg("package")
-            .arg("show-executables")
-            .output()?;
-        let name = String::from_utf8(name.stdout).map_err(|_| BuildError::ExecutableNameInvalid)?;
-        let name = name
-            .lines()
-            .next()
-            .ok_or(BuildError::ExecutableNameInvalid)?;
-        Ok(name.to_string())
+        BuildContext::only_executable(&Self::executable_names()?).map(str::to_string)
     }
 
     pub async fn config(&self) -> Result<Option<&ProjectConfig>> {
@@ -86,9 +152,9 @@ impl Project {
         let config_path = self.config_path();
         debug!(?config_path, "Attempting to read config");
 
-        match fs::read_to_string(config_path).await {
+        match fs::read_to_string(&config_path).await {
             Ok(contents) => {
-                let parsed: ProjectConfig = contents.parse()?;
+                let parsed = ProjectConfig::parse(&config_path, contents)?;
                 self.config.set(parsed).unwrap();
                 Ok(self.config.get())
             }
@@ -101,20 +167,547 @@ impl Project {
     }
 }
 
+/// Why [`search_upwards`] stopped without finding the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    RepoRoot,
+    HomeDir,
+    FilesystemBoundary,
+    FilesystemRoot,
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RepoRoot => write!(f, "the root of a git repository"),
+            Self::HomeDir => write!(f, "your home directory"),
+            Self::FilesystemBoundary => write!(f, "the edge of a filesystem"),
+            Self::FilesystemRoot => write!(f, "the root of the filesystem"),
+        }
+    }
+}
+
+/// What [`search_upwards`] looked through before giving up.
+#[derive(Debug, Default)]
+struct SearchLog {
+    /// Each directory which didn't contain the file, starting from the first.
+    searched: Vec<PathBuf>,
+    stopped_because: Option<StopReason>,
+}
+
+/// Searches `start` and its ancestors for a file named `file_name`, ignoring case, and returns
+/// its path.
+///
+/// The search stops at `home_dir` (the user's home directory), the root of a git repository, or
+/// a filesystem boundary. Where it looked and why it stopped are recorded in `log`.
+async fn search_upwards(
+    start: PathBuf,
+    file_name: &str,
+    home_dir: Option<&Path>,
+    mut log: Option<&mut SearchLog>,
+) -> Result<Option<PathBuf>> {
+    let mut candidate = start;
+    loop {
+        trace!(?candidate, file_name, "Searching for file");
+
+        let mut is_repo_root = false;
+        let mut read_dir = fs::read_dir(&candidate).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let entry_name = entry.file_name();
+            if entry_name.eq_ignore_ascii_case(file_name) {
+                return Ok(Some(entry.path()));
+            }
+
+            if entry_name == ".git" {
+                is_repo_root = true;
+            }
+        }
+
+        if let Some(log) = log.as_deref_mut() {
+            log.searched.push(candidate.clone());
+        }
+
+        let stop = if is_repo_root {
+            debug!(path = ?candidate, "Reached the root of a git repository");
+            Some(StopReason::RepoRoot)
+        } else if home_dir == Some(candidate.as_path()) {
+            debug!(path = ?candidate, "Reached the home directory");
+            Some(StopReason::HomeDir)
+        } else {
+            match candidate.parent() {
+                None => Some(StopReason::FilesystemRoot),
+                Some(parent) if is_mount_point(&candidate, parent).await? => {
+                    debug!(path = ?candidate, "Reached a filesystem boundary");
+                    Some(StopReason::FilesystemBoundary)
+                }
+                Some(_) => None,
+            }
+        };
+        if let Some(stop) = stop {
+            if let Some(log) = log {
+                log.stopped_because = Some(stop);
+            }
+            return Ok(None);
+        }
+
+        candidate = candidate
+            .parent()
+            .expect("only the root has no parent")
+            .to_owned();
+    }
+}
+
+/// Checks whether `dir` is on a different filesystem than its parent directory.
+#[cfg(unix)]
+async fn is_mount_point(dir: &Path, parent: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let (dir, parent) = tokio::try_join!(fs::metadata(dir), fs::metadata(parent))?;
+    Ok(dir.dev() != parent.dev())
+}
+
+#[cfg(not(unix))]
+async fn is_mount_point(_dir: &Path, _parent: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
 #[derive(Debug, Deserialize, Clone)]
-#[serde(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct ProjectConfig {
-    pub llvm_version: String,
+    pub llvm_version: Option<Spanned<String>>,
+    /// A local toolchain, like an LLVM build directory, to use instead of installing
+    /// `llvm-version`. Relative to the project root.
+    pub llvm_path: Option<Spanned<PathBuf>>,
+    pub upload: Option<UploadConfig>,
+    pub build: Option<BuildConfig>,
+    pub toolchain: Option<ToolchainConfig>,
+    pub hooks: Option<HooksConfig>,
+    /// Named build profiles, selected with `swift v5 build --target <name>`.
+    pub profiles: Option<BTreeMap<String, ProfileConfig>>,
 }
 
 impl ProjectConfig {
     const FILE_NAME: &str = "v5.toml";
+
+    /// Parses the contents of a config file, producing a diagnostic which points at the
+    /// offending part of `contents` if it is invalid.
+    pub fn parse(path: &Path, contents: String) -> Result<Self> {
+        let config: Self = match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(error) => {
+                return Err(Error::InvalidConfig(Box::new(TomlError::new(
+                    path, contents, &error,
+                ))));
+            }
+        };
+
+        if let Err((message, span)) = config.validate() {
+            // Like toml's own errors for missing keys, point at the whole file if there's no
+            // particular value to blame
+            let span = span.unwrap_or(0..contents.len());
+            return Err(Error::InvalidConfig(Box::new(TomlError::from_parts(
+                path,
+                contents,
+                &message,
+                Some(span),
+            ))));
+        }
+
+        Ok(config)
+    }
+
+    /// Checks for errors which can't be caught while deserializing, returning the error
+    /// message and the location of the offending value, if there is one.
+    fn validate(&self) -> Result<(), (String, Option<Range<usize>>)> {
+        match (&self.llvm_version, &self.llvm_path) {
+            (Some(version), None) => {
+                // The configured repository isn't known while loading, so only plain versions
+                // and the official repository's tags are accepted here
+                if ToolchainVersion::parse(version.get_ref()).is_err() {
+                    return Err((
+                        "invalid LLVM toolchain version, expected a version like `21.1.1` or \
+                         `21.1.0-rc1`"
+                            .to_string(),
+                        Some(version.span()),
+                    ));
+                }
+            }
+            (Some(_), Some(path)) => {
+                return Err((
+                    "`llvm-path` can't be used together with `llvm-version`".to_string(),
+                    Some(path.span()),
+                ));
+            }
+            (None, Some(_)) => {}
+            (None, None) => {
+                return Err((
+                    "missing field `llvm-version`, or `llvm-path` for a local toolchain"
+                        .to_string(),
+                    None,
+                ));
+            }
+        }
+
+        let profile_args = self
+            .profiles
+            .iter()
+            .flat_map(|profiles| profiles.values())
+            .flat_map(|profile| profile.swift_args.iter().flatten());
+        let swift_args = self
+            .build
+            .iter()
+            .flat_map(|b| b.swift_args.iter().flatten())
+            .chain(profile_args);
+        for arg in swift_args {
+            let option = arg
+                .get_ref()
+                .split_once('=')
+                .map_or(arg.get_ref().as_str(), |(option, _)| option);
+            if BuildConfig::RESERVED_SWIFT_ARGS.contains(&option) {
+                return Err((
+                    format!("`{option}` is set by swift-v5 and can't be overridden"),
+                    Some(arg.span()),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Device settings used when uploading programs, from the `[upload]` table.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UploadConfig {
+    /// The program slot to upload to, from 1 to 8.
+    #[serde(default, deserialize_with = "deserialize_slot")]
+    pub slot: Option<u8>,
+    /// The serial port of the V5 device, if it shouldn't be detected automatically.
+    pub port: Option<String>,
+    /// What the V5 brain should do once the upload finishes.
+    pub after_upload: Option<AfterUpload>,
+}
+
+impl UploadConfig {
+    pub const SLOTS: RangeInclusive<u8> = 1..=8;
+    pub const DEFAULT_SLOT: u8 = 1;
+}
+
+fn deserialize_slot<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+    let slot = u8::deserialize(deserializer)?;
+    if !UploadConfig::SLOTS.contains(&slot) {
+        return Err(de::Error::custom(format!(
+            "invalid slot {slot}, expected a slot from {} to {}",
+            UploadConfig::SLOTS.start(),
+            UploadConfig::SLOTS.end()
+        )));
+    }
+
+    Ok(Some(slot))
+}
+
+fn deserialize_jobs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let jobs = u32::deserialize(deserializer)?;
+    if jobs == 0 {
+        return Err(de::Error::custom(
+            "invalid job count 0, expected at least 1",
+        ));
+    }
+
+    Ok(Some(jobs))
+}
+
+/// Build settings, from the `[build]` table.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BuildConfig {
+    /// Arguments passed to `swift build` before the ones given on the command line.
+    pub swift_args: Option<Vec<Spanned<String>>>,
+    /// A directory to copy the built program to, relative to the project root.
+    pub out_dir: Option<PathBuf>,
+    /// How many jobs `swift build` may run in parallel.
+    #[serde(default, deserialize_with = "deserialize_jobs")]
+    pub jobs: Option<u32>,
+    /// The build profile to use when none is given on the command line.
+    pub default_target: Option<String>,
+}
+
+impl BuildConfig {
+    /// Options which swift-v5 passes to `swift build` itself, and so can't be overridden.
+    pub const RESERVED_SWIFT_ARGS: &[&str] = &[
+        "-c",
+        "--configuration",
+        "--triple",
+        "--toolset",
+        "--swift-sdk",
+        "--show-bin-path",
+    ];
+}
+
+/// A custom build profile, from a `[profiles.<name>]` table.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// The SwiftPM configuration the profile builds with.
+    pub base: BuildTarget,
+    /// Arguments passed to `swift build` after the ones in `[build]`.
+    pub swift_args: Option<Vec<Spanned<String>>>,
+}
+
+/// Settings for how the project uses its toolchain, from the `[toolchain]` table.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ToolchainConfig {
+    /// Link `./llvm-toolchain` with a relative path, so the link survives the project being
+    /// synced to another machine.
+    pub relative: Option<bool>,
+    /// Where to link the toolchain, relative to the project root. Defaults to `llvm-toolchain`.
+    pub link: Option<PathBuf>,
+}
+
+/// Commands run at points in swift-v5's work, from the `[hooks]` table.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Shell commands run in order from the project root after a successful V5 build.
+    pub post_build: Option<Vec<String>>,
+}
+
+/// An action taken by the V5 brain after a program is uploaded.
+#[derive(Debug, Deserialize, AsRefStr, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum AfterUpload {
+    /// Do nothing.
+    None,
+    /// Open the program's screen.
+    #[default]
+    ShowScreen,
+    /// Start running the program.
+    Run,
 }
 
-impl FromStr for ProjectConfig {
-    type Err = toml::de::Error;
+#[cfg(test)]
+mod tests {
+    use miette::Diagnostic;
+
+    use super::*;
+
+    /// Parses a `v5.toml`, returning the error's message and the text its label points at.
+    fn parse_error(contents: &str) -> (String, String) {
+        match ProjectConfig::parse(Path::new("v5.toml"), contents.to_string()) {
+            Err(Error::InvalidConfig(error)) => {
+                let label = error
+                    .labels()
+                    .and_then(|mut labels| labels.next())
+                    .expect("errors point at the config");
+                let span = label.offset()..label.offset() + label.len();
+                (error.to_string(), contents[span].to_string())
+            }
+            other => panic!("expected an invalid config, got {other:?}"),
+        }
+    }
+
+    /// The help shown for an invalid `v5.toml`.
+    fn help(contents: &str) -> String {
+        match ProjectConfig::parse(Path::new("v5.toml"), contents.to_string()) {
+            Err(error) => error.help().expect("config errors have help").to_string(),
+            Ok(_) => panic!("expected an invalid config"),
+        }
+    }
+
+    #[test]
+    fn valid_versions() {
+        for version in ["21.1.1", "21.1.0-rc1"] {
+            let contents = format!("llvm-version = \"{version}\"\n");
+            let config = ProjectConfig::parse(Path::new("v5.toml"), contents).unwrap();
+            assert_eq!(config.llvm_version.unwrap().into_inner(), version);
+        }
+    }
+
+    #[test]
+    fn invalid_version_points_at_value() {
+        for version in ["latest", "../21.1.1", ""] {
+            let (message, span) = parse_error(&format!(
+                "# Pinned toolchain\nllvm-version = \"{version}\"\n[upload]\nslot = 2\n"
+            ));
+            assert!(message.starts_with("invalid LLVM toolchain version"));
+            assert_eq!(span, format!("\"{version}\""));
+        }
+    }
+
+    #[test]
+    fn version_and_path_point_at_path() {
+        let (message, span) =
+            parse_error("llvm-version = \"21.1.1\"\nllvm-path = \"../llvm/build\"\n");
+        assert_eq!(
+            message,
+            "`llvm-path` can't be used together with `llvm-version`"
+        );
+        assert_eq!(span, "\"../llvm/build\"");
+    }
+
+    #[test]
+    fn missing_version_points_at_file() {
+        let contents = "[upload]\nslot = 2\n";
+        let (message, span) = parse_error(contents);
+        assert!(message.starts_with("missing field `llvm-version`"));
+        assert_eq!(span, contents);
+    }
+
+    #[test]
+    fn unknown_keys_point_at_key() {
+        for (contents, key) in [
+            (
+                "llvm-version = \"21.1.1\"\nllvm-verison = \"21.1.1\"\n",
+                "llvm-verison",
+            ),
+            ("llvm-version = \"21.1.1\"\n[upload]\nslto = 2\n", "slto"),
+            (
+                "llvm-version = \"21.1.1\"\n[build]\nswift-arg = []\n",
+                "swift-arg",
+            ),
+        ] {
+            let (message, span) = parse_error(contents);
+            assert!(
+                message.starts_with(&format!("unknown field `{key}`")),
+                "{message}"
+            );
+            assert_eq!(span, key);
+        }
+    }
+
+    #[test]
+    fn suggests_similar_keys() {
+        assert_eq!(
+            help("llvm-version = \"21.1.1\"\n[upload]\nslto = 2\n"),
+            "did you mean `slot`?"
+        );
+        assert_eq!(
+            help("llvm-version = \"21.1.1\"\n[upload]\nafter-upload = \"runn\"\n"),
+            "did you mean `run`?"
+        );
+        // Nothing is close enough to suggest
+        assert_eq!(
+            help("llvm-version = \"21.1.1\"\ncolour = true\n"),
+            "fix the errors in `v5.toml`"
+        );
+    }
+
+    /// Creates a git repository in a temporary directory, with the given files in it.
+    fn repo(files: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        for file in files {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    #[tokio::test]
+    async fn finds_config_in_ancestor() {
+        let dir = repo(&["v5.toml", "robots/arm/Package.swift"]);
+        let found = search_upwards(dir.path().join("robots/arm"), "v5.toml", None, None)
+            .await
+            .unwrap();
+        assert_eq!(found, Some(dir.path().join("v5.toml")));
+    }
+
+    #[tokio::test]
+    async fn nearest_config_wins() {
+        let dir = repo(&["v5.toml", "robots/arm/V5.TOML", "robots/arm/Package.swift"]);
+        let found = search_upwards(dir.path().join("robots/arm"), "v5.toml", None, None)
+            .await
+            .unwrap();
+        // Names are compared ignoring case
+        assert_eq!(found, Some(dir.path().join("robots/arm/V5.TOML")));
+    }
+
+    #[tokio::test]
+    async fn stops_at_repo_root() {
+        let dir = repo(&["a/b/main.swift"]);
+        let mut log = SearchLog::default();
+        let found = search_upwards(
+            dir.path().join("a/b"),
+            "Package.swift",
+            None,
+            Some(&mut log),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(found, None);
+        assert_eq!(log.stopped_because, Some(StopReason::RepoRoot));
+        assert_eq!(
+            log.searched,
+            [
+                dir.path().join("a/b"),
+                dir.path().join("a"),
+                dir.path().to_owned()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_home_dir() {
+        // Not a repository, so only the home directory stops the search
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home");
+        std::fs::create_dir_all(home.join("robot")).unwrap();
+        // Beyond the home directory, so never found
+        std::fs::write(dir.path().join("Package.swift"), "").unwrap();
+
+        let mut log = SearchLog::default();
+        let found = search_upwards(
+            home.join("robot"),
+            "Package.swift",
+            Some(&home),
+            Some(&mut log),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(found, None);
+        assert_eq!(log.stopped_because, Some(StopReason::HomeDir));
+        assert_eq!(log.searched, [home.join("robot"), home]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn detects_mount_points() {
+        // procfs is always mounted separately from the root filesystem
+        assert!(
+            is_mount_point(Path::new("/proc"), Path::new("/"))
+                .await
+                .unwrap()
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        assert!(
+            !is_mount_point(&dir.path().join("sub"), dir.path())
+                .await
+                .unwrap()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn stops_at_filesystem_boundary() {
+        let mut log = SearchLog::default();
+        let found = search_upwards(
+            PathBuf::from("/proc/self"),
+            "Package.swift",
+            None,
+            Some(&mut log),
+        )
+        .await
+        .unwrap();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        toml::from_str(s)
+        assert_eq!(found, None);
+        assert_eq!(log.stopped_because, Some(StopReason::FilesystemBoundary));
+        assert_eq!(log.searched.last(), Some(&PathBuf::from("/proc")));
     }
 }
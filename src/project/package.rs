@@ -0,0 +1,57 @@
+//! A model of the JSON printed by `swift package describe --type json`.
+
+use serde::Deserialize;
+
+/// The parts of a Swift package's manifest that swift-v5 cares about.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PackageDescription {
+    pub name: String,
+    #[serde(default)]
+    pub products: Vec<Product>,
+    #[serde(default)]
+    pub targets: Vec<Target>,
+}
+
+impl PackageDescription {
+    /// The package's executable products, in manifest order.
+    pub fn executables(&self) -> impl Iterator<Item = &Product> {
+        self.products
+            .iter()
+            .filter(|product| product.kind == ProductType::Executable)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Product {
+    pub name: String,
+    #[serde(default)]
+    pub targets: Vec<String>,
+    #[serde(rename = "type")]
+    pub kind: ProductType,
+}
+
+/// The kind of a product, written by SwiftPM like `{"executable": null}`.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProductType {
+    Executable,
+    /// A library, along with its linkage (e.g. `automatic`).
+    Library(Vec<String>),
+    Snippet,
+    Plugin,
+    Test,
+    Macro,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Target {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub product_memberships: Vec<String>,
+}
@@ -1,16 +1,60 @@
-use std::io::ErrorKind;
-#[cfg(unix)]
-use std::path::Path;
+use std::{
+    env::{self, consts::EXE_SUFFIX},
+    fmt::{self, Display},
+    fs,
+    io::{self, ErrorKind, IsTerminal},
+    path::{Component, Path, PathBuf},
+};
 
-use inquire::Confirm;
-use owo_colors::OwoColorize;
+use clap::Args;
+use tracing::debug;
 
 use crate::{
-    msg,
+    Error,
+    config::{Config, EffectiveConfig},
+    confirm, msg,
     project::Project,
-    toolchain::{ToolchainClient, ToolchainVersion, install::install},
+    toolchain::{
+        DeleteMode, InstallationState, ToolchainError, ToolchainVersion, delete_path,
+        install::{InstallAction, InstallOpts, VersionRequest, install_request},
+        is_toolchain_dir,
+    },
+    ui,
 };
 
+/// Where the project's toolchain is linked by default, relative to the project root.
+pub const DEFAULT_LINK_PATH: &str = "llvm-toolchain";
+
+#[derive(Args, Debug, Default)]
+pub struct ActivateOpts {
+    /// An installed toolchain version to link instead of the configured one, until the next
+    /// `activate`. Nothing is written to v5.toml
+    pub version: Option<String>,
+    /// Replace whatever is at the link's path, moving anything but a link to the trash
+    #[arg(long)]
+    pub force: bool,
+    /// Link the toolchain with a relative path, e.g. for projects synced between machines
+    #[arg(long)]
+    pub relative: bool,
+    /// Where to create the link, instead of `toolchain.link` in v5.toml or ./llvm-toolchain
+    #[arg(long, value_name = "PATH")]
+    pub path: Option<PathBuf>,
+    /// Keep a working link even if it points at a different toolchain, like one activated for a
+    /// specific version. Set when building.
+    #[arg(skip)]
+    pub keep_existing: bool,
+}
+
+/// Returns where the project's toolchain should be linked.
+///
+/// A configured path is relative to the project root.
+pub fn link_path(project: &Project, config: &EffectiveConfig) -> PathBuf {
+    let configured = config.toolchain_link.as_deref();
+    project
+        .path()
+        .join(configured.unwrap_or(Path::new(DEFAULT_LINK_PATH)))
+}
+
 #[cfg(unix)]
 fn symlink_internal<A: AsRef<Path>, B: AsRef<Path>>(original: A, to: B) -> std::io::Result<()> {
     std::os::unix::fs::symlink(original, to)
@@ -20,39 +64,494 @@ fn symlink_internal(original: AsRef<Path>, to: AsRef<Path>) -> io::Result<()> {
     std::os::windows::fs::symlink_dir(original, to)
 }
 
-pub async fn symlink() -> crate::Result<bool> {
-    if Path::new("./llvm-toolchain").exists() {
-        return Ok(true);
+/// Returns the directory a link points to, resolving relative targets against the link's
+/// own directory.
+pub fn resolve_link(link: &Path) -> io::Result<PathBuf> {
+    let target = fs::read_link(link)?;
+    Ok(match link.parent() {
+        Some(parent) if target.is_relative() => parent.join(target),
+        _ => target,
+    })
+}
+
+/// Computes a relative path which leads from the directory `base` to `target`.
+///
+/// Both paths should be absolute; `None` is returned if they don't share a root.
+pub fn relative_path(base: &Path, target: &Path) -> Option<PathBuf> {
+    let mut base = base.components().peekable();
+    let mut target = target.components().peekable();
+
+    // Skip the common prefix
+    let mut shares_root = false;
+    while let (Some(a), Some(b)) = (base.peek(), target.peek()) {
+        if a != b {
+            break;
+        }
+        shares_root = true;
+        base.next();
+        target.next();
     }
-    let confirmation = Confirm::new("Activate toolchain?")
-        .with_default(true)
-        .with_help_message("Symlinks the LLVM toolchain to ./llvm-toolchain (required for building projects). Make sure you're in your project's directory for this step.")
-        .prompt()?;
-    if !confirmation {
-        return Ok(false);
+    if !shares_root {
+        return None;
     }
+
+    let mut path: PathBuf = base.map(|_| Component::ParentDir).collect();
+    path.extend(target);
+    Some(path)
+}
+
+/// Picks the path the link at `link` should point at.
+fn link_target(toolchain: PathBuf, link: &Path, relative: bool) -> io::Result<PathBuf> {
+    if !relative {
+        return Ok(toolchain);
+    }
+
+    if cfg!(windows) {
+        eprintln!(
+            "{:>12} relative links aren't supported on Windows, using an absolute path",
+            ui::paint("Warning", ui::WARNING)
+        );
+        return Ok(toolchain);
+    }
+
+    // Canonicalize both sides so that symlinked directories (like /tmp on macOS) don't
+    // produce a path which only works from one of them.
+    let base = link
+        .parent()
+        .map_or_else(env::current_dir, |parent| Ok(parent.to_owned()))?
+        .canonicalize()?;
+    let toolchain = toolchain.canonicalize()?;
+    Ok(relative_path(&base, &toolchain).unwrap_or(toolchain))
+}
+
+/// Links the project's toolchain into the project, installing it first if needed.
+///
+/// A link to a different toolchain is replaced, unless [`ActivateOpts::keep_existing`] is set.
+///
+/// Returns `false` if the user declined to activate or install the toolchain.
+pub async fn symlink(config: &Config, opts: &ActivateOpts) -> crate::Result<bool> {
     let project = Project::find().await?;
-    let toolchain = ToolchainClient::using_data_dir().await?;
-    let version = if let Some(config) = project.config().await? {
-        ToolchainVersion::named(&config.llvm_version)
+    let effective = config.resolve(project.config().await?)?;
+    let link = match &opts.path {
+        Some(path) => path.clone(),
+        None => link_path(&project, &effective),
+    };
+    let existing = match fs::symlink_metadata(&link) {
+        Ok(metadata) => Some(metadata),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let is_link = existing
+        .as_ref()
+        .is_some_and(|metadata| metadata.is_symlink());
+    if let Some(metadata) = &existing
+        && !is_link
+        && !opts.force
+    {
+        return Err(Error::LinkOccupied {
+            path: link,
+            kind: if metadata.is_dir() {
+                "directory"
+            } else {
+                "file"
+            },
+        });
+    }
+
+    let overridden = opts
+        .version
+        .as_deref()
+        .map(|version| effective.toolchain_repo.parse_version(version))
+        .transpose()?;
+
+    // Nothing is configured to compare a working link against without looking up the latest
+    // release, so it's kept. A broken link is left to be repaired by installing the toolchain
+    // it points to.
+    let unpinned = effective.llvm_version.is_none() && effective.llvm_path.is_none();
+    if is_link
+        && link.exists()
+        && !opts.force
+        && overridden.is_none()
+        && (opts.keep_existing || unpinned)
+    {
+        return Ok(true);
+    }
+
+    let toolchain = effective.toolchain_client().await?;
+    let (target, version) = if let Some(version) = &overridden {
+        debug!(%version, "Using the toolchain version given on the command line");
+        (toolchain.install_path_for(version)?, Some(version.clone()))
+    } else if let Some(llvm_path) = &effective.llvm_path {
+        // Checked before asking, since there's nothing to install if it's wrong
+        let local = project.path().join(llvm_path);
+        if !is_toolchain_dir(&local) {
+            return Err(ToolchainError::LocalToolchainInvalid { path: local }.into());
+        }
+        debug!(?local, "Using the local toolchain");
+        (local, None)
     } else {
-        toolchain.latest_release().await?.version().to_owned()
-    };
-    let already_installed = toolchain.install_path_for(&version);
-    // if we're forcing the symlink, we don't care whether its alr installed
-    if !already_installed.exists() {
-        msg!("Selected toolchain is not installed. Installing...", "");
-        // TODO: avoid recalling Project::find, ToolchainClient::using_data_dir, etc.
-        install(true).await?; // force since we know it doesn't exist alr
-        Ok(true)
+        let version = if let Some(llvm_version) = &effective.llvm_version {
+            let source = effective.source("llvm-version");
+            debug!(llvm_version, ?source, "Using the pinned toolchain version");
+            effective.toolchain_repo.parse_version(llvm_version)?
+        } else {
+            debug!("No toolchain version is pinned, using the latest");
+            toolchain.latest_release(false).await?.version().to_owned()
+        };
+        (toolchain.install_path_for(&version)?, Some(version))
+    };
+
+    if is_link && !opts.force && links_to(&link, &target) {
+        debug!(?link, ?target, "The toolchain is already linked");
+        return Ok(true);
+    }
+
+    // Asking is pointless when the user named the version to activate
+    if overridden.is_none() {
+        let confirmation = confirm(
+            "Activate toolchain?",
+            &format!(
+                "Symlinks the LLVM toolchain to {} (required for building projects).",
+                link.display()
+            ),
+            effective.assume_yes,
+        )?;
+        if !confirmation {
+            return Ok(false);
+        }
+    }
+
+    if let Some(version) = &version {
+        let state = toolchain.installation_state(version);
+        if state != InstallationState::Installed {
+            if overridden.is_some() {
+                // Asking is optional here, since the version may just have been mistyped
+                let answer = confirm(
+                    &format!("LLVM toolchain {version} is not installed. Install it?"),
+                    "Downloads it without changing v5.toml. No = cancel",
+                    effective.assume_yes,
+                );
+                let install = match answer {
+                    Ok(install) => install,
+                    Err(Error::PromptUnavailable { .. }) => false,
+                    Err(error) => return Err(error),
+                };
+                if !install {
+                    return Err(ToolchainError::VersionNotInstalled {
+                        version: version.clone(),
+                        installed: toolchain
+                            .installed_versions()
+                            .await?
+                            .into_iter()
+                            .filter(|version| toolchain.version_is_installed(version))
+                            .collect(),
+                    }
+                    .into());
+                }
+            } else if state == InstallationState::Partial {
+                msg!(
+                    "Selected toolchain was only partially installed. Reinstalling...",
+                    ""
+                );
+            } else {
+                msg!("Selected toolchain is not installed. Installing...", "");
+            }
+
+            // TODO: avoid rebuilding the ToolchainClient
+            // Not forced, since that would throw away a partial download which could be resumed
+            let request = VersionRequest::Exact(version.clone());
+            let outcome = install_request(&InstallOpts::default(), &effective, request).await?;
+            if outcome.action == InstallAction::Cancelled {
+                return Ok(false);
+            }
+        }
+    }
+
+    if let Some(metadata) = &existing {
+        remove_existing(&link, metadata).await?;
+    }
+    let relative = opts.relative || effective.relative_symlink;
+    create_link(target, &link, relative)?;
+
+    if let Some(version) = &overridden {
+        msg!("Activated", "{version}");
+        note_configured(&effective, version);
+    }
+    Ok(true)
+}
+
+/// Checks that the project's toolchain leads to the tools a build needs, offering to repair the
+/// link if it doesn't.
+///
+/// Without a terminal or under `--yes`, a broken link is an error instead, since repairing it
+/// may download a toolchain. Returns `false` if the user cancelled the repair.
+pub async fn ensure_usable(config: &Config) -> crate::Result<bool> {
+    let project = Project::find().await?;
+    let effective = config.resolve(project.config().await?)?;
+    // A local toolchain is used directly, which is where the link points anyway
+    let path = match &effective.llvm_path {
+        Some(llvm_path) => project.path().join(llvm_path),
+        None => link_path(&project, &effective),
+    };
+    let Err(problem) = check_toolchain(&path) else {
+        return Ok(true);
+    };
+    debug!(?path, ?problem, "The toolchain can't be built with");
+    let error = Error::ToolchainUnusable {
+        path: path.clone(),
+        problem,
+    };
+
+    // There's nothing to reinstall for a local toolchain
+    let can_ask = io::stdin().is_terminal() && io::stderr().is_terminal();
+    if effective.llvm_path.is_some() || effective.assume_yes || !can_ask {
+        return Err(error);
+    }
+
+    eprintln!("{:>12} {error}", ui::paint("Warning", ui::WARNING));
+    let repair = confirm(
+        "Repair the toolchain?",
+        "Reinstalls the toolchain if needed and links it again. No = cancel the build",
+        false,
+    )?;
+    if !repair {
+        return Ok(false);
+    }
+
+    // Already agreed to, so activating shouldn't ask again
+    let mut config = config.clone();
+    config.flags.assume_yes = true;
+    let activate = ActivateOpts {
+        force: true,
+        ..Default::default()
+    };
+    if !symlink(&config, &activate).await? {
+        return Ok(false);
+    }
+
+    // Activating doesn't reinstall a toolchain which was damaged after it was installed
+    check_toolchain(&path).map_err(|problem| Error::ToolchainUnusable { path, problem })?;
+    msg!("Repaired", "the toolchain link");
+    Ok(true)
+}
+
+/// Points out the toolchain the config selects when a different version was activated, so the
+/// override isn't forgotten.
+fn note_configured(config: &EffectiveConfig, activated: &ToolchainVersion) {
+    let configured = if let Some(llvm_path) = &config.llvm_path {
+        format!("uses the local toolchain at {}", llvm_path.display())
+    } else if let Some(pinned) = config
+        .llvm_version
+        .as_deref()
+        .and_then(|pinned| config.toolchain_repo.parse_version(pinned).ok())
+    {
+        if pinned == *activated {
+            return;
+        }
+        format!("pins {pinned}")
     } else {
-        match symlink_internal(already_installed, String::from("./llvm-toolchain")) {
-            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
-                // The symlink already exists, which is fine.
-                Ok(())
+        return;
+    };
+
+    let source = match config.source("llvm-path").or(config.source("llvm-version")) {
+        Some(source) => source.to_string(),
+        None => "the config".to_string(),
+    };
+    eprintln!(
+        "{:>12} {source} {configured}; run `{}` to go back to it",
+        ui::paint("Note", ui::WARNING),
+        crate::invocation::command("activate")
+    );
+}
+
+/// The files a build needs from the toolchain, relative to its root.
+pub const REQUIRED_TOOLS: [&str; 2] = ["bin/clang", "bin/ld.lld"];
+
+/// Why the toolchain link can't be built with, found by [`check_toolchain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkProblem {
+    /// Nothing is at the link's path.
+    Missing,
+    /// The link points at something which doesn't exist, like an uninstalled toolchain.
+    Dangling { target: PathBuf },
+    /// The link leads to a directory without the tools a build needs, like a partly
+    /// extracted toolchain.
+    MissingTools {
+        target: PathBuf,
+        missing: Vec<&'static str>,
+    },
+}
+
+impl Display for LinkProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "doesn't exist"),
+            Self::Dangling { target } => {
+                write!(f, "points at {}, which doesn't exist", target.display())
             }
-            res => res,
-        }?;
-        Ok(true)
+            Self::MissingTools { target, missing } => write!(
+                f,
+                "leads to {}, which is missing {}",
+                target.display(),
+                missing.join(" and ")
+            ),
+        }
+    }
+}
+
+/// Checks that `link` resolves to a toolchain with the tools a build needs, and returns the
+/// toolchain's canonical path.
+///
+/// `link` may also be the toolchain directory itself, like a local toolchain.
+pub fn check_toolchain(link: &Path) -> Result<PathBuf, LinkProblem> {
+    let target = match link.canonicalize() {
+        Ok(target) => target,
+        Err(_) => {
+            return Err(match resolve_link(link) {
+                Ok(target) => LinkProblem::Dangling { target },
+                Err(_) if link.symlink_metadata().is_ok() => LinkProblem::Dangling {
+                    target: link.to_owned(),
+                },
+                Err(_) => LinkProblem::Missing,
+            });
+        }
+    };
+
+    let missing: Vec<&'static str> = REQUIRED_TOOLS
+        .into_iter()
+        .filter(|tool| !target.join(format!("{tool}{EXE_SUFFIX}")).is_file())
+        .collect();
+    if !missing.is_empty() {
+        return Err(LinkProblem::MissingTools { target, missing });
+    }
+
+    Ok(target)
+}
+
+/// Checks whether `link` is a symlink which resolves to the directory `target`.
+fn links_to(link: &Path, target: &Path) -> bool {
+    match (link.canonicalize(), target.canonicalize()) {
+        (Ok(resolved), Ok(target)) => resolved == target,
+        _ => false,
+    }
+}
+
+/// Clears whatever is at the link's path so it can be linked again.
+///
+/// Links are simply removed, but anything else is moved to the trash, since it may be a real
+/// copy of a toolchain or something the user created by accident.
+async fn remove_existing(link: &Path, metadata: &fs::Metadata) -> crate::Result<()> {
+    if metadata.is_symlink() {
+        debug!(?link, "Removing the existing link");
+        // Windows links to directories are removed like directories. The metadata is the
+        // link's own, so `is_dir` is always false for it.
+        #[cfg(windows)]
+        let is_dir_link = std::os::windows::fs::FileTypeExt::is_symlink_dir(&metadata.file_type());
+        #[cfg(not(windows))]
+        let is_dir_link = false;
+        if is_dir_link {
+            fs::remove_dir(link)?;
+        } else {
+            fs::remove_file(link)?;
+        }
+    } else {
+        delete_path(link, DeleteMode::Trash).await?;
+        msg!("Trashed", "{}, which was in the way", link.display());
+    }
+    Ok(())
+}
+
+/// Links `link` to the toolchain at `toolchain`, creating its parent directories if needed.
+fn create_link(toolchain: PathBuf, link: &Path, relative: bool) -> io::Result<()> {
+    if let Some(parent) = link.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let target = link_target(toolchain, link, relative)?;
+    debug!(?target, ?link, "Linking toolchain");
+    match symlink_internal(target, link) {
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            // The symlink already exists, which is fine.
+            Ok(())
+        }
+        res => res,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a toolchain in `dir` with the given tools.
+    fn fake_toolchain(dir: &Path, tools: &[&str]) {
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        for tool in tools {
+            fs::write(dir.join(format!("{tool}{EXE_SUFFIX}")), "").unwrap();
+        }
+    }
+
+    #[test]
+    fn healthy_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let toolchain = dir.path().join("21.1.1");
+        fake_toolchain(&toolchain, &REQUIRED_TOOLS);
+        let link = dir.path().join("llvm-toolchain");
+        symlink_internal(&toolchain, &link).unwrap();
+
+        assert_eq!(
+            check_toolchain(&link),
+            Ok(toolchain.canonicalize().unwrap())
+        );
+    }
+
+    #[test]
+    fn local_toolchain_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_toolchain(dir.path(), &REQUIRED_TOOLS);
+
+        assert_eq!(
+            check_toolchain(dir.path()),
+            Ok(dir.path().canonicalize().unwrap())
+        );
+    }
+
+    #[test]
+    fn dangling_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let toolchain = dir.path().join("21.1.1");
+        let link = dir.path().join("llvm-toolchain");
+        symlink_internal(&toolchain, &link).unwrap();
+
+        assert_eq!(
+            check_toolchain(&link),
+            Err(LinkProblem::Dangling { target: toolchain })
+        );
+    }
+
+    #[test]
+    fn partial_toolchain() {
+        let dir = tempfile::tempdir().unwrap();
+        let toolchain = dir.path().join("21.1.1");
+        fake_toolchain(&toolchain, &["bin/clang"]);
+        let link = dir.path().join("llvm-toolchain");
+        symlink_internal(&toolchain, &link).unwrap();
+
+        assert_eq!(
+            check_toolchain(&link),
+            Err(LinkProblem::MissingTools {
+                target: toolchain.canonicalize().unwrap(),
+                missing: vec!["bin/ld.lld"],
+            })
+        );
+    }
+
+    #[test]
+    fn missing_link() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(
+            check_toolchain(&dir.path().join("llvm-toolchain")),
+            Err(LinkProblem::Missing)
+        );
     }
 }
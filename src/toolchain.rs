@@ -8,34 +8,61 @@ use std::{
     cell::OnceCell,
     fmt::{self, Debug, Display},
     io::SeekFrom,
+    num::NonZeroU32,
     path::{Path, PathBuf},
-    sync::Arc,
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
     time::Duration,
 };
 
 use camino::Utf8Path;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
 use futures::TryStreamExt;
-use indicatif::ProgressBar;
+use indicatif::{HumanBytes, ProgressBar};
 use miette::Diagnostic;
 use octocrab::{
-    Octocrab,
+    Octocrab, Page,
     models::repos::{Asset, Release},
 };
-use reqwest::header;
-use sha2::{Digest, Sha256};
+use rayon::iter::{ParallelBridge, ParallelIterator};
+use reqwest::{RequestBuilder, StatusCode, header};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Sha512, digest::DynDigest};
 use strum::AsRefStr;
 use thiserror::Error;
-use tokio::io::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::{
+    io::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufWriter},
+    task::spawn_blocking,
+};
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, instrument, trace, warn};
+use tracing::{debug, instrument, trace, warn};
+use url::Url;
 
 use crate::{
     CheckCancellation, DIRS, PROGRESS_STYLE, PROGRESS_STYLE_MSG, PROGRESS_STYLE_SPINNER, Result,
-    TRASH, fs,
+    TRASH, config::Secret, fs,
 };
 
+pub mod default;
+pub mod du;
+pub mod env;
+pub mod export;
 mod extract;
+pub mod import;
 pub mod install;
+pub mod metadata;
+pub mod uninstall;
+pub mod which;
+
+use install::InstallReporter;
+use metadata::InstallMetadata;
+
+/// How often to check whether another process has finished installing a toolchain.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 static APP_USER_AGENT: &str = concat!(
     env!("CARGO_PKG_NAME"),
@@ -65,18 +92,180 @@ pub enum ToolchainError {
         allowed_arches: Vec<HostArch>,
         candidates: Vec<String>,
     },
+    #[error(
+        "Arm Toolchain for Embedded {version} does not exist.{}",
+        if suggestions.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "\nSimilar versions:\n{}",
+                suggestions.iter().map(|version| format!(" • {version}")).collect::<Vec<_>>().join("\n")
+            )
+        }
+    )]
+    #[diagnostic(code(swift_v5::toolchain::version_not_found))]
+    #[diagnostic(help("check the requested version, or the `llvm-version` pinned in `v5.toml`"))]
+    VersionNotFound {
+        version: ToolchainVersion,
+        suggestions: Vec<ToolchainVersion>,
+    },
+    #[error("LLVM toolchain {version} is not installed")]
+    #[diagnostic(code(swift_v5::toolchain::not_installed))]
+    #[diagnostic(help("run `{}` to install it", crate::invocation::command("install")))]
+    NotInstalled { version: ToolchainVersion },
+    #[error("`{input}` is not a valid LLVM toolchain version")]
+    #[diagnostic(code(swift_v5::toolchain::invalid_version))]
+    #[diagnostic(help("versions look like `21.1.1` or `21.1.0-rc1`"))]
+    InvalidVersion { input: String },
+    #[error(
+        "LLVM toolchain {version} is in use:\n{}",
+        reasons.iter().map(|reason| format!(" • {reason}")).collect::<Vec<_>>().join("\n")
+    )]
+    #[diagnostic(code(swift_v5::toolchain::in_use))]
+    #[diagnostic(help("pass `--force` to uninstall it anyway"))]
+    InUse {
+        version: ToolchainVersion,
+        reasons: Vec<String>,
+    },
+    #[error("Another swift-v5 process is installing LLVM toolchain {version}")]
+    #[diagnostic(code(swift_v5::toolchain::install_in_progress))]
+    #[diagnostic(help("wait for it to finish, or run without `--no-wait` to wait for it"))]
+    InstallInProgress { version: ToolchainVersion },
+    #[error("{} doesn't contain an LLVM toolchain", path.display())]
+    #[diagnostic(code(swift_v5::toolchain::local_toolchain_invalid))]
+    #[diagnostic(help(
+        "`llvm-path` in v5.toml should point at the toolchain's root directory, which contains \
+         `bin/clang`"
+    ))]
+    LocalToolchainInvalid { path: PathBuf },
+    #[error("The mirror has no file at {}", path.display())]
+    #[diagnostic(code(swift_v5::toolchain::download_failed))]
+    #[diagnostic(help("check that the archive has been copied to the mirror"))]
+    MirrorFileMissing { path: PathBuf },
+    #[error("{url} does not refer to a local file")]
+    #[diagnostic(code(swift_v5::toolchain::invalid_file_url))]
+    InvalidFileUrl { url: Url },
+    #[error(
+        "LLVM toolchain {version} is not installed.\n{}",
+        if installed.is_empty() {
+            "No toolchains are installed.".to_string()
+        } else {
+            format!(
+                "Installed versions:\n{}",
+                installed.iter().map(|version| format!(" • {version}")).collect::<Vec<_>>().join("\n")
+            )
+        }
+    )]
+    #[diagnostic(code(swift_v5::toolchain::not_installed))]
+    VersionNotInstalled {
+        version: ToolchainVersion,
+        installed: Vec<ToolchainVersion>,
+    },
+    #[error("{} is not an exported toolchain", path.display())]
+    #[diagnostic(code(swift_v5::toolchain::not_an_export))]
+    #[diagnostic(help(
+        "toolchain archives can be made with `{}`",
+        crate::invocation::command("toolchain export")
+    ))]
+    NotAnExport { path: PathBuf },
+    #[error(
+        "The toolchain was exported on {os} ({arch}), but this computer is {} ({})",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )]
+    #[diagnostic(code(swift_v5::toolchain::import_host_mismatch))]
+    #[diagnostic(help(
+        "pass `--force` to import it anyway, e.g. if it's a universal macOS toolchain"
+    ))]
+    ImportHostMismatch { os: String, arch: String },
+    #[error("No LLVM toolchains are installed")]
+    #[diagnostic(code(swift_v5::toolchain::none_installed))]
+    #[diagnostic(help("run `{}` to install one", crate::invocation::command("install")))]
+    NoneInstalled,
+    #[error(
+        "The LLVM toolchain at {} has no tool named `{tool}`.\nSearched:\n{}{}",
+        toolchain.display(),
+        searched.iter().map(|path| format!(" • {}", path.display())).collect::<Vec<_>>().join("\n"),
+        if available.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "\nAvailable tools:\n{}",
+                available.iter().map(|tool| format!(" • {tool}")).collect::<Vec<_>>().join("\n")
+            )
+        }
+    )]
+    #[diagnostic(code(swift_v5::toolchain::tool_not_found))]
+    ToolNotFound {
+        /// The toolchain's root directory.
+        toolchain: PathBuf,
+        tool: String,
+        searched: Vec<PathBuf>,
+        available: Vec<String>,
+    },
     #[error("Cannot download {name} because it has an invalid name")]
     #[diagnostic(code(swift_v5::toolchain::invalid_asset_name))]
     InvalidAssetName { name: String },
 
     #[error(
-        "The checksum of the downloaded asset did not match the expected value.
+        "The {algorithm} checksum of the downloaded asset did not match the expected value.
 - Expected: {expected:?}
 - Actual: {actual:?}"
     )]
     #[diagnostic(code(swift_v5::toolchain::checksum_mismatch))]
     #[diagnostic(help("the downloaded file may be corrupted or incomplete"))]
-    ChecksumMismatch { expected: String, actual: String },
+    ChecksumMismatch {
+        algorithm: ChecksumAlgorithm,
+        expected: String,
+        actual: String,
+    },
+    #[error(
+        "The {algorithm} checksum of the downloaded asset still didn't match after downloading it again.
+- Expected: {expected:?}
+- Actual: {actual:?}"
+    )]
+    #[diagnostic(code(swift_v5::toolchain::repeated_checksum_mismatch))]
+    #[diagnostic(help(
+        "the release or the configured `mirror` may be serving a corrupted file, or something may \
+         be interfering with the connection"
+    ))]
+    RepeatedChecksumMismatch {
+        algorithm: ChecksumAlgorithm,
+        expected: String,
+        actual: String,
+    },
+    #[error(
+        "The download ended early: received {} of {}",
+        HumanBytes(*actual),
+        HumanBytes(*expected)
+    )]
+    #[diagnostic(code(swift_v5::toolchain::truncated_download))]
+    #[diagnostic(help("run the command again to resume the download"))]
+    TruncatedDownload { expected: u64, actual: u64 },
+    #[error("The release has no checksum file for the downloaded asset ({url})")]
+    #[diagnostic(code(swift_v5::toolchain::checksum_missing))]
+    #[diagnostic(help(
+        "the download can't be verified; run without `--require-checksum` to install it anyway"
+    ))]
+    ChecksumMissing { url: Url },
+    #[error("The checksum file contains a line which isn't a {algorithm} checksum: `{line}`")]
+    #[diagnostic(code(swift_v5::toolchain::checksum_file_invalid))]
+    ChecksumFileInvalid {
+        algorithm: ChecksumAlgorithm,
+        line: String,
+    },
+    #[error(
+        "The checksum file doesn't list {asset}.\nIt has checksums for:\n{}",
+        available.iter().map(|name| format!(" • {name}")).collect::<Vec<_>>().join("\n")
+    )]
+    #[diagnostic(code(swift_v5::toolchain::checksum_not_listed))]
+    #[diagnostic(help(
+        "the download can't be verified; check that the mirror's checksum file is up to date"
+    ))]
+    ChecksumNotListed {
+        asset: String,
+        available: Vec<String>,
+    },
 
     #[error("Could not extract the toolchain asset")]
     #[diagnostic(transparent)]
@@ -125,12 +314,16 @@ impl HostOS {
 pub enum HostArch {
     #[strum(serialize = "universal")]
     Universal,
+    #[strum(serialize = "AArch64")]
     AAarch64,
     #[strum(serialize = "x86_64")]
     X86_64,
 }
 
 impl HostArch {
+    /// The architectures whose toolchains run on this host, best first.
+    ///
+    /// On macOS the native architecture comes before universal binaries.
     pub const fn current() -> &'static [Self] {
         const ALLOWED_ARCHES: &[HostArch] = &[
             #[cfg(target_arch = "x86_64")]
@@ -153,114 +346,420 @@ impl HostArch {
     }
 }
 
+/// The kinds of archive toolchains are published in.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    #[value(name = "tar.xz")]
+    TarXz,
+    Zip,
+    Dmg,
+}
+
+impl ArchiveFormat {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::TarXz => "tar.xz",
+            Self::Zip => "zip",
+            Self::Dmg => "dmg",
+        }
+    }
+
+    /// The formats to look for on the given OS, best first.
+    ///
+    /// DMGs come last on macOS since mounting and copying out of them is much slower and more
+    /// fragile than extracting an archive.
+    pub const fn preference(os: HostOS) -> &'static [Self] {
+        match os {
+            HostOS::Windows => &[Self::Zip, Self::TarXz, Self::Dmg],
+            HostOS::Darwin | HostOS::Linux => &[Self::TarXz, Self::Zip, Self::Dmg],
+        }
+    }
+}
+
+impl Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
 pub struct ToolchainRelease {
     release: Arc<Release>,
+    repo: Arc<ToolchainRepo>,
     version: OnceCell<ToolchainVersion>,
 }
 
 impl ToolchainRelease {
-    const ALLOWED_EXTENSIONS: &[&str] = &["dmg", "tar.xz", "zip"];
-
     pub fn new(release: Release) -> Self {
+        Self::from_repo(release, Arc::default())
+    }
+
+    /// Wraps a release of the given repository, which determines how its tag is parsed.
+    pub fn from_repo(release: Release, repo: Arc<ToolchainRepo>) -> Self {
         Self {
             version: OnceCell::new(),
             release: Arc::new(release),
+            repo,
         }
     }
 
     pub fn version(&self) -> &ToolchainVersion {
         self.version
-            .get_or_init(|| ToolchainVersion::from_tag_name(&self.release.tag_name))
+            .get_or_init(|| self.repo.version_from_tag(&self.release.tag_name))
+    }
+
+    /// The name of the release's git tag, like `release-20.1.0-ATfE`.
+    pub fn tag_name(&self) -> &str {
+        &self.release.tag_name
+    }
+
+    /// Whether this release is an unpublished draft.
+    pub fn is_draft(&self) -> bool {
+        self.release.draft
+    }
+
+    /// Whether GitHub marks this release as a prerelease, like a release candidate.
+    pub fn is_prerelease(&self) -> bool {
+        self.release.prerelease
     }
 
+    /// When the release was published, if it has been.
+    pub fn published_at(&self) -> Option<DateTime<Utc>> {
+        self.release.published_at
+    }
+
+    /// The release notes, in Markdown.
+    pub fn notes(&self) -> Option<&str> {
+        self.release.body.as_deref()
+    }
+
+    /// The URL of the release's page on GitHub.
+    pub fn html_url(&self) -> &Url {
+        &self.release.html_url
+    }
+
+    /// The files attached to the release.
+    pub fn assets(&self) -> impl Iterator<Item = ToolchainAsset> + '_ {
+        self.release.assets.iter().map(|asset| {
+            let mut toolchain_asset = ToolchainAsset::from(asset);
+            toolchain_asset.checksum_api_urls =
+                [ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Sha512]
+                    .into_iter()
+                    .filter_map(|algorithm| {
+                        let name = format!("{}.{}", asset.name, algorithm.extension());
+                        let checksum = self.release.assets.iter().find(|a| a.name == name)?;
+                        Some((algorithm, checksum.url.clone()))
+                    })
+                    .collect();
+            toolchain_asset
+        })
+    }
+
+    /// Picks the asset for the given host, preferring earlier architectures in `allowed_arches`
+    /// and then earlier formats in `formats`.
+    ///
+    /// Assets for other architectures or in formats which aren't listed are never picked.
     pub fn asset_for(
         &self,
         os: HostOS,
         allowed_arches: &[HostArch],
-    ) -> Result<&Asset, ToolchainError> {
+        formats: &[ArchiveFormat],
+    ) -> Result<ToolchainAsset, ToolchainError> {
         debug!(
             options = self.release.assets.len(),
-            ?os, ?allowed_arches, allowed_exts = ?Self::ALLOWED_EXTENSIONS,
+            ?os,
+            ?allowed_arches,
+            ?formats,
             "Searching for a compatible toolchain asset"
         );
 
-        let asset = self
-            .release
-            .assets
-            .iter()
-            .find(|a| {
+        let mut candidates: Vec<((usize, usize), ToolchainAsset)> = self
+            .assets()
+            .filter_map(|a| {
                 let mut components: Vec<&str> = a.name.split('-').collect();
 
                 // Remove the file extension from the last file name component
                 let last_idx = components.len() - 1;
 
-                let (last_component, file_extension) = components[last_idx]
-                    .split_once('.')
-                    .expect("filename has extension");
+                let (last_component, file_extension) = components[last_idx].split_once('.')?;
                 components[last_idx] = last_component;
 
                 let correct_os = components.contains(&os.as_ref());
-                let correct_arch = allowed_arches
+                let arch_rank = allowed_arches
                     .iter()
-                    .any(|arch| components.contains(&arch.as_ref()));
-                let correct_extension = Self::ALLOWED_EXTENSIONS.contains(&file_extension);
+                    .position(|arch| components.contains(&arch.as_ref()));
+                let format_rank = formats
+                    .iter()
+                    .position(|format| format.extension() == file_extension);
 
-                let valid = correct_os && correct_arch && correct_extension;
+                let valid = correct_os && arch_rank.is_some() && format_rank.is_some();
                 trace!(
                     name = a.name,
-                    correct_os, correct_arch, correct_extension, "Asset valid: {valid}"
+                    correct_os,
+                    ?arch_rank,
+                    ?format_rank,
+                    "Asset valid: {valid}"
                 );
 
-                valid
+                let (Some(arch_rank), Some(format_rank)) = (arch_rank, format_rank) else {
+                    return None;
+                };
+                correct_os.then_some(((arch_rank, format_rank), a))
             })
-            .ok_or_else(|| ToolchainError::ReleaseAssetMissing {
+            .collect();
+
+        // The architecture matters most, since universal binaries are twice the size of native
+        // ones. Sorting is stable, so equally ranked assets keep the release's order.
+        candidates.sort_by_key(|(rank, _)| *rank);
+        let mut candidates = candidates.into_iter();
+        let Some(((arch_rank, format_rank), asset)) = candidates.next() else {
+            return Err(ToolchainError::ReleaseAssetMissing {
                 allowed_os: os,
                 allowed_arches: allowed_arches.to_vec(),
-                candidates: self
-                    .release
-                    .assets
-                    .iter()
-                    .map(|a| a.name.to_string())
-                    .collect(),
-            })?;
+                candidates: self.assets().map(|a| a.name).collect(),
+            });
+        };
 
-        debug!(name = asset.name, "Found compatible asset");
+        debug!(
+            name = asset.name,
+            arch = allowed_arches[arch_rank].as_ref(),
+            format = %formats[format_rank],
+            passed_over = ?candidates.map(|(_, a)| a.name).collect::<Vec<_>>(),
+            "Found compatible asset"
+        );
 
         Ok(asset)
     }
 }
 
+/// How much of a toolchain version is on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallationState {
+    /// The toolchain is fully installed.
+    Installed,
+    /// The toolchain's directory exists, but its install didn't finish.
+    Partial,
+    /// The toolchain isn't installed.
+    Absent,
+}
+
+/// Keeps other swift-v5 processes from installing a toolchain version or writing to a
+/// cached download until it's dropped.
+///
+/// See [`ToolchainClient::lock_version`].
+#[derive(Debug)]
+pub struct FileLock {
+    _file: Option<std::fs::File>,
+}
+
+/// A file attached to a toolchain release, such as an archive for one host platform.
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolchainAsset {
+    pub name: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+    pub download_url: Url,
+    /// The asset's GitHub API endpoint, which is downloaded from instead when authenticated.
+    pub api_url: Url,
+    /// The API endpoints of the release's checksum files for this asset.
+    pub checksum_api_urls: Vec<(ChecksumAlgorithm, Url)>,
+}
+
+impl From<&Asset> for ToolchainAsset {
+    fn from(asset: &Asset) -> Self {
+        Self {
+            name: asset.name.clone(),
+            size: u64::try_from(asset.size).unwrap_or_default(),
+            download_url: asset.browser_download_url.clone(),
+            api_url: asset.url.clone(),
+            checksum_api_urls: Vec::new(),
+        }
+    }
+}
+
+/// A toolchain version, which also names the directory it's installed in.
+///
+/// Names read from files and release tags are checked with [`Self::is_valid_name`], so they
+/// can't point outside the toolchains directory. Anything which uses the name as a path checks
+/// it again, since the field can also be set directly.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub struct ToolchainVersion {
     pub name: String,
 }
 
 impl ToolchainVersion {
-    pub fn named(name: impl Into<String>) -> Self {
-        Self { name: name.into() }
+    /// Creates a version with the given name, which must be [valid](Self::is_valid_name).
+    pub fn named(name: impl Into<String>) -> Result<Self, ToolchainError> {
+        let version = Self { name: name.into() };
+        version.check_name()?;
+        Ok(version)
+    }
+
+    /// Fails with [`ToolchainError::InvalidVersion`] if the name isn't
+    /// [valid](Self::is_valid_name), like one set through the public field.
+    pub fn check_name(&self) -> Result<(), ToolchainError> {
+        if !Self::is_valid_name(&self.name) {
+            return Err(ToolchainError::InvalidVersion {
+                input: self.name.clone(),
+            });
+        }
+        Ok(())
     }
 
+    /// Whether `name` can be used as the name of a toolchain's directory.
+    ///
+    /// Names can't be empty, start with a dot, or contain path separators, drive separators or
+    /// control characters, which rules out `..` and anything else that could escape the
+    /// toolchains directory or be hidden in it.
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && !name.starts_with('.')
+            && !name.contains(['/', '\\', ':'])
+            && !name.chars().any(char::is_control)
+    }
+
+    /// Parses the version out of a tag in the official repository, like `release-20.1.0-ATfE`.
     pub fn from_tag_name(tag_name: impl AsRef<str>) -> Self {
-        let mut name = tag_name.as_ref();
-        name = name
-            .strip_prefix(ToolchainClient::RELEASE_PREFIX)
-            .unwrap_or(name);
-        name = name
-            .strip_suffix(ToolchainClient::RELEASE_SUFFIX)
-            .unwrap_or(name);
+        ToolchainRepo::default().version_from_tag(tag_name.as_ref())
+    }
 
-        Self {
+    /// Parses a version typed by the user, like `21.1.1`, `v21.1.1` or `release-21.1.1-ATfE`,
+    /// for use where the configured repository isn't known.
+    ///
+    /// Only tags in the official repository are understood; see [`ToolchainRepo::parse_version`].
+    pub fn parse(input: &str) -> Result<Self, ToolchainError> {
+        ToolchainRepo::default().parse_version(input)
+    }
+}
+
+/// The GitHub repository toolchains are released from, and how its release tags are named.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ToolchainRepo {
+    pub owner: String,
+    pub name: String,
+    /// The text before the version in a release's tag.
+    pub tag_prefix: String,
+    /// The text after the version in a release's tag, which also identifies toolchain releases
+    /// among any others in the repository.
+    pub tag_suffix: String,
+}
+
+impl ToolchainRepo {
+    pub const DEFAULT_OWNER: &str = "arm";
+    pub const DEFAULT_NAME: &str = "arm-toolchain";
+    pub const DEFAULT_TAG_PREFIX: &str = "release-";
+    pub const DEFAULT_TAG_SUFFIX: &str = "-ATfE"; // arm toolchain for embedded
+
+    /// Whether a release with this tag is a toolchain release, whose version can be installed.
+    pub fn is_toolchain_tag(&self, tag_name: &str) -> bool {
+        tag_name.ends_with(&self.tag_suffix)
+            && ToolchainVersion::is_valid_name(&self.version_from_tag(tag_name).name)
+    }
+
+    /// Parses the version out of a release's tag.
+    ///
+    /// The name isn't checked, since any release has a tag; see
+    /// [`ToolchainRepo::is_toolchain_tag`].
+    pub fn version_from_tag(&self, tag_name: &str) -> ToolchainVersion {
+        let mut name = tag_name;
+        name = name.strip_prefix(&self.tag_prefix).unwrap_or(name);
+        name = name.strip_suffix(&self.tag_suffix).unwrap_or(name);
+        ToolchainVersion {
             name: name.to_string(),
         }
     }
 
-    fn to_tag_name(&self) -> String {
-        format!(
-            "{}{}{}",
-            ToolchainClient::RELEASE_PREFIX,
-            self.name,
-            ToolchainClient::RELEASE_SUFFIX
-        )
+    /// Parses a version typed by the user, like `21.1.1`, `v21.1.1`, or a full tag in this
+    /// repository.
+    ///
+    /// The version must be made of dot-separated numbers, optionally followed by `-rcN`.
+    pub fn parse_version(&self, input: &str) -> Result<ToolchainVersion, ToolchainError> {
+        let without_v = input
+            .strip_prefix('v')
+            .filter(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or(input);
+        let version = self.version_from_tag(without_v);
+
+        let (numbers, rc) = match version.name.split_once("-rc") {
+            Some((numbers, rc)) => (numbers, Some(rc)),
+            None => (version.name.as_str(), None),
+        };
+        let is_number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+        let valid = numbers.split('.').count() >= 2
+            && numbers.split('.').all(is_number)
+            && rc.is_none_or(is_number);
+
+        if !valid {
+            return Err(ToolchainError::InvalidVersion {
+                input: input.to_string(),
+            });
+        }
+
+        Ok(version)
+    }
+
+    pub fn tag_for(&self, version: &ToolchainVersion) -> String {
+        format!("{}{}{}", self.tag_prefix, version.name, self.tag_suffix)
+    }
+}
+
+impl Default for ToolchainRepo {
+    fn default() -> Self {
+        Self {
+            owner: Self::DEFAULT_OWNER.to_string(),
+            name: Self::DEFAULT_NAME.to_string(),
+            tag_prefix: Self::DEFAULT_TAG_PREFIX.to_string(),
+            tag_suffix: Self::DEFAULT_TAG_SUFFIX.to_string(),
+        }
+    }
+}
+
+impl FromStr for ToolchainRepo {
+    type Err = String;
+
+    /// Parses an `owner/name` pair, keeping the default tag format.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+                Ok(Self {
+                    owner: owner.to_string(),
+                    name: name.to_string(),
+                    ..Self::default()
+                })
+            }
+            _ => Err(format!(
+                "expected a repository like `owner/name`, found `{s}`"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for ToolchainRepo {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for ToolchainRepo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
+impl TryFrom<String> for ToolchainVersion {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::named(name).map_err(|error| error.to_string())
+    }
+}
+
+impl From<ToolchainVersion> for String {
+    fn from(version: ToolchainVersion) -> Self {
+        version.name
     }
 }
 
@@ -270,6 +769,89 @@ impl Display for ToolchainVersion {
     }
 }
 
+/// Rewrites a download URL just before it's requested.
+pub type UrlRewrite = Arc<dyn Fn(Url) -> Url + Send + Sync>;
+
+/// Configures a [`ToolchainClient`].
+///
+/// Created by [`ToolchainClient::builder`].
+#[derive(Clone)]
+pub struct ToolchainClientBuilder {
+    toolchains_path: PathBuf,
+    cache_path: PathBuf,
+    gh_client: Option<Arc<Octocrab>>,
+    rewrite_url: Option<UrlRewrite>,
+    repo: ToolchainRepo,
+}
+
+impl ToolchainClientBuilder {
+    /// Installs toolchains from the given repository instead of Arm's, e.g. a fork.
+    pub fn repo(mut self, repo: ToolchainRepo) -> Self {
+        self.repo = repo;
+        self
+    }
+
+    /// Uses the given GitHub client to look up releases, e.g. one with a custom base URI.
+    ///
+    /// By default, an unauthenticated client is created for each toolchain client.
+    pub fn github_client(mut self, gh_client: Arc<Octocrab>) -> Self {
+        self.gh_client = Some(gh_client);
+        self
+    }
+
+    /// Rewrites the URL of every archive and checksum file before it's downloaded, e.g. to
+    /// point the client at a local server.
+    pub fn rewrite_urls(mut self, rewrite: impl Fn(Url) -> Url + Send + Sync + 'static) -> Self {
+        self.rewrite_url = Some(Arc::new(rewrite));
+        self
+    }
+
+    /// Creates the client, along with its toolchains and cache directories.
+    pub async fn build(self) -> Result<ToolchainClient> {
+        let Self {
+            toolchains_path,
+            cache_path,
+            gh_client,
+            rewrite_url,
+            repo,
+        } = self;
+        trace!(
+            ?toolchains_path,
+            ?cache_path,
+            "Initializing toolchain downloader"
+        );
+
+        tokio::try_join!(
+            fs::create_dir_all(&toolchains_path),
+            fs::create_dir_all(&cache_path),
+        )?;
+
+        let gh_client = match gh_client {
+            Some(gh_client) => gh_client,
+            None => Arc::new(Octocrab::builder().build().map_err(ToolchainError::from)?),
+        };
+
+        Ok(ToolchainClient {
+            gh_client,
+            client: reqwest::Client::builder()
+                .user_agent(APP_USER_AGENT)
+                .build()
+                .unwrap(),
+            toolchains_path,
+            cache_path,
+            mirror: None,
+            rewrite_url,
+            repo: Arc::new(repo),
+            preferred_format: None,
+            require_checksum: false,
+            delete_mode: DeleteMode::Trash,
+            github_token: None,
+            extract_threads: None,
+            reporter: None,
+        })
+    }
+}
+
 /// A client for downloading and installing the Arm Toolchain for Embedded (ATfE).
 #[derive(Clone)]
 pub struct ToolchainClient {
@@ -277,6 +859,18 @@ pub struct ToolchainClient {
     client: reqwest::Client,
     cache_path: PathBuf,
     toolchains_path: PathBuf,
+    mirror: Option<Url>,
+    rewrite_url: Option<UrlRewrite>,
+    repo: Arc<ToolchainRepo>,
+    preferred_format: Option<ArchiveFormat>,
+    require_checksum: bool,
+    delete_mode: DeleteMode,
+    /// The token assets are downloaded through the GitHub API with, if any.
+    github_token: Option<Secret>,
+    /// How many threads decode `.tar.xz` archives, or one per core if unset.
+    extract_threads: Option<NonZeroU32>,
+    /// Receives updates and progress bars, which are otherwise shown in the terminal.
+    reporter: Option<Arc<dyn InstallReporter>>,
 }
 
 impl Debug for ToolchainClient {
@@ -284,25 +878,59 @@ impl Debug for ToolchainClient {
         f.debug_struct("ToolchainClient")
             .field("cache_path", &self.cache_path)
             .field("toolchains_path", &self.toolchains_path)
+            .field("mirror", &self.mirror)
+            .field("repo", &self.repo)
+            .field("rewrites_urls", &self.rewrite_url.is_some())
+            .field("preferred_format", &self.preferred_format)
+            .field("require_checksum", &self.require_checksum)
+            .field("delete_mode", &self.delete_mode)
+            .field("has_github_token", &self.github_token.is_some())
+            .field("extract_threads", &self.extract_threads)
+            .field("has_reporter", &self.reporter.is_some())
             .finish()
     }
 }
 
 impl ToolchainClient {
-    pub const REPO_OWNER: &str = "arm";
-    pub const REPO_NAME: &str = "arm-toolchain";
-    pub const RELEASE_PREFIX: &str = "release-";
-    pub const RELEASE_SUFFIX: &str = "-ATfE"; // arm toolchain for embedded
+    /// The environment variable which disables [`ToolchainClient::migrate_from`] in
+    /// [`ToolchainClient::using_data_dir`].
+    pub const NO_MIGRATE_VAR: &str = "SWIFT_V5_NO_MIGRATE";
 
     /// Creates a new toolchain client that installs to a platform-specific data directory.
     ///
     /// For example, on macOS this is `~/Library/Application Support/dev.vexide.swift-v5/llvm-toolchains`.
-    pub async fn using_data_dir() -> Result<Self> {
-        Self::new(
+    ///
+    /// Toolchains installed in older locations are moved here first, unless the
+    /// `SWIFT_V5_NO_MIGRATE` environment variable is set. Each one is reported to `reporter`,
+    /// which then receives the client's updates like [`ToolchainClient::with_reporter`].
+    pub async fn using_data_dir(reporter: Option<Arc<dyn InstallReporter>>) -> Result<Self> {
+        let mut client = Self::new(
             DIRS.data_local_dir().join("llvm-toolchains"),
-            DIRS.cache_dir().join("downloads/llvm-toolchains"),
+            Self::default_cache_path(),
         )
-        .await
+        .await?;
+        client.reporter = reporter;
+
+        if std::env::var_os(Self::NO_MIGRATE_VAR).is_some_and(|value| !value.is_empty()) {
+            debug!("Skipping toolchain migration");
+        } else {
+            client
+                .migrate_from(&Self::legacy_toolchains_paths())
+                .await?;
+        }
+
+        Ok(client)
+    }
+
+    /// Directories which older versions of swift-v5 installed toolchains to.
+    fn legacy_toolchains_paths() -> Vec<PathBuf> {
+        // The roaming data directory, which differs from the local one on Windows
+        vec![DIRS.data_dir().join("llvm-toolchains")]
+    }
+
+    /// Returns the platform-specific directory where downloaded archives are cached.
+    pub fn default_cache_path() -> PathBuf {
+        DIRS.cache_dir().join("downloads/llvm-toolchains")
     }
 
     /// Creates a client that installs toolchains in the specified folder.
@@ -310,104 +938,512 @@ impl ToolchainClient {
         toolchains_path: impl Into<PathBuf>,
         cache_path: impl Into<PathBuf>,
     ) -> Result<Self> {
-        let toolchains_path = toolchains_path.into();
-        let cache_path = cache_path.into();
-        trace!(
-            ?toolchains_path,
-            ?cache_path,
-            "Initializing toolchain downloader"
-        );
+        Self::builder(toolchains_path, cache_path).build().await
+    }
 
-        tokio::try_join!(
-            fs::create_dir_all(&toolchains_path),
-            fs::create_dir_all(&cache_path),
-        )?;
+    /// Starts configuring a client that installs toolchains in the specified folder.
+    pub fn builder(
+        toolchains_path: impl Into<PathBuf>,
+        cache_path: impl Into<PathBuf>,
+    ) -> ToolchainClientBuilder {
+        ToolchainClientBuilder {
+            toolchains_path: toolchains_path.into(),
+            cache_path: cache_path.into(),
+            gh_client: None,
+            rewrite_url: None,
+            repo: ToolchainRepo::default(),
+        }
+    }
 
-        Ok(Self {
-            gh_client: octocrab::instance(),
-            client: reqwest::Client::builder()
-                .user_agent(APP_USER_AGENT)
-                .build()
-                .unwrap(),
-            toolchains_path,
-            cache_path,
-        })
+    /// Downloads toolchain archives from the given URL instead of GitHub.
+    ///
+    /// Assets are expected to be found directly inside the mirror, using the same file names
+    /// as the GitHub release.
+    ///
+    /// The mirror can also be a `file://` URL, like a directory on a network drive, in which
+    /// case archives are copied from it.
+    pub fn with_mirror(mut self, mut mirror: Url) -> Self {
+        if !mirror.path().ends_with('/') {
+            mirror.set_path(&format!("{}/", mirror.path()));
+        }
+
+        self.mirror = Some(mirror);
+        self
     }
 
-    /// Fetches the latest release of the Arm Toolchain for Embedded (ATfE) from the ARM GitHub repository.
-    #[instrument(skip(self))]
-    pub async fn latest_release(&self) -> Result<ToolchainRelease, ToolchainError> {
-        debug!("Fetching latest release from GitHub repo");
+    /// Installs toolchains from the given repository instead of Arm's, e.g. a fork.
+    pub fn with_repo(mut self, repo: ToolchainRepo) -> Self {
+        self.repo = Arc::new(repo);
+        self
+    }
 
-        let releases = self
-            .gh_client
-            .repos(Self::REPO_OWNER, Self::REPO_NAME)
-            .releases()
-            .list()
-            .per_page(10)
-            .send()
-            .await?;
+    /// The directory toolchains are installed in, with one subdirectory per version.
+    pub fn toolchains_path(&self) -> &Path {
+        &self.toolchains_path
+    }
 
-        let Some(latest_embedded_release) = releases
-            .items
-            .iter()
-            .find(|r| r.tag_name.ends_with(Self::RELEASE_SUFFIX))
-        else {
-            return Err(ToolchainError::LatestReleaseMissing {
-                candidates: releases.items.into_iter().map(|r| r.tag_name).collect(),
-            });
-        };
+    /// The directory downloaded archives are kept in.
+    pub fn cache_path(&self) -> &Path {
+        &self.cache_path
+    }
 
-        Ok(ToolchainRelease::new(latest_embedded_release.clone()))
+    /// The repository toolchains are installed from.
+    pub fn repo(&self) -> &ToolchainRepo {
+        &self.repo
     }
 
-    /// Fetches the given release of the Arm Toolchain for Embedded (ATfE) from the ARM GitHub repository.
-    #[instrument(skip(self))]
-    pub async fn get_release(
-        &self,
-        version: &ToolchainVersion,
-    ) -> Result<ToolchainRelease, ToolchainError> {
-        let release = self
-            .gh_client
-            .repos(Self::REPO_OWNER, Self::REPO_NAME)
-            .releases()
-            .get_by_tag(&dbg!(version.to_tag_name()))
-            .await?;
+    /// Uses the given GitHub client to look up releases, e.g. an authenticated one.
+    pub fn with_github_client(mut self, gh_client: Arc<Octocrab>) -> Self {
+        self.gh_client = gh_client;
+        self
+    }
 
-        Ok(ToolchainRelease::new(release.clone()))
+    /// Downloads assets through the GitHub API with the given token, which is the only way to
+    /// download them from a private repository.
+    ///
+    /// Mirrors are still downloaded from directly.
+    pub fn with_github_token(mut self, token: Secret) -> Self {
+        self.github_token = Some(token);
+        self
     }
 
-    /// Returns the path where the given toolchain version would be installed.
-    pub fn install_path_for(&self, version: &ToolchainVersion) -> PathBuf {
-        self.toolchains_path.join(&version.name)
+    /// Decodes `.tar.xz` archives on the given number of threads instead of one per core, e.g.
+    /// to use less memory.
+    pub fn with_extract_threads(mut self, threads: Option<NonZeroU32>) -> Self {
+        self.extract_threads = threads;
+        self
     }
 
-    /// Checks if the specified toolchain version is already installed.
-    pub fn version_is_installed(&self, version: &ToolchainVersion) -> bool {
-        self.install_path_for(version).exists()
+    /// Fails installations when the release has no checksum file for the asset, instead of
+    /// installing it unverified.
+    pub fn with_checksum_required(mut self, required: bool) -> Self {
+        self.require_checksum = required;
+        self
     }
 
-    /// Downloads the specified asset, verifies its checksum, extracts it, and installs it to the appropriate location.
-    ///
-    /// Returns the path to the extracted toolchain directory.
+    /// Picks assets in the given format when a release has one, over the host's usual
+    /// preference.
+    pub fn with_preferred_format(mut self, format: Option<ArchiveFormat>) -> Self {
+        self.preferred_format = format;
+        self
+    }
+
+    /// The archive formats to look for on the given OS, best first.
+    pub fn format_preference(&self, os: HostOS) -> Vec<ArchiveFormat> {
+        let mut formats = ArchiveFormat::preference(os).to_vec();
+        if let Some(preferred) = self.preferred_format {
+            formats.retain(|&format| format != preferred);
+            formats.insert(0, preferred);
+        }
+        formats
+    }
+
+    /// Sets how old installations are deleted when a toolchain is reinstalled.
+    pub fn with_delete_mode(mut self, mode: DeleteMode) -> Self {
+        self.delete_mode = mode;
+        self
+    }
+
+    /// Sends the client's updates, like progress bars and migrated toolchains, to `reporter`.
     ///
-    /// This method will also handle resuming downloads if the file already exists and is partially downloaded.
-    #[instrument(
+    /// [`ToolchainClient::install_version`] uses its options' reporter instead.
+    pub fn with_reporter(mut self, reporter: Arc<dyn InstallReporter>) -> Self {
+        self.reporter = Some(reporter);
+        self
+    }
+
+    /// How this client deletes installations.
+    pub fn delete_mode(&self) -> DeleteMode {
+        self.delete_mode
+    }
+
+    /// Prepares a progress bar to be shown, either in the terminal or through the install
+    /// reporter.
+    fn progress_bar(&self, bar: ProgressBar) -> ProgressBar {
+        match &self.reporter {
+            Some(reporter) => reporter.progress_bar(bar),
+            None => crate::ui::add_progress(bar),
+        }
+    }
+
+    /// Returns the URL the given asset should be downloaded from.
+    pub fn download_url(&self, asset: &ToolchainAsset) -> Result<Url, ToolchainError> {
+        match &self.mirror {
+            Some(mirror) => {
+                mirror
+                    .join(&asset.name)
+                    .map_err(|_| ToolchainError::InvalidAssetName {
+                        name: asset.name.to_string(),
+                    })
+            }
+            None => Ok(asset.download_url.clone()),
+        }
+    }
+
+    /// The GitHub API token to download release files with, unless they come from a mirror.
+    fn api_token(&self) -> Option<&Secret> {
+        self.github_token.as_ref().filter(|_| self.mirror.is_none())
+    }
+
+    /// Starts a request for a release file at `url`, or through its API endpoint when
+    /// authenticated.
+    ///
+    /// GitHub answers the API request with a redirect to a signed download URL, which is
+    /// followed without the token. Headers like `Range` are kept.
+    fn release_file_request(&self, url: Url, api_url: Option<&Url>) -> RequestBuilder {
+        match (self.api_token(), api_url) {
+            (Some(token), Some(api_url)) => {
+                trace!(%api_url, "Downloading through the GitHub API");
+                self.client
+                    .get(self.request_url(api_url.clone()))
+                    .bearer_auth(token.expose())
+                    .header(header::ACCEPT, "application/octet-stream")
+            }
+            _ => self.client.get(url).header(header::ACCEPT, "*/*"),
+        }
+    }
+
+    /// Applies the builder's URL rewrite, if any, to a URL which is about to be requested.
+    fn request_url(&self, url: Url) -> Url {
+        match &self.rewrite_url {
+            Some(rewrite) => rewrite(url),
+            None => url,
+        }
+    }
+
+    /// Fetches the latest release of the Arm Toolchain for Embedded (ATfE) from the ARM GitHub repository.
+    ///
+    /// Drafts are always skipped, and prereleases are skipped unless `include_prereleases` is set.
+    #[instrument(skip(self))]
+    pub async fn latest_release(
+        &self,
+        include_prereleases: bool,
+    ) -> Result<ToolchainRelease, ToolchainError> {
+        debug!("Fetching latest release from GitHub repo");
+
+        let mut releases = self.releases();
+        let mut candidates = Vec::new();
+        while let Some(release) = releases.next().await? {
+            let is_embedded = self.repo.is_toolchain_tag(release.tag_name());
+            if is_embedded
+                && !release.is_draft()
+                && (include_prereleases || !release.is_prerelease())
+            {
+                return Ok(release);
+            }
+
+            candidates.push(release.tag_name().to_string());
+        }
+
+        Err(ToolchainError::LatestReleaseMissing { candidates })
+    }
+
+    /// Lists the releases in the toolchain repository, newest first.
+    ///
+    /// Pages are fetched as they're needed, up to a total of [`ReleasePages::MAX_RELEASES`].
+    pub fn releases(&self) -> ReleasePages<'_> {
+        ReleasePages {
+            client: self,
+            page: None,
+            buffered: Vec::new().into_iter(),
+            examined: 0,
+        }
+    }
+
+    /// Fetches the given release of the Arm Toolchain for Embedded (ATfE) from the ARM GitHub repository.
+    #[instrument(skip(self))]
+    pub async fn get_release(
+        &self,
+        version: &ToolchainVersion,
+    ) -> Result<ToolchainRelease, ToolchainError> {
+        let result = self
+            .gh_client
+            .repos(&self.repo.owner, &self.repo.name)
+            .releases()
+            .get_by_tag(&self.repo.tag_for(version))
+            .await;
+
+        match result {
+            Ok(release) => Ok(ToolchainRelease::from_repo(release, self.repo.clone())),
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == StatusCode::NOT_FOUND =>
+            {
+                debug!("Release does not exist, searching for similar versions");
+
+                let mut releases = self.releases();
+                let mut tag_names = Vec::new();
+                while let Some(release) = releases.next().await? {
+                    tag_names.push(release.tag_name().to_string());
+                }
+
+                Err(ToolchainError::VersionNotFound {
+                    version: version.clone(),
+                    suggestions: similar_versions(
+                        &self.repo,
+                        version,
+                        tag_names.iter().map(String::as_str),
+                    ),
+                })
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Returns the path where the given toolchain version would be installed.
+    ///
+    /// Fails with [`ToolchainError::InvalidVersion`] if the version's name isn't
+    /// [valid](ToolchainVersion::is_valid_name), since the path could then be outside the
+    /// toolchains directory.
+    pub fn install_path_for(&self, version: &ToolchainVersion) -> Result<PathBuf, ToolchainError> {
+        version.check_name()?;
+        Ok(self.toolchains_path.join(&version.name))
+    }
+
+    /// Takes the lock on installing `version`, so that only one process at a time downloads and
+    /// extracts it.
+    ///
+    /// If another process holds the lock, this waits for it to finish, showing a spinner, or
+    /// fails with [`ToolchainError::InstallInProgress`] if `wait` is false. The lock is advisory
+    /// and belongs to an open file, so the operating system releases it when the lock is
+    /// dropped or its process exits, even if the process crashed.
+    pub async fn lock_version(
+        &self,
+        version: &ToolchainVersion,
+        wait: bool,
+        cancel_token: &CancellationToken,
+    ) -> Result<FileLock, ToolchainError> {
+        // Checked like `install_path_for`, since the name is part of the lock's file name too
+        version.check_name()?;
+        fs::create_dir_all(&self.toolchains_path).await?;
+        let lock_path = self.toolchains_path.join(format!(".{}.lock", version.name));
+        let waiting =
+            format!("Waiting for another swift-v5 process to finish installing {version}...");
+
+        self.lock_file(&lock_path, wait.then_some(waiting), cancel_token)
+            .await?
+            .ok_or_else(|| ToolchainError::InstallInProgress {
+                version: version.clone(),
+            })
+    }
+
+    /// Takes an exclusive lock on the file at `lock_path`, creating it if needed.
+    ///
+    /// If another process holds the lock, this shows the `waiting` message until it's released,
+    /// or returns `None` straight away if there's no message.
+    async fn lock_file(
+        &self,
+        lock_path: &Path,
+        waiting: Option<String>,
+        cancel_token: &CancellationToken,
+    ) -> Result<Option<FileLock>, ToolchainError> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(lock_path)?;
+
+        let try_lock = || match file.try_lock() {
+            Ok(()) => Ok(true),
+            Err(std::fs::TryLockError::WouldBlock) => Ok(false),
+            Err(std::fs::TryLockError::Error(error)) => Err(error),
+        };
+        match try_lock() {
+            Ok(true) => return Ok(Some(FileLock { _file: Some(file) })),
+            Ok(false) => {}
+            // Some filesystems, like certain network drives, don't support locks at all
+            Err(error) if error.kind() == std::io::ErrorKind::Unsupported => {
+                debug!(?lock_path, %error, "Continuing without a lock");
+                return Ok(Some(FileLock { _file: None }));
+            }
+            Err(error) => return Err(error.into()),
+        }
+
+        let Some(waiting) = waiting else {
+            return Ok(None);
+        };
+
+        debug!(
+            ?lock_path,
+            "Waiting for another process to release the lock"
+        );
+        let spinner = self.progress_bar(
+            ProgressBar::new_spinner()
+                .with_message(waiting)
+                .with_style(PROGRESS_STYLE_SPINNER.clone()),
+        );
+        spinner.enable_steady_tick(Duration::from_millis(300));
+        let result = loop {
+            tokio::select! {
+                _ = tokio::time::sleep(LOCK_POLL_INTERVAL) => {}
+                _ = cancel_token.cancelled() => break Err(ToolchainError::Cancelled),
+            }
+            match try_lock() {
+                Ok(true) => break Ok(()),
+                Ok(false) => {}
+                Err(error) => break Err(error.into()),
+            }
+        };
+        spinner.finish_and_clear();
+        result?;
+
+        Ok(Some(FileLock { _file: Some(file) }))
+    }
+
+    /// Reads where an installed toolchain came from, or returns `None` if it was installed by an
+    /// older version of swift-v5 which didn't record it.
+    pub async fn install_metadata(
+        &self,
+        version: &ToolchainVersion,
+    ) -> Result<Option<InstallMetadata>, ToolchainError> {
+        InstallMetadata::read(&self.install_path_for(version)?).await
+    }
+
+    /// Checks if the specified toolchain version is already installed.
+    ///
+    /// Directories left behind by an interrupted install don't count, see
+    /// [`ToolchainClient::installation_state`].
+    pub fn version_is_installed(&self, version: &ToolchainVersion) -> bool {
+        self.installation_state(version) == InstallationState::Installed
+    }
+
+    /// Checks whether the specified toolchain version is fully installed, or whether an
+    /// install was interrupted partway through.
+    ///
+    /// A toolchain is complete once its install metadata has been written. Toolchains
+    /// installed before the metadata existed count as complete if they contain `clang`.
+    pub fn installation_state(&self, version: &ToolchainVersion) -> InstallationState {
+        // A name which can't be a directory can't have been installed
+        let Ok(install_path) = self.install_path_for(version) else {
+            return InstallationState::Absent;
+        };
+        if !install_path.exists() {
+            return InstallationState::Absent;
+        }
+
+        let complete =
+            install_path.join(metadata::FILE_NAME).exists() || is_toolchain_dir(&install_path);
+        if complete {
+            InstallationState::Installed
+        } else {
+            InstallationState::Partial
+        }
+    }
+
+    /// Returns the path to a binary like `clang` in an installed toolchain, see [`find_tool`].
+    pub fn tool_path(
+        &self,
+        version: &ToolchainVersion,
+        tool: &str,
+    ) -> Result<PathBuf, ToolchainError> {
+        // A partial install might not have the tool yet, or only part of it
+        if self.installation_state(version) != InstallationState::Installed {
+            return Err(ToolchainError::NotInstalled {
+                version: version.clone(),
+            });
+        }
+
+        find_tool(&self.install_path_for(version)?, tool)
+    }
+
+    /// Returns the path to `clang` in an installed toolchain.
+    pub fn clang_path(&self, version: &ToolchainVersion) -> Result<PathBuf, ToolchainError> {
+        self.tool_path(version, "clang")
+    }
+
+    /// Returns the path to `llvm-objcopy` in an installed toolchain.
+    pub fn objcopy_path(&self, version: &ToolchainVersion) -> Result<PathBuf, ToolchainError> {
+        self.tool_path(version, "llvm-objcopy")
+    }
+
+    /// Moves toolchains installed in any of `legacy_paths` into this client's toolchains
+    /// directory, so they don't have to be downloaded again.
+    ///
+    /// Versions which are already installed here are left where they are, so this can safely
+    /// run more than once. Legacy directories are removed once they're empty.
+    pub async fn migrate_from(&self, legacy_paths: &[PathBuf]) -> Result<(), ToolchainError> {
+        for legacy_path in legacy_paths {
+            if *legacy_path == self.toolchains_path {
+                continue;
+            }
+
+            let mut read_dir = match fs::read_dir(legacy_path).await {
+                Ok(read_dir) => read_dir,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            while let Some(entry) = read_dir.next_entry().await? {
+                if !entry.file_type().await?.is_dir() {
+                    continue;
+                }
+
+                let destination = self.toolchains_path.join(entry.file_name());
+                if destination.exists() {
+                    debug!(
+                        source = ?entry.path(),
+                        ?destination,
+                        "Toolchain is already installed, not migrating"
+                    );
+                    continue;
+                }
+
+                debug!(source = ?entry.path(), ?destination, "Migrating toolchain");
+                extract::mv(&entry.path(), &destination, CancellationToken::new()).await?;
+                if let Some(reporter) = &self.reporter {
+                    reporter.migrated(&entry.file_name().to_string_lossy(), legacy_path);
+                }
+            }
+
+            // Only succeeds if nothing was left behind
+            if fs::remove_dir(legacy_path).await.is_ok() {
+                debug!(?legacy_path, "Removed legacy toolchains directory");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the installed toolchain versions, oldest first.
+    pub async fn installed_versions(&self) -> Result<Vec<ToolchainVersion>, ToolchainError> {
+        let mut versions = Vec::new();
+        let mut read_dir = fs::read_dir(&self.toolchains_path).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            // Skips staging directories, which are hidden
+            if entry.file_type().await?.is_dir()
+                && let Some(name) = entry.file_name().to_str()
+                && let Ok(version) = ToolchainVersion::named(name)
+            {
+                versions.push(version);
+            }
+        }
+
+        versions.sort_by_cached_key(|version| {
+            version
+                .name
+                .split('.')
+                .map(|part| part.parse::<u32>().unwrap_or_default())
+                .collect::<Vec<_>>()
+        });
+        Ok(versions)
+    }
+
+    /// Downloads the specified asset, verifies its checksum, extracts it, and installs it to the appropriate location.
+    ///
+    /// Returns the path to the extracted toolchain directory.
+    ///
+    /// This method will also handle resuming downloads if the file already exists and is partially downloaded.
+    #[instrument(
         skip(self, release, asset, cancel_token),
         fields(version = release.version().name, asset.name)
     )]
     pub async fn download_and_install(
         &self,
         release: &ToolchainRelease,
-        asset: &Asset,
+        asset: &ToolchainAsset,
         cancel_token: CancellationToken,
     ) -> Result<PathBuf, ToolchainError> {
-        let file_name = Utf8Path::new(&asset.name).file_name().ok_or_else(|| {
-            ToolchainError::InvalidAssetName {
-                name: asset.name.to_string(),
-            }
-        })?;
-        let archive_destination = self.cache_path.join(file_name);
+        let archive_destination = self.archive_path(asset)?;
+        let file_name = asset.name.as_str();
 
         debug!(asset.name, ?archive_destination, "Downloading asset");
 
@@ -423,60 +1459,122 @@ impl ToolchainClient {
             let client = self.clone();
             let asset = asset.clone();
             let archive_destination = archive_destination.clone();
+            let cancel_token = cancel_token.clone();
             async move {
-                let mut downloaded_file =
-                    client.download_asset(&asset, &archive_destination).await?;
-
-                debug!("Calculating checksum for downloaded file");
-                let checksum_bytes = calculate_file_checksum(&mut downloaded_file).await?;
-                let checksum_hex = hex::encode(checksum_bytes);
-                trace!(?checksum_hex, "Checksum calculated");
-
-                Ok::<_, ToolchainError>((downloaded_file, checksum_hex))
+                client
+                    .download_asset(&asset, &archive_destination, &cancel_token)
+                    .await
             }
         });
 
         let join_future =
             async { tokio::try_join!(download_task, download_checksum_task).unwrap() };
 
-        let ((mut downloaded_file, real_checksum), expected_checksum) = tokio::select! {
+        let (mut downloaded_file, expected_checksum) = tokio::select! {
             (download_result, checksum_result) = join_future => (download_result?, checksum_result?),
             _ = cancel_token.cancelled() => return Err(ToolchainError::Cancelled),
         };
 
+        // No point hashing a download that can't be installed anyway
+        if expected_checksum.is_none() && self.require_checksum {
+            return Err(ToolchainError::ChecksumMissing {
+                url: self.checksum_url(asset, ChecksumAlgorithm::Sha256)?,
+            });
+        }
+
+        // Hash with whichever algorithm the release published a checksum for, so the file
+        // only has to be read once.
+        let algorithm = expected_checksum
+            .as_ref()
+            .map_or(ChecksumAlgorithm::Sha256, |checksum| checksum.algorithm);
+
         // Verify the checksum to make sure the download was successful and the file is not corrupted.
+        // A corrupted file would otherwise be resumed by every later attempt, so it's downloaded
+        // again from scratch once before giving up.
+        let mut retried = false;
+        let real_checksum = loop {
+            debug!(%algorithm, "Calculating checksum for downloaded file");
+            let progress_bar = self.progress_bar(
+                ProgressBar::new(0)
+                    .with_style(PROGRESS_STYLE_MSG.clone())
+                    .with_message("Verifying"),
+            );
+            let real_checksum =
+                calculate_file_checksum(&downloaded_file, algorithm, &progress_bar, &cancel_token)
+                    .await?;
+            trace!(?real_checksum, "Checksum calculated");
+
+            let Some(expected_checksum) = &expected_checksum else {
+                break real_checksum;
+            };
+            let checksums_match = real_checksum.eq_ignore_ascii_case(&expected_checksum.hex);
+            debug!(
+                ?real_checksum,
+                ?expected_checksum,
+                "Checksum verification: {checksums_match}"
+            );
+            if checksums_match {
+                break real_checksum;
+            }
+            if retried {
+                return Err(ToolchainError::RepeatedChecksumMismatch {
+                    algorithm,
+                    expected: expected_checksum.hex.clone(),
+                    actual: real_checksum,
+                });
+            }
 
-        let checksums_match = real_checksum.eq_ignore_ascii_case(&expected_checksum);
-        debug!(
-            ?real_checksum,
-            ?expected_checksum,
-            "Checksum verification: {checksums_match}"
-        );
-        if !checksums_match {
-            return Err(ToolchainError::Io(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Checksum mismatch",
-            )));
+            debug!(
+                expected = expected_checksum.hex,
+                actual = real_checksum,
+                "Checksum mismatch, downloading the asset again"
+            );
+            if let Some(reporter) = &self.reporter {
+                reporter.retrying(asset);
+            }
+            drop(downloaded_file);
+            self.evict_cached(&asset.name).await?;
+            retried = true;
+
+            downloaded_file = tokio::select! {
+                download_result = self.download_asset(asset, &archive_destination, &cancel_token) => download_result?,
+                _ = cancel_token.cancelled() => return Err(ToolchainError::Cancelled),
+            };
+        };
+
+        let checksum_verified = expected_checksum.is_some();
+        if !checksum_verified {
+            debug!("Skipping checksum verification");
+            if let Some(reporter) = &self.reporter {
+                reporter.unverified(asset);
+            }
         }
 
         debug!("Download finished");
 
         // Now choose the extraction method based on the file extension.
 
-        let extract_location = self.install_path_for(release.version());
+        let extract_location = self.install_path_for(release.version())?;
 
         cancel_token.check_cancellation(ToolchainError::Cancelled)?;
 
         debug!(archive = ?archive_destination, ?extract_location, "Extracting downloaded archive");
-        let progress_bar = ProgressBar::new_spinner()
-            .with_message("Extracting toolchain... (this may take a few minutes)")
-            .with_style(PROGRESS_STYLE_SPINNER.clone());
-
-        progress_bar.enable_steady_tick(Duration::from_millis(300));
+        // ZIP archives are read from start to end, so they get a real progress bar
+        let progress_bar = if file_name.ends_with(".zip") {
+            self.progress_bar(ProgressBar::new(0).with_style(PROGRESS_STYLE_MSG.clone()))
+        } else {
+            let spinner = self.progress_bar(
+                ProgressBar::new_spinner()
+                    .with_message("Extracting toolchain... (this may take a few minutes)")
+                    .with_style(PROGRESS_STYLE_SPINNER.clone()),
+            );
+            spinner.enable_steady_tick(Duration::from_millis(300));
+            spinner
+        };
 
         if extract_location.exists() {
             debug!("Destination folder already exists, removing it");
-            TRASH.delete(&extract_location)?;
+            delete_path(&extract_location, self.delete_mode).await?;
         }
 
         downloaded_file.seek(SeekFrom::Start(0)).await?;
@@ -489,28 +1587,115 @@ impl ToolchainClient {
             )
             .await?;
         } else if file_name.ends_with(".zip") {
-            extract::extract_zip(downloaded_file, extract_location.clone()).await?;
+            extract::extract_zip(
+                downloaded_file,
+                extract_location.clone(),
+                &progress_bar,
+                cancel_token,
+            )
+            .await?;
         } else if file_name.ends_with(".tar.xz") {
-            extract::extract_tar_xz(downloaded_file, extract_location.clone(), cancel_token)
-                .await?;
+            extract::extract_tar_xz(
+                downloaded_file,
+                extract_location.clone(),
+                self.extract_threads,
+                cancel_token,
+            )
+            .await?;
         } else {
             unreachable!("Unsupported file format");
         }
 
         progress_bar.finish_with_message("Extraction complete");
 
+        let metadata = InstallMetadata {
+            schema_version: metadata::SCHEMA_VERSION,
+            version: release.version().clone(),
+            asset_name: asset.name.clone(),
+            asset_url: self.request_url(self.download_url(asset)?),
+            checksum_algorithm: algorithm,
+            checksum: real_checksum,
+            checksum_verified,
+            installed_at: Utc::now(),
+            installed_by: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        metadata.write(&extract_location).await?;
+
         Ok(extract_location)
     }
 
+    /// Returns the path the asset is downloaded to before it's extracted.
+    fn archive_path(&self, asset: &ToolchainAsset) -> Result<PathBuf, ToolchainError> {
+        self.archive_path_for(&asset.name)
+    }
+
+    fn archive_path_for(&self, asset_name: &str) -> Result<PathBuf, ToolchainError> {
+        let file_name = Utf8Path::new(asset_name).file_name().ok_or_else(|| {
+            ToolchainError::InvalidAssetName {
+                name: asset_name.to_string(),
+            }
+        })?;
+        Ok(self.cache_path.join(file_name))
+    }
+
+    /// Deletes the cached download of an asset, so the next install downloads it from scratch
+    /// rather than resuming it.
+    ///
+    /// Returns the number of bytes which were freed.
+    pub async fn evict_cached(&self, asset_name: &str) -> Result<u64, ToolchainError> {
+        let archive_path = self.archive_path_for(asset_name)?;
+        let len = match fs::metadata(&archive_path).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+
+        debug!(?archive_path, len, "Evicting cached download");
+        fs::remove_file(&archive_path).await?;
+        Ok(len)
+    }
+
+    /// Returns how much of the asset has already been downloaded by an earlier, interrupted
+    /// install which can be resumed.
+    pub async fn downloaded_len(&self, asset: &ToolchainAsset) -> Result<u64, ToolchainError> {
+        match fs::metadata(self.archive_path(asset)?).await {
+            // A file that's too large will be downloaded again from scratch.
+            Ok(metadata) if metadata.len() <= asset.size => Ok(metadata.len()),
+            Ok(_) => Ok(0),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Downloads the asset to the specified destination path without checksum verification or extraction.
     ///
     /// If the destination path already has a partially downloaded file, it will resume the download from where it left off.
-    #[instrument(skip(self, asset))]
+    ///
+    /// Waiting for another process to finish downloading the same file stops when
+    /// `cancel_token` is cancelled.
+    #[instrument(skip(self, asset, cancel_token))]
     async fn download_asset(
         &self,
-        asset: &Asset,
+        asset: &ToolchainAsset,
         destination: &Path,
+        cancel_token: &CancellationToken,
     ) -> Result<fs::File, ToolchainError> {
+        // Two processes appending to the same file would interleave their downloads. The lock
+        // is a separate file because Windows wouldn't let this process write to a locked file
+        // through another handle either. Dropping the future releases it.
+        let file_name = destination
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let lock_path = destination.with_file_name(format!(".{file_name}.lock"));
+        let waiting = format!(
+            "Waiting for another swift-v5 process to finish downloading {}...",
+            asset.name
+        );
+        let _lock = self
+            .lock_file(&lock_path, Some(waiting), cancel_token)
+            .await?;
+
         let mut file = fs::File::options()
             .read(true)
             .append(true)
@@ -522,7 +1707,7 @@ impl ToolchainClient {
 
         // Some initial checks before we start downloading to see if it makes sense to continue.
 
-        if current_file_length > asset.size as u64 {
+        if current_file_length > asset.size {
             // Having *too much* data doesn't make any sense... just restart the download from scratch.
             warn!(
                 ?current_file_length,
@@ -534,7 +1719,7 @@ impl ToolchainClient {
             current_file_length = file.seek(SeekFrom::End(0)).await?;
         }
 
-        if current_file_length == asset.size as u64 {
+        if current_file_length == asset.size {
             debug!("File already downloaded, skipping download");
             return Ok(file);
         }
@@ -543,7 +1728,7 @@ impl ToolchainClient {
         // set the Range header to continue downloading from where we left off.
 
         let next_byte_index = current_file_length;
-        let last_byte_index = asset.size as u64 - 1;
+        let last_byte_index = asset.size - 1;
         let range_header = format!("bytes={next_byte_index}-{last_byte_index}");
         trace!(?range_header, "Setting Range header for download");
 
@@ -551,90 +1736,1062 @@ impl ToolchainClient {
             debug!("Resuming an existing download");
         }
 
-        let progress = ProgressBar::new(asset.size as u64).with_style(PROGRESS_STYLE.clone());
+        let progress =
+            self.progress_bar(ProgressBar::new(asset.size).with_style(PROGRESS_STYLE.clone()));
         progress.set_position(current_file_length as u64);
         progress.reset_eta();
 
+        let url = self.request_url(self.download_url(asset)?);
+        if url.scheme() == "file" {
+            return copy_local_asset(
+                &url,
+                asset,
+                file,
+                current_file_length,
+                destination,
+                &progress,
+            )
+            .await;
+        }
+
         // At this point, we're all good to just start copying bytes from the stream to the file.
 
-        let mut stream = self
-            .client
-            .get(asset.browser_download_url.clone())
+        let response = self
+            .release_file_request(url, Some(&asset.api_url))
             .header(header::RANGE, range_header)
-            .header(header::ACCEPT, "*/*")
             .send()
             .await?
-            .error_for_status()?
-            .bytes_stream();
+            .error_for_status()?;
 
-        let mut writer = BufWriter::new(file);
+        // A server which ignores the Range header sends the whole file again.
+        if response.status() != StatusCode::PARTIAL_CONTENT && current_file_length > 0 {
+            debug!("Server doesn't support resuming, restarting the download");
+            file.set_len(0).await?;
+            current_file_length = 0;
+            progress.set_position(0);
+        }
+        let expected_len = response
+            .content_length()
+            .map(|len| current_file_length + len);
 
-        while let Some(chunk) = stream.try_next().await? {
+        let mut stream = response.bytes_stream();
+        let mut writer = BufWriter::new(file);
+        let mut actual_len = current_file_length;
+
+        loop {
+            let chunk = match stream.try_next().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                // What was received is still written below, so it isn't downloaded again
+                Err(error) if actual_len > current_file_length => {
+                    debug!(
+                        ?error,
+                        actual_len, "Connection lost partway through the download"
+                    );
+                    break;
+                }
+                Err(error) => return Err(error.into()),
+            };
             writer.write_all(&chunk).await?;
+            actual_len += chunk.len() as u64;
             progress.inc(chunk.len() as u64);
         }
 
         writer.flush().await?;
+        // Make sure the archive is really on disk before it's hashed and extracted, so a crash
+        // can't leave a file which is shorter than a resumed download expects. Compared to the
+        // download itself, this costs next to nothing.
+        writer.get_ref().sync_all().await?;
         progress.finish();
+
+        // A connection which is closed mid-body either ends the stream early or fails it. The
+        // partial file is kept so the next attempt can resume it.
+        if actual_len != asset.size || expected_len.is_some_and(|len| len != actual_len) {
+            return Err(ToolchainError::TruncatedDownload {
+                expected: asset.size,
+                actual: actual_len,
+            });
+        }
         debug!(?destination, "Download completed");
 
         Ok(writer.into_inner())
     }
 
-    /// Downloads the expected SHA256 checksum for the asset.
+    /// Returns the URL of the file containing the asset's checksum with the given algorithm.
+    fn checksum_url(
+        &self,
+        asset: &ToolchainAsset,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<Url, ToolchainError> {
+        let mut checksum_url = self.download_url(asset)?;
+        checksum_url.set_path(&format!(
+            "{}.{}",
+            checksum_url.path(),
+            algorithm.extension()
+        ));
+        Ok(checksum_url)
+    }
+
+    /// Downloads the expected checksum for the asset, preferring SHA-256 and falling back to
+    /// SHA-512.
     ///
-    /// The resulting string contains the checksum in hex format.
-    async fn fetch_asset_checksum(&self, asset: Asset) -> Result<String, ToolchainError> {
-        let mut sha256_url = asset.browser_download_url.clone();
-        sha256_url.set_path(&format!("{}.sha256", sha256_url.path()));
-
-        let mut checksum_file = self
-            .client
-            .get(sha256_url)
-            .send()
-            .await?
-            .error_for_status()?
-            .text()
-            .await?;
+    /// Returns `None` if the release doesn't have a checksum file for the asset.
+    async fn fetch_asset_checksum(
+        &self,
+        asset: ToolchainAsset,
+    ) -> Result<Option<Checksum>, ToolchainError> {
+        for algorithm in [ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Sha512] {
+            let url = self.request_url(self.checksum_url(&asset, algorithm)?);
+            let checksum_file = if url.scheme() == "file" {
+                match fs::read_to_string(local_path(&url)?).await {
+                    Ok(contents) => contents,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        debug!(%url, "Mirror has no checksum file");
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            } else {
+                let api_url = asset
+                    .checksum_api_urls
+                    .iter()
+                    .find(|(a, _)| *a == algorithm)
+                    .map(|(_, url)| url);
+                // The release's assets are already known, so there's nothing to request
+                if self.api_token().is_some() && api_url.is_none() {
+                    debug!(%algorithm, "Release has no checksum file");
+                    continue;
+                }
+
+                let response = self.release_file_request(url, api_url).send().await?;
+                if response.status() == StatusCode::NOT_FOUND {
+                    debug!(url = %response.url(), "Release has no checksum file");
+                    continue;
+                }
+
+                response.error_for_status()?.text().await?
+            };
+
+            let hex = parse_checksum_file(&checksum_file, &asset.name, algorithm)?;
+            return Ok(Some(Checksum { algorithm, hex }));
+        }
 
-        // Trim off the filename from the checksum file, which is usually in the format:
-        // `<checksum> <filename>`
+        Ok(None)
+    }
+}
+
+/// A hash function which releases publish checksums with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    /// The extension of the algorithm's checksum files, like `sha256`.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
 
-        let mut parts = checksum_file.split_ascii_whitespace();
-        let hash_part = parts.next().unwrap_or("");
-        checksum_file.truncate(hash_part.len());
+    /// The length of the algorithm's checksums in hex.
+    const fn hex_len(self) -> usize {
+        match self {
+            Self::Sha256 => 64,
+            Self::Sha512 => 128,
+        }
+    }
 
-        Ok(checksum_file)
+    /// Creates a hasher which calculates this kind of checksum.
+    pub fn hasher(self) -> Box<dyn DynDigest + Send> {
+        match self {
+            Self::Sha256 => Box::new(Sha256::default()),
+            Self::Sha512 => Box::new(Sha512::default()),
+        }
     }
 }
 
-/// Scans the entire file and calculates its SHA256 checksum.
-async fn calculate_file_checksum(file: &mut fs::File) -> Result<[u8; 32], io::Error> {
-    let file_size = file.metadata().await?.len();
+impl Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sha256 => f.write_str("SHA-256"),
+            Self::Sha512 => f.write_str("SHA-512"),
+        }
+    }
+}
+
+/// A checksum published alongside a release asset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    /// The checksum in hex.
+    pub hex: String,
+}
+
+/// Finds the asset's checksum in the contents of a checksum file.
+///
+/// Both `<hash>  <file>` lines (as written by `sha256sum`, where the file name may be prefixed
+/// with `*` or `./`) and BSD-style `SHA256 (<file>) = <hash>` lines are understood. A file
+/// with a single line is assumed to be for the asset whatever file name it gives, while files
+/// with several lines are searched for the asset's name.
+fn parse_checksum_file(
+    contents: &str,
+    asset_name: &str,
+    algorithm: ChecksumAlgorithm,
+) -> Result<String, ToolchainError> {
+    let bsd_prefix = format!("{} (", algorithm.extension().to_ascii_uppercase());
+    let mut entries = Vec::new();
+    for line in contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        let entry = if let Some(rest) = line.strip_prefix(bsd_prefix.as_str()) {
+            rest.rsplit_once(") = ")
+                .map(|(name, hash)| (hash.trim(), Some(name)))
+        } else {
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let hash = parts.next().unwrap_or_default();
+            let name = parts
+                .next()
+                .map(|name| name.trim_start().trim_start_matches('*'))
+                .filter(|name| !name.is_empty());
+            Some((hash, name))
+        };
+
+        match entry {
+            Some((hash, name))
+                if hash.len() == algorithm.hex_len()
+                    && hash.bytes().all(|b| b.is_ascii_hexdigit()) =>
+            {
+                let name = name.map(|name| name.strip_prefix("./").unwrap_or(name));
+                entries.push((hash, name));
+            }
+            _ => {
+                return Err(ToolchainError::ChecksumFileInvalid {
+                    algorithm,
+                    line: line.to_string(),
+                });
+            }
+        }
+    }
 
-    let progress_bar = ProgressBar::new(file_size)
-        .with_style(PROGRESS_STYLE_MSG.clone())
-        .with_message("Verifying");
+    let matching = match entries.as_slice() {
+        [] => None,
+        [(hash, _)] => Some(*hash),
+        entries => entries.iter().find_map(|(hash, name)| {
+            let name = (*name)?;
+            let file_name = Utf8Path::new(name).file_name().unwrap_or(name);
+            (file_name == asset_name).then_some(*hash)
+        }),
+    };
+
+    matching
+        .map(str::to_string)
+        .ok_or_else(|| ToolchainError::ChecksumNotListed {
+            asset: asset_name.to_string(),
+            available: entries
+                .iter()
+                .filter_map(|(_, name)| name.map(str::to_string))
+                .collect(),
+        })
+}
 
-    file.seek(SeekFrom::Start(0)).await?;
-    let mut reader = BufReader::new(file);
+/// Converts a `file://` URL into the path it refers to.
+fn local_path(url: &Url) -> Result<PathBuf, ToolchainError> {
+    url.to_file_path()
+        .map_err(|()| ToolchainError::InvalidFileUrl { url: url.clone() })
+}
 
-    let mut hasher = Sha256::default();
-    let mut data = vec![0; 64 * 1024];
+/// Copies an asset from a `file://` mirror into the download cache, resuming a partial copy
+/// the same way as an HTTP download.
+///
+/// The archive is always copied rather than hard-linked, since the cached file is later
+/// appended to, truncated and evicted, none of which may touch the mirror's copy.
+async fn copy_local_asset(
+    url: &Url,
+    asset: &ToolchainAsset,
+    file: fs::File,
+    current_file_length: u64,
+    destination: &Path,
+    progress: &ProgressBar,
+) -> Result<fs::File, ToolchainError> {
+    let source = local_path(url)?;
+    debug!(?source, ?destination, "Copying asset from mirror");
+    let mut reader = match fs::File::open(&source).await {
+        Ok(reader) => reader,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(ToolchainError::MirrorFileMissing { path: source });
+        }
+        Err(e) => return Err(e.into()),
+    };
+    reader.seek(SeekFrom::Start(current_file_length)).await?;
 
+    let mut writer = BufWriter::new(file);
+    let mut actual_len = current_file_length;
+    let mut buffer = vec![0; 1024 * 1024];
     loop {
-        let len = reader.read(&mut data).await?;
+        let len = reader.read(&mut buffer).await?;
         if len == 0 {
             break;
         }
 
-        hasher.update(&data[..len]);
-        progress_bar.inc(len as u64);
+        writer.write_all(&buffer[..len]).await?;
+        actual_len += len as u64;
+        progress.inc(len as u64);
     }
 
-    let checksum = hasher.finalize().into();
+    writer.flush().await?;
+    writer.get_ref().sync_all().await?;
+    progress.finish();
 
+    if actual_len != asset.size {
+        return Err(ToolchainError::TruncatedDownload {
+            expected: asset.size,
+            actual: actual_len,
+        });
+    }
+
+    Ok(writer.into_inner())
+}
+
+/// Returns the path to a binary like `clang` in the toolchain at `root`.
+///
+/// Both `bin/<tool>` and `bin/<tool>.exe` are tried, so the name shouldn't include a suffix.
+pub fn find_tool(root: &Path, tool: &str) -> Result<PathBuf, ToolchainError> {
+    let bin_dir = root.join("bin");
+    let searched = vec![bin_dir.join(tool), bin_dir.join(format!("{tool}.exe"))];
+    if let Some(path) = searched.iter().find(|path| is_executable(path)) {
+        return Ok(path.clone());
+    }
+
+    let available = match std::fs::read_dir(&bin_dir) {
+        Ok(read_dir) => {
+            let mut available = read_dir
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| is_executable(&entry.path()))
+                .filter_map(|entry| {
+                    let name = entry.file_name().into_string().ok()?;
+                    Some(name.strip_suffix(".exe").unwrap_or(&name).to_string())
+                })
+                .collect::<Vec<_>>();
+            available.sort();
+            available
+        }
+        Err(err) => {
+            debug!(?err, ?bin_dir, "Failed to list toolchain binaries");
+            Vec::new()
+        }
+    };
+
+    Err(ToolchainError::ToolNotFound {
+        toolchain: root.to_owned(),
+        tool: tool.to_string(),
+        searched,
+        available,
+    })
+}
+
+/// Checks whether `path` looks like the root of an LLVM toolchain, which has `bin/clang`.
+pub fn is_toolchain_dir(path: &Path) -> bool {
+    let bin = path.join("bin");
+    ["clang", "clang.exe"]
+        .iter()
+        .any(|name| is_executable(&bin.join(name)))
+}
+
+/// Checks whether `path` is a file which can be run.
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+/// Walks through the releases in the toolchain repository one page at a time.
+///
+/// Created by [`ToolchainClient::releases`].
+pub struct ReleasePages<'a> {
+    client: &'a ToolchainClient,
+    page: Option<Page<Release>>,
+    buffered: std::vec::IntoIter<Release>,
+    examined: usize,
+}
+
+impl ReleasePages<'_> {
+    /// The number of releases to look through before giving up, so that a repository with
+    /// many unrelated releases can't cause an unbounded number of requests.
+    pub const MAX_RELEASES: usize = 100;
+    const PER_PAGE: u8 = 30;
+
+    /// Returns the next release, fetching another page if needed.
+    pub async fn next(&mut self) -> Result<Option<ToolchainRelease>, ToolchainError> {
+        loop {
+            if self.examined >= Self::MAX_RELEASES {
+                debug!(examined = self.examined, "Reached the release search limit");
+                return Ok(None);
+            }
+
+            if let Some(release) = self.buffered.next() {
+                self.examined += 1;
+                return Ok(Some(ToolchainRelease::from_repo(
+                    release,
+                    self.client.repo.clone(),
+                )));
+            }
+
+            let next_page = match &self.page {
+                None => Some(
+                    self.client
+                        .gh_client
+                        .repos(&self.client.repo.owner, &self.client.repo.name)
+                        .releases()
+                        .list()
+                        .per_page(Self::PER_PAGE)
+                        .send()
+                        .await?,
+                ),
+                Some(page) => self.client.gh_client.get_page(&page.next).await?,
+            };
+
+            let Some(mut page) = next_page else {
+                return Ok(None);
+            };
+            trace!(items = page.items.len(), "Fetched a page of releases");
+            self.buffered = page.take_items().into_iter();
+            if self.buffered.len() == 0 {
+                return Ok(None);
+            }
+            self.page = Some(page);
+        }
+    }
+}
+
+/// Picks the versions from the given release tags which look like a typo of `version`.
+///
+/// The results are ordered from most to least similar.
+fn similar_versions<'a>(
+    repo: &ToolchainRepo,
+    version: &ToolchainVersion,
+    tag_names: impl IntoIterator<Item = &'a str>,
+) -> Vec<ToolchainVersion> {
+    const MAX_SUGGESTIONS: usize = 3;
+    const MIN_SIMILARITY: f64 = 0.8;
+
+    let mut candidates: Vec<(f64, ToolchainVersion)> = tag_names
+        .into_iter()
+        .filter(|tag_name| repo.is_toolchain_tag(tag_name))
+        .map(|tag_name| repo.version_from_tag(tag_name))
+        .map(|candidate| {
+            (
+                strsim::jaro_winkler(&version.name, &candidate.name),
+                candidate,
+            )
+        })
+        .filter(|(similarity, _)| *similarity >= MIN_SIMILARITY)
+        .collect();
+
+    candidates.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    trace!(?candidates, "Ranked similar versions");
+
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// How files and directories are removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeleteMode {
+    /// Move them to the system trash, so they can be recovered.
+    #[default]
+    Trash,
+    /// Delete them permanently.
+    Permanent,
+}
+
+/// Removes a file or directory.
+///
+/// Headless machines often don't have a trash, so if moving to the trash fails for a reason
+/// other than the path itself, it's deleted permanently instead.
+pub(crate) async fn delete_path(path: &Path, mode: DeleteMode) -> Result<(), ToolchainError> {
+    if mode == DeleteMode::Trash {
+        match TRASH.delete(path) {
+            Ok(()) => return Ok(()),
+            Err(err @ (trash::Error::TargetedRoot | trash::Error::CouldNotAccess { .. })) => {
+                return Err(err.into());
+            }
+            Err(err) => {
+                warn!(
+                    ?err,
+                    ?path,
+                    "Couldn't move to the trash, deleting permanently instead"
+                );
+            }
+        }
+    }
+
+    if fs::symlink_metadata(path).await?.is_dir() {
+        fs::remove_dir_all(path).await?;
+    } else {
+        fs::remove_file(path).await?;
+    }
+    Ok(())
+}
+
+/// Adds up the sizes of the files in a directory and all of its subdirectories.
+///
+/// Toolchains contain tens of thousands of files, so subdirectories are walked in parallel.
+/// Symlinks aren't followed.
+pub async fn dir_size(path: impl Into<PathBuf>) -> Result<u64, ToolchainError> {
+    fn walk(path: &Path) -> std::io::Result<u64> {
+        std::fs::read_dir(path)?
+            .par_bridge()
+            .map(|entry| {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                if metadata.is_dir() {
+                    walk(&entry.path())
+                } else {
+                    Ok(metadata.len())
+                }
+            })
+            .try_reduce(|| 0, |a, b| Ok(a + b))
+    }
+
+    let path = path.into();
+    Ok(spawn_blocking(move || walk(&path)).await.unwrap()?)
+}
+
+/// The size of the chunks read while hashing. Large reads are noticeably faster than the
+/// default buffer size on multi-gigabyte archives.
+const CHECKSUM_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// Scans the entire file and calculates its checksum, returned in hex.
+///
+/// Hashing is CPU-bound, so it runs on a blocking thread which reports its progress through
+/// an atomic counter. The progress bar's length is set to the size of the file.
+async fn calculate_file_checksum(
+    file: &fs::File,
+    algorithm: ChecksumAlgorithm,
+    progress_bar: &ProgressBar,
+    cancel_token: &CancellationToken,
+) -> Result<String, ToolchainError> {
+    let file_size = file.metadata().await?.len();
+    progress_bar.set_length(file_size);
+
+    let mut std_file = file.try_clone().await?.into_std().await;
+    let bytes_hashed = Arc::new(AtomicU64::new(0));
+    let task = spawn_blocking({
+        let bytes_hashed = bytes_hashed.clone();
+        let cancel_token = cancel_token.clone();
+        move || {
+            use std::io::{Read, Seek};
+
+            std_file.seek(SeekFrom::Start(0))?;
+
+            let mut hasher = algorithm.hasher();
+            let mut data = vec![0; CHECKSUM_BUFFER_SIZE];
+            loop {
+                cancel_token.check_cancellation(ToolchainError::Cancelled)?;
+
+                let len = std_file.read(&mut data)?;
+                if len == 0 {
+                    break;
+                }
+
+                hasher.update(&data[..len]);
+                bytes_hashed.fetch_add(len as u64, Ordering::Relaxed);
+            }
+
+            Ok::<String, ToolchainError>(hex::encode(hasher.finalize()))
+        }
+    });
+    tokio::pin!(task);
+
+    let mut ticker = tokio::time::interval(Duration::from_millis(100));
+    let checksum = loop {
+        tokio::select! {
+            result = &mut task => break result.unwrap()?,
+            _ = ticker.tick() => progress_bar.set_position(bytes_hashed.load(Ordering::Relaxed)),
+        }
+    };
+
+    progress_bar.set_position(bytes_hashed.load(Ordering::Relaxed));
     progress_bar.finish();
 
     Ok(checksum)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Names which could escape the toolchains directory or hide in it.
+    const UNSAFE_NAMES: [&str; 8] = [
+        "", "..", "../x", "a/b", "a\\b", "C:x", ".hidden", "21.1.1\n",
+    ];
+
+    #[test]
+    fn rejects_unsafe_names() {
+        for name in UNSAFE_NAMES {
+            assert!(!ToolchainVersion::is_valid_name(name), "{name:?}");
+            assert!(
+                matches!(
+                    ToolchainVersion::named(name),
+                    Err(ToolchainError::InvalidVersion { .. })
+                ),
+                "{name:?}"
+            );
+            assert!(ToolchainVersion::try_from(name.to_string()).is_err());
+            assert!(ToolchainVersion::parse(name).is_err(), "{name:?}");
+        }
+
+        assert!(ToolchainVersion::named("21.1.1").is_ok());
+        assert!(ToolchainVersion::named("21.1.0-rc1").is_ok());
+    }
+
+    #[tokio::test]
+    async fn unsafe_names_have_no_install_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = ToolchainClient::new(dir.path().join("toolchains"), dir.path().join("cache"))
+            .await
+            .unwrap();
+
+        for name in UNSAFE_NAMES {
+            // The field is public, so it can be set to anything
+            let version = ToolchainVersion {
+                name: name.to_string(),
+            };
+            assert!(
+                matches!(
+                    client.install_path_for(&version),
+                    Err(ToolchainError::InvalidVersion { .. })
+                ),
+                "{name:?}"
+            );
+            assert!(
+                matches!(
+                    client
+                        .lock_version(&version, false, &CancellationToken::new())
+                        .await,
+                    Err(ToolchainError::InvalidVersion { .. })
+                ),
+                "{name:?}"
+            );
+            assert_eq!(
+                client.installation_state(&version),
+                InstallationState::Absent
+            );
+        }
+
+        let version = ToolchainVersion::named("21.1.1").unwrap();
+        assert_eq!(
+            client.install_path_for(&version).unwrap(),
+            dir.path().join("toolchains/21.1.1")
+        );
+    }
+
+    const ASSET: &str = "ATfE-21.1.1-Linux-x86_64.tar.xz";
+
+    /// A SHA-256 checksum made of one repeated hex digit.
+    fn hash(digit: char) -> String {
+        digit.to_string().repeat(64)
+    }
+
+    fn parse(contents: &str) -> Result<String, ToolchainError> {
+        parse_checksum_file(contents, ASSET, ChecksumAlgorithm::Sha256)
+    }
+
+    #[test]
+    fn single_checksum() {
+        // A single line is for the asset whatever it names, or if it names nothing
+        for contents in [
+            format!("{}\n", hash('a')),
+            format!("{}  other-name.tar.xz\n", hash('a')),
+            format!("\n  {}  {ASSET}  \n\n", hash('a')),
+        ] {
+            assert_eq!(parse(&contents).unwrap(), hash('a'), "{contents:?}");
+        }
+    }
+
+    #[test]
+    fn multiple_checksums() {
+        let contents = format!(
+            "{}  ATfE-21.1.1-Darwin-universal.dmg\n{} *./dist/{ASSET}\n{}  ATfE-21.1.1-Windows-x86_64.zip\n",
+            hash('a'),
+            hash('b'),
+            hash('c'),
+        );
+        assert_eq!(parse(&contents).unwrap(), hash('b'));
+
+        let bsd = format!(
+            "SHA256 (ATfE-21.1.1-Darwin-universal.dmg) = {}\nSHA256 ({ASSET}) = {}\n",
+            hash('a'),
+            hash('b'),
+        );
+        assert_eq!(parse(&bsd).unwrap(), hash('b'));
+    }
+
+    #[test]
+    fn binary_marker() {
+        let contents = format!("{} *{ASSET}\n{} *other.zip\n", hash('d'), hash('e'));
+        assert_eq!(parse(&contents).unwrap(), hash('d'));
+    }
+
+    #[test]
+    fn missing_entry() {
+        let contents = format!("{}  a.dmg\n{}  b.zip\n", hash('a'), hash('b'));
+        assert!(matches!(
+            parse(&contents),
+            Err(ToolchainError::ChecksumNotListed { asset, available })
+                if asset == ASSET && available == ["a.dmg", "b.zip"]
+        ));
+        assert!(matches!(
+            parse(""),
+            Err(ToolchainError::ChecksumNotListed { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_lines() {
+        for contents in [
+            "not a checksum\n".to_string(),
+            // A SHA-512 checksum in a SHA-256 file
+            format!("{}{}  {ASSET}\n", hash('a'), hash('a')),
+            format!("{}  {ASSET}\n<html>\n", hash('a')),
+        ] {
+            assert!(
+                matches!(
+                    parse(&contents),
+                    Err(ToolchainError::ChecksumFileInvalid { .. })
+                ),
+                "{contents:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn installation_states() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = ToolchainClient::new(dir.path().join("toolchains"), dir.path().join("cache"))
+            .await
+            .unwrap();
+        let state = |name: &str| client.installation_state(&ToolchainVersion::named(name).unwrap());
+        let install_path = |name: &str| dir.path().join("toolchains").join(name);
+
+        assert_eq!(state("21.1.1"), InstallationState::Absent);
+
+        // An install which was interrupted before the metadata was written
+        std::fs::create_dir_all(install_path("21.1.1").join("bin")).unwrap();
+        assert_eq!(state("21.1.1"), InstallationState::Partial);
+
+        std::fs::write(install_path("21.1.1").join(metadata::FILE_NAME), "{}").unwrap();
+        assert_eq!(state("21.1.1"), InstallationState::Installed);
+    }
+
+    #[tokio::test]
+    async fn partial_installs_have_no_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = ToolchainClient::new(dir.path().join("toolchains"), dir.path().join("cache"))
+            .await
+            .unwrap();
+        let version = ToolchainVersion::named("21.1.1").unwrap();
+        let install_path = dir.path().join("toolchains/21.1.1");
+        std::fs::create_dir_all(install_path.join("bin")).unwrap();
+        executable(&install_path.join("bin/llvm-objcopy"));
+
+        // The tool is there, but the rest of the install might not be
+        assert!(matches!(
+            client.objcopy_path(&version),
+            Err(ToolchainError::NotInstalled { .. })
+        ));
+
+        std::fs::write(install_path.join(metadata::FILE_NAME), "{}").unwrap();
+        assert_eq!(
+            client.objcopy_path(&version).unwrap(),
+            install_path.join("bin/llvm-objcopy")
+        );
+    }
+
+    /// Toolchains installed before the metadata existed count as installed if they have
+    /// `clang`, even without the other tools a build needs.
+    #[tokio::test]
+    async fn legacy_installs_need_clang() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = ToolchainClient::new(dir.path().join("toolchains"), dir.path().join("cache"))
+            .await
+            .unwrap();
+        let version = ToolchainVersion::named("20.1.0").unwrap();
+        let bin = dir.path().join("toolchains/20.1.0/bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("ld.lld"), "").unwrap();
+        assert_eq!(
+            client.installation_state(&version),
+            InstallationState::Partial
+        );
+
+        executable(&bin.join("clang"));
+        assert_eq!(
+            client.installation_state(&version),
+            InstallationState::Installed
+        );
+    }
+
+    /// Creates an empty file which can be run.
+    fn executable(path: &Path) {
+        std::fs::write(path, "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn finds_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = ToolchainClient::new(dir.path().join("toolchains"), dir.path().join("cache"))
+            .await
+            .unwrap();
+        let version = ToolchainVersion::named("21.1.1").unwrap();
+        assert!(matches!(
+            client.tool_path(&version, "clang"),
+            Err(ToolchainError::NotInstalled { .. })
+        ));
+
+        let bin = dir.path().join("toolchains/21.1.1/bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        executable(&bin.join("clang"));
+        executable(&bin.join("ld.lld.exe"));
+        // Files which can't be run aren't tools
+        std::fs::write(bin.join("README"), "").unwrap();
+
+        assert_eq!(
+            client.tool_path(&version, "clang").unwrap(),
+            bin.join("clang")
+        );
+        assert_eq!(
+            client.tool_path(&version, "ld.lld").unwrap(),
+            bin.join("ld.lld.exe")
+        );
+
+        let missing = client.tool_path(&version, "llvm-objcopy");
+        #[cfg(unix)]
+        let expected: &[&str] = &["clang", "ld.lld"];
+        #[cfg(not(unix))]
+        let expected: &[&str] = &["README", "clang", "ld.lld"];
+        assert!(
+            matches!(
+                &missing,
+                Err(ToolchainError::ToolNotFound { tool, available, .. })
+                    if tool == "llvm-objcopy" && available == expected
+            ),
+            "{missing:?}"
+        );
+    }
+
+    /// A client whose progress bars are hidden, and the lock file it should use.
+    async fn locking_client(dir: &Path) -> (ToolchainClient, PathBuf) {
+        struct Hidden;
+        impl InstallReporter for Hidden {}
+
+        let client = ToolchainClient::new(dir.join("toolchains"), dir.join("cache"))
+            .await
+            .unwrap()
+            .with_reporter(Arc::new(Hidden));
+        (client, dir.join(".test.lock"))
+    }
+
+    #[tokio::test]
+    async fn lock_waits_until_released() {
+        let dir = tempfile::tempdir().unwrap();
+        let (client, lock_path) = locking_client(dir.path()).await;
+        let cancel_token = CancellationToken::new();
+
+        let first = client.lock_file(&lock_path, None, &cancel_token).await;
+        let first = first.unwrap().expect("lock is free");
+        // Without a message, a held lock isn't waited for
+        let not_waiting = client.lock_file(&lock_path, None, &cancel_token).await;
+        assert!(not_waiting.unwrap().is_none());
+
+        let waiting = client.lock_file(&lock_path, Some("Waiting".to_string()), &cancel_token);
+        tokio::pin!(waiting);
+        let still_held = tokio::time::timeout(LOCK_POLL_INTERVAL * 3, &mut waiting).await;
+        assert!(still_held.is_err());
+
+        drop(first);
+        let second = tokio::time::timeout(LOCK_POLL_INTERVAL * 4, waiting)
+            .await
+            .expect("lock is taken once it's released");
+        assert!(second.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn lock_wait_can_be_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let (client, lock_path) = locking_client(dir.path()).await;
+        let _first = client
+            .lock_file(&lock_path, None, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        let cancel_token = CancellationToken::new();
+        cancel_token.cancel();
+        let waiting = client
+            .lock_file(&lock_path, Some("Waiting".to_string()), &cancel_token)
+            .await;
+        assert!(matches!(waiting, Err(ToolchainError::Cancelled)));
+    }
+
+    /// Records the toolchains which were migrated.
+    #[derive(Default)]
+    struct MigrationRecorder(std::sync::Mutex<Vec<String>>);
+
+    impl InstallReporter for MigrationRecorder {
+        fn migrated(&self, name: &str, _from: &Path) {
+            self.0.lock().unwrap().push(name.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn migrates_legacy_toolchains() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("llvm-toolchains");
+        let toolchains = dir.path().join("toolchains");
+        std::fs::create_dir_all(legacy.join("20.1.0/bin")).unwrap();
+        std::fs::write(legacy.join("20.1.0/bin/clang"), "old").unwrap();
+        std::fs::create_dir_all(legacy.join("21.1.1")).unwrap();
+        std::fs::write(legacy.join("notes.txt"), "").unwrap();
+        // Already installed, so it stays where it is
+        std::fs::create_dir_all(toolchains.join("21.1.1")).unwrap();
+
+        let recorder = Arc::new(MigrationRecorder::default());
+        let client = ToolchainClient::new(&toolchains, dir.path().join("cache"))
+            .await
+            .unwrap()
+            .with_reporter(recorder.clone());
+        client
+            .migrate_from(std::slice::from_ref(&legacy))
+            .await
+            .unwrap();
+
+        assert_eq!(*recorder.0.lock().unwrap(), ["20.1.0"]);
+        assert_eq!(
+            std::fs::read_to_string(toolchains.join("20.1.0/bin/clang")).unwrap(),
+            "old"
+        );
+        assert!(!legacy.join("20.1.0").exists());
+        assert!(legacy.join("21.1.1").exists());
+
+        // Running again changes nothing, and the directory is kept since it isn't empty
+        client
+            .migrate_from(std::slice::from_ref(&legacy))
+            .await
+            .unwrap();
+        assert_eq!(recorder.0.lock().unwrap().len(), 1);
+        assert!(legacy.exists());
+    }
+
+    #[tokio::test]
+    async fn removes_emptied_legacy_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("llvm-toolchains");
+        std::fs::create_dir_all(legacy.join("20.1.0")).unwrap();
+        let client = ToolchainClient::new(dir.path().join("toolchains"), dir.path().join("cache"))
+            .await
+            .unwrap();
+
+        // Directories which don't exist are skipped
+        let missing = dir.path().join("missing");
+        client
+            .migrate_from(&[missing, legacy.clone()])
+            .await
+            .unwrap();
+
+        assert!(!legacy.exists());
+        assert_eq!(
+            client.installed_versions().await.unwrap(),
+            [ToolchainVersion::named("20.1.0").unwrap()]
+        );
+    }
+
+    #[test]
+    fn parses_versions_in_repo() {
+        let repo = ToolchainRepo {
+            owner: "example".to_string(),
+            name: "llvm".to_string(),
+            tag_prefix: "llvmorg-".to_string(),
+            tag_suffix: "-v5".to_string(),
+        };
+        for input in ["21.1.1", "v21.1.1", "llvmorg-21.1.1-v5"] {
+            assert_eq!(
+                repo.parse_version(input).unwrap(),
+                ToolchainVersion::named("21.1.1").unwrap(),
+                "{input}"
+            );
+        }
+        // A tag from the official repository isn't a version in this one
+        assert!(repo.parse_version("release-21.1.1-ATfE").is_err());
+
+        assert_eq!(
+            ToolchainVersion::parse("release-21.1.0-rc1-ATfE").unwrap(),
+            ToolchainVersion::named("21.1.0-rc1").unwrap()
+        );
+        for input in ["21", "21.1.x", "21.1.1-rc", "latest"] {
+            assert!(ToolchainVersion::parse(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn suggests_similar_versions() {
+        let repo = ToolchainRepo::default();
+        let tags = [
+            "release-21.1.1-ATfE",
+            "release-21.1.0-ATfE",
+            "release-20.1.0-ATfE",
+            "release-19.1.5-ATfE",
+            "release-21.1.1-other",
+            "nightly",
+        ];
+        let names = |version: &str| -> Vec<String> {
+            similar_versions(&repo, &ToolchainVersion::named(version).unwrap(), tags)
+                .into_iter()
+                .map(|version| version.name)
+                .collect()
+        };
+
+        assert_eq!(names("21.1.2"), ["21.1.1", "21.1.0"]);
+        // Tags which aren't toolchain releases are never suggested
+        assert!(
+            names("21.1.1-other")
+                .iter()
+                .all(|name| name != "21.1.1-other")
+        );
+        assert!(names("5").is_empty());
+
+        let many = [
+            "release-21.1.1-ATfE",
+            "release-21.1.3-ATfE",
+            "release-21.1.4-ATfE",
+            "release-21.1.5-ATfE",
+        ];
+        let suggestions =
+            similar_versions(&repo, &ToolchainVersion::named("21.1.2").unwrap(), many);
+        assert_eq!(suggestions.len(), 3);
+    }
+}
@@ -0,0 +1,49 @@
+use clap::Args;
+
+use crate::{config::Config, msg};
+
+/// The global config key which holds the default version.
+const DEFAULT_KEY: &str = "toolchain.default";
+
+#[derive(Args, Debug, Default)]
+pub struct DefaultOpts {
+    /// The toolchain version to use when a project doesn't pin one, like `21.1.1`
+    pub version: Option<String>,
+    /// Clear the default, so the latest toolchain is used instead
+    #[arg(long, conflicts_with = "version")]
+    pub unset: bool,
+}
+
+/// Prints, sets or clears the toolchain version used by projects which don't pin one.
+pub async fn default(opts: &DefaultOpts, config: &Config) -> crate::Result<()> {
+    if opts.unset {
+        if crate::config::edit::set_global(DEFAULT_KEY, None).await? {
+            msg!("Removed", "the default toolchain version");
+        } else {
+            eprintln!("No default toolchain version is set");
+        }
+        return Ok(());
+    }
+
+    let Some(version) = &opts.version else {
+        match config
+            .global
+            .toolchain
+            .as_ref()
+            .and_then(|t| t.default.as_ref())
+        {
+            Some(version) => println!("{version}"),
+            None => eprintln!("No default toolchain version is set"),
+        }
+        return Ok(());
+    };
+
+    let version = config
+        .resolve(None)?
+        .toolchain_repo
+        .parse_version(version)?;
+    crate::config::edit::set_global(DEFAULT_KEY, Some(version.name.as_str().into())).await?;
+    msg!("Updated", "default toolchain version to {version}");
+
+    Ok(())
+}
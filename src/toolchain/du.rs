@@ -0,0 +1,168 @@
+use std::{cmp::Reverse, io::ErrorKind, path::PathBuf};
+
+use clap::Args;
+use indicatif::HumanBytes;
+use serde::Serialize;
+
+use crate::{
+    Error,
+    config::Config,
+    fs,
+    project::Project,
+    toolchain::dir_size,
+    ui::{self, OutputFormat},
+};
+
+#[derive(Args, Debug, Default)]
+pub struct DuOpts {
+    /// How to print the report
+    #[arg(long, value_enum, default_value_t)]
+    pub output: OutputFormat,
+}
+
+/// How much space swift-v5's toolchains and downloads take up.
+#[derive(Debug, Serialize)]
+struct DiskUsage {
+    toolchains: Vec<ToolchainUsage>,
+    downloads: Vec<DownloadUsage>,
+    total_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ToolchainUsage {
+    version: String,
+    path: PathBuf,
+    bytes: u64,
+    /// Whether this is the version the current project uses.
+    pinned: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct DownloadUsage {
+    name: String,
+    path: PathBuf,
+    bytes: u64,
+}
+
+/// Prints the size of each installed toolchain and downloaded archive, largest first.
+pub async fn du(opts: &DuOpts, config: &Config) -> crate::Result<()> {
+    let project = match Project::find().await {
+        Ok(project) => Some(project),
+        Err(Error::CannotFindProject { .. }) => None,
+        Err(err) => return Err(err),
+    };
+    let project_config = match &project {
+        Some(project) => project.config().await?,
+        None => None,
+    };
+    let config = config.resolve(project_config)?;
+    let toolchain = config.toolchain_client().await?;
+    let pinned = config
+        .llvm_version
+        .as_deref()
+        .map(|version| config.toolchain_repo.parse_version(version))
+        .transpose()?;
+
+    let mut toolchains = Vec::new();
+    for version in toolchain.installed_versions().await? {
+        let path = toolchain.install_path_for(&version)?;
+        toolchains.push(ToolchainUsage {
+            bytes: dir_size(&path).await?,
+            pinned: pinned.as_ref() == Some(&version),
+            version: version.name,
+            path,
+        });
+    }
+    toolchains.sort_by_key(|t| Reverse(t.bytes));
+
+    let mut downloads = Vec::new();
+    match fs::read_dir(toolchain.cache_path()).await {
+        Ok(mut read_dir) => {
+            while let Some(entry) = read_dir.next_entry().await? {
+                // Hidden files are locks rather than downloads
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                let metadata = entry.metadata().await?;
+                if metadata.is_file() && !hidden {
+                    downloads.push(DownloadUsage {
+                        name: entry.file_name().to_string_lossy().into_owned(),
+                        path: entry.path(),
+                        bytes: metadata.len(),
+                    });
+                }
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    downloads.sort_by_key(|d| Reverse(d.bytes));
+
+    let toolchains_bytes: u64 = toolchains.iter().map(|t| t.bytes).sum();
+    let downloads_bytes: u64 = downloads.iter().map(|d| d.bytes).sum();
+    let usage = DiskUsage {
+        toolchains,
+        downloads,
+        total_bytes: toolchains_bytes + downloads_bytes,
+    };
+
+    if opts.output == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&usage).expect("disk usage is serializable")
+        );
+        return Ok(());
+    }
+
+    let name_width = usage
+        .toolchains
+        .iter()
+        .map(|t| t.version.len() + 2)
+        .chain(usage.downloads.iter().map(|d| d.name.len()))
+        .chain(["TOOLCHAIN".len(), "DOWNLOAD".len()])
+        .max()
+        .unwrap_or_default();
+
+    println!(
+        "{}",
+        ui::paint(
+            format!("{:<name_width$} {:>10}", "TOOLCHAIN", "SIZE"),
+            ui::EMPHASIS
+        )
+    );
+    for toolchain in &usage.toolchains {
+        let marker = if toolchain.pinned { " *" } else { "" };
+        let name = format!("{}{marker}", toolchain.version);
+        println!(
+            "{name:<name_width$} {:>10}",
+            HumanBytes(toolchain.bytes).to_string()
+        );
+    }
+
+    println!();
+    println!(
+        "{}",
+        ui::paint(
+            format!("{:<name_width$} {:>10}", "DOWNLOAD", "SIZE"),
+            ui::EMPHASIS
+        )
+    );
+    for download in &usage.downloads {
+        println!(
+            "{:<name_width$} {:>10}",
+            download.name,
+            HumanBytes(download.bytes).to_string()
+        );
+    }
+
+    println!();
+    println!(
+        "Toolchains: {}, downloads: {}, total: {}",
+        HumanBytes(toolchains_bytes),
+        HumanBytes(downloads_bytes),
+        ui::paint(HumanBytes(usage.total_bytes), ui::EMPHASIS)
+    );
+    if usage.toolchains.iter().any(|t| t.pinned) {
+        println!("{}", ui::paint("* used by this project", ui::DIMMED));
+    }
+
+    Ok(())
+}
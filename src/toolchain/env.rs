@@ -0,0 +1,128 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+
+use crate::{config::Config, toolchain::which::active_toolchain};
+
+/// The variable which points at the active toolchain's root.
+const TOOLCHAIN_VAR: &str = "SWIFT_V5_TOOLCHAIN";
+
+#[derive(Args, Debug, Default)]
+pub struct EnvOpts {
+    /// The shell to print commands for, instead of the one in $SHELL
+    #[arg(long, value_enum)]
+    pub shell: Option<Shell>,
+    /// Print the toolchain's location as JSON instead of shell commands
+    #[arg(long, conflicts_with = "shell")]
+    pub json: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl Shell {
+    /// Guesses the user's shell from the `SHELL` environment variable.
+    pub fn detect() -> Self {
+        if cfg!(windows) {
+            return Shell::Powershell;
+        }
+
+        let shell = env::var_os("SHELL").map(PathBuf::from);
+        let name = shell
+            .as_deref()
+            .and_then(Path::file_name)
+            .and_then(|name| name.to_str());
+        match name {
+            Some("zsh") => Shell::Zsh,
+            Some("fish") => Shell::Fish,
+            Some("pwsh" | "powershell") => Shell::Powershell,
+            _ => Shell::Bash,
+        }
+    }
+
+    /// Formats a command which sets an environment variable.
+    fn set_var(self, name: &str, value: &str) -> String {
+        match self {
+            Shell::Bash | Shell::Zsh => format!("export {name}={}", sh_quote(value)),
+            Shell::Fish => format!("set -gx {name} {}", fish_quote(value)),
+            Shell::Powershell => format!("$env:{name} = {}", powershell_quote(value)),
+        }
+    }
+
+    /// Formats a command which adds a directory to the start of `PATH`.
+    fn prepend_path(self, dir: &str) -> String {
+        match self {
+            Shell::Bash | Shell::Zsh => format!("export PATH={}:\"$PATH\"", sh_quote(dir)),
+            Shell::Fish => format!("set -gx PATH {} $PATH", fish_quote(dir)),
+            Shell::Powershell => {
+                let separator = if cfg!(windows) { ';' } else { ':' };
+                format!(
+                    "$env:PATH = {} + $env:PATH",
+                    powershell_quote(&format!("{dir}{separator}"))
+                )
+            }
+        }
+    }
+}
+
+/// Where the active toolchain lives, as printed by `--json`.
+#[derive(Debug, Serialize)]
+struct ToolchainEnv {
+    /// The toolchain's version, or `None` for a project's local toolchain.
+    version: Option<String>,
+    toolchain: PathBuf,
+    bin: PathBuf,
+}
+
+/// Prints the commands which make the active toolchain's binaries available in a shell.
+pub async fn env(opts: &EnvOpts, config: &Config) -> crate::Result<()> {
+    let toolchain = active_toolchain(config).await?;
+    let root = std::path::absolute(toolchain.root()?)?;
+    let bin = root.join("bin");
+
+    if opts.json {
+        let env = ToolchainEnv {
+            version: toolchain.version().map(|version| version.name.clone()),
+            toolchain: root,
+            bin,
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&env).expect("toolchain env is serializable")
+        );
+        return Ok(());
+    }
+
+    let shell = opts.shell.unwrap_or_else(Shell::detect);
+    println!("{}", shell.prepend_path(&bin.display().to_string()));
+    println!(
+        "{}",
+        shell.set_var(TOOLCHAIN_VAR, &root.display().to_string())
+    );
+
+    Ok(())
+}
+
+/// Quotes a string for POSIX shells.
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quotes a string for fish, which allows escaping quotes inside single-quoted strings.
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+/// Quotes a string for PowerShell, where single-quoted strings are literal.
+fn powershell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
@@ -0,0 +1,239 @@
+//! Packaging installed toolchains into archives which can be imported on another computer,
+//! e.g. one without internet access.
+//!
+//! An export is an xz-compressed tarball containing a [`MANIFEST_NAME`] file followed by the
+//! toolchain's files under [`TOOLCHAIN_DIR`]. A `.sha256` file is written next to it.
+
+use std::{
+    env::consts,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use indicatif::ProgressBar;
+use liblzma::write::XzEncoder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::task::spawn_blocking;
+use walkdir::WalkDir;
+
+use crate::{
+    PROGRESS_STYLE_MSG,
+    config::Config,
+    fs, msg,
+    toolchain::{
+        InstallationState, ToolchainClient, ToolchainError, ToolchainVersion, dir_size,
+        metadata::InstallMetadata,
+    },
+    ui,
+};
+
+/// The name of the manifest at the root of an export.
+pub const MANIFEST_NAME: &str = "swiftv5-export.json";
+/// The directory inside an export which holds the toolchain's files.
+pub const TOOLCHAIN_DIR: &str = "toolchain";
+/// The extension of exported toolchains.
+pub const EXTENSION: &str = "swiftv5-toolchain.tar.xz";
+
+/// The current version of the export manifest's format.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Describes the toolchain inside an export.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub schema_version: u32,
+    pub version: ToolchainVersion,
+    /// The OS the toolchain runs on, as in [`std::env::consts::OS`].
+    pub os: String,
+    /// The architecture the toolchain runs on, as in [`std::env::consts::ARCH`].
+    pub arch: String,
+    pub exported_at: DateTime<Utc>,
+    /// The version of swift-v5 which made the export.
+    pub exported_by: String,
+    /// Where the toolchain was originally installed from, if that was recorded.
+    pub install: Option<InstallMetadata>,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportOpts {
+    /// The installed toolchain version to export, like `21.1.1`
+    pub version: String,
+    /// The directory to write the archive to
+    #[arg(long, short, value_name = "DIR", default_value = ".")]
+    pub output_dir: PathBuf,
+}
+
+/// Packages an installed toolchain into an archive in the output directory.
+pub async fn export(opts: &ExportOpts, config: &Config) -> crate::Result<PathBuf> {
+    let effective = config.resolve(None)?;
+    let version = effective.toolchain_repo.parse_version(&opts.version)?;
+    let toolchain = effective.toolchain_client().await?;
+    export_to(&toolchain, version, &opts.output_dir).await
+}
+
+/// Packages an installed toolchain into an archive in `output_dir`, with its checksum file.
+pub(super) async fn export_to(
+    toolchain: &ToolchainClient,
+    version: ToolchainVersion,
+    output_dir: &Path,
+) -> crate::Result<PathBuf> {
+    if toolchain.installation_state(&version) != InstallationState::Installed {
+        let mut installed = toolchain.installed_versions().await?;
+        installed.retain(|version| toolchain.version_is_installed(version));
+        return Err(ToolchainError::VersionNotInstalled { version, installed }.into());
+    }
+
+    let install_path = toolchain.install_path_for(&version)?;
+    let manifest = ExportManifest {
+        schema_version: SCHEMA_VERSION,
+        version: version.clone(),
+        os: consts::OS.to_string(),
+        arch: consts::ARCH.to_string(),
+        exported_at: Utc::now(),
+        exported_by: env!("CARGO_PKG_VERSION").to_string(),
+        install: toolchain.install_metadata(&version).await?,
+    };
+
+    let file_name = format!(
+        "{}-{}-{}.{EXTENSION}",
+        version.name,
+        consts::OS,
+        consts::ARCH
+    );
+    fs::create_dir_all(output_dir).await?;
+    let archive_path = output_dir.join(&file_name);
+
+    let progress = ui::add_progress(
+        ProgressBar::new(dir_size(&install_path).await?)
+            .with_style(PROGRESS_STYLE_MSG.clone())
+            .with_message("Compressing"),
+    );
+    let checksum = spawn_blocking({
+        let archive_path = archive_path.clone();
+        let progress = progress.clone();
+        move || write_archive(&archive_path, &install_path, &manifest, &progress)
+    })
+    .await
+    .unwrap()?;
+    progress.finish_and_clear();
+
+    fs::write(
+        output_dir.join(format!("{file_name}.sha256")),
+        format!("{checksum}  {file_name}\n"),
+    )
+    .await?;
+    msg!("Exported", "{version} to {}", archive_path.display());
+
+    Ok(archive_path)
+}
+
+/// Writes the export archive, returning its SHA256 checksum in hex.
+pub(super) fn write_archive(
+    archive_path: &Path,
+    install_path: &Path,
+    manifest: &ExportManifest,
+    progress: &ProgressBar,
+) -> io::Result<String> {
+    let file = HashingWriter {
+        inner: std::fs::File::create(archive_path)?,
+        hasher: Sha256::default(),
+    };
+    let mut builder = tar::Builder::new(XzEncoder::new(file, 6));
+    builder.follow_symlinks(false);
+
+    let manifest = serde_json::to_vec_pretty(manifest).expect("export manifest is serializable");
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    builder.append_data(&mut header, MANIFEST_NAME, manifest.as_slice())?;
+
+    for entry in WalkDir::new(install_path) {
+        let entry = entry?;
+        let relative = entry
+            .path()
+            .strip_prefix(install_path)
+            .expect("walked paths are inside the install path");
+        builder.append_path_with_name(entry.path(), Path::new(TOOLCHAIN_DIR).join(relative))?;
+
+        if entry.file_type().is_file() {
+            progress.inc(entry.metadata()?.len());
+        }
+    }
+
+    let mut file = builder.into_inner()?.finish()?;
+    file.flush()?;
+    file.inner.sync_all()?;
+    Ok(hex::encode(file.hasher.finalize()))
+}
+
+/// Hashes everything written through it.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toolchain::{ChecksumAlgorithm, parse_checksum_file};
+
+    #[tokio::test]
+    async fn writes_manifest_and_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("toolchains/21.1.1/bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("clang"), "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(bin.join("clang"), std::fs::Permissions::from_mode(0o755))
+                .unwrap();
+        }
+        let toolchain =
+            ToolchainClient::new(dir.path().join("toolchains"), dir.path().join("cache"))
+                .await
+                .unwrap();
+        let version = ToolchainVersion::named("21.1.1").unwrap();
+
+        let archive = export_to(&toolchain, version.clone(), &dir.path().join("out"))
+            .await
+            .unwrap();
+
+        let file_name = archive.file_name().unwrap().to_str().unwrap();
+        assert!(file_name.ends_with(EXTENSION));
+        let sidecar =
+            std::fs::read_to_string(archive.with_file_name(format!("{file_name}.sha256"))).unwrap();
+        let expected = parse_checksum_file(&sidecar, file_name, ChecksumAlgorithm::Sha256).unwrap();
+        let contents = std::fs::read(&archive).unwrap();
+        assert_eq!(expected, hex::encode(Sha256::digest(&contents)));
+
+        // The manifest comes first, so it can be read without unpacking the toolchain
+        let mut tar = tar::Archive::new(liblzma::read::XzDecoder::new(contents.as_slice()));
+        let mut entries = tar.entries().unwrap();
+        let manifest_entry = entries.next().unwrap().unwrap();
+        assert_eq!(manifest_entry.path().unwrap(), Path::new(MANIFEST_NAME));
+        let manifest: ExportManifest = serde_json::from_reader(manifest_entry).unwrap();
+        assert_eq!(manifest.version, version);
+        assert_eq!(manifest.os, consts::OS);
+        assert_eq!(manifest.install, None);
+        let paths = entries
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect::<Vec<_>>();
+        assert!(paths.contains(&Path::new(TOOLCHAIN_DIR).join("bin/clang")));
+    }
+}
@@ -2,20 +2,29 @@
 //! such as DMG, ZIP, and TAR.XZ.
 
 use std::{
-    io::BufReader,
+    collections::HashMap,
+    io::{BufReader, Read, Seek, SeekFrom},
+    num::NonZeroU32,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
 };
 
 use backon::{ConstantBuilder, Retryable};
-use liblzma::read::XzDecoder;
+use indicatif::ProgressBar;
+use liblzma::{read::XzDecoder, stream::MtStreamBuilder};
 use miette::Diagnostic;
 use tempfile::tempdir;
 use thiserror::Error;
 use tokio::{
     io::{self},
     runtime::Handle,
+    select,
     task::{JoinSet, spawn_blocking},
+    time::interval,
 };
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, instrument, trace};
@@ -54,6 +63,11 @@ pub enum ExtractError {
     #[diagnostic(code(swift_v5::toolchain::extract::contents_not_found))]
     ContentsNotFound,
 
+    #[error("The path {} is too long to extract to", path.display())]
+    #[diagnostic(code(swift_v5::toolchain::extract::path_too_long))]
+    #[diagnostic(help("set `toolchains-dir` in the global config to a shorter directory"))]
+    PathTooLong { path: PathBuf },
+
     #[error("Failed to read directory while extracting toolchain")]
     #[diagnostic(code(swift_v5::toolchain::extract::walk_directory_failed))]
     WalkDir(#[from] walkdir::Error),
@@ -67,29 +81,133 @@ pub enum ExtractError {
     Zip(#[from] ZipError),
 }
 
+/// The longest path Windows allows, even with the `\\?\` prefix.
+const MAX_EXTENDED_PATH_LEN: usize = 32_767;
+
+/// Converts an absolute path to an extended-length path on Windows, so files can be written
+/// past the usual 260 character limit. Other platforms don't have the limit, so the path is
+/// returned as-is.
+///
+/// LLVM's include directories are nested deeply enough to exceed the limit when the toolchain
+/// is installed under the user's AppData folder.
+pub fn long_path(path: &Path) -> io::Result<PathBuf> {
+    if !cfg!(windows) {
+        return Ok(path.to_owned());
+    }
+
+    // Extended-length paths aren't normalized by Windows, so they must be absolute and free
+    // of `.` and `..` components.
+    let path = std::path::absolute(path)?;
+    let path_str = path.as_os_str().to_string_lossy();
+    Ok(if path_str.starts_with(r"\\?\") {
+        path
+    } else if let Some(unc) = path_str.strip_prefix(r"\\") {
+        PathBuf::from(format!(r"\\?\UNC\{unc}"))
+    } else {
+        PathBuf::from(format!(r"\\?\{path_str}"))
+    })
+}
+
+/// Extracts a ZIP archive, unwrapping its root directory if it has one.
+///
+/// The progress bar's length is set to the size of the archive, and it follows how far into
+/// the archive the extraction has read.
 pub async fn extract_zip(
     zip_file: fs::File,
     destination: PathBuf,
+    progress_bar: &ProgressBar,
+    cancel_token: CancellationToken,
 ) -> Result<fs::File, ToolchainError> {
-    let mut reader = BufReader::new(zip_file.into_std().await);
+    let file = zip_file.into_std().await;
+    progress_bar.set_length(file.metadata()?.len());
+    progress_bar.set_message("Extracting toolchain...");
+
+    let position = Arc::new(AtomicU64::new(0));
+    let reader = Tracked {
+        inner: BufReader::new(file),
+        position: position.clone(),
+        cancel_token: cancel_token.clone(),
+    };
+    let task = spawn_blocking(move || {
+        let mut archive = zip::ZipArchive::new(reader).map_err(ExtractError::Zip)?;
+        check_path_lengths(&archive, &long_path(&destination)?)?;
+        archive
+            .extract_unwrapped_root_dir(destination, root_dir_common_filter)
+            .map_err(ExtractError::Zip)?;
+        Ok::<_, ToolchainError>(archive.into_inner())
+    });
+    tokio::pin!(task);
+
+    let mut ticker = interval(Duration::from_millis(100));
+    let result = loop {
+        select! {
+            result = &mut task => break result.unwrap(),
+            _ = ticker.tick() => progress_bar.set_position(position.load(Ordering::Relaxed)),
+        }
+    };
+    let reader = match result {
+        Ok(reader) => reader,
+        Err(_) if cancel_token.is_cancelled() => return Err(ToolchainError::Cancelled),
+        Err(error) => return Err(error),
+    };
+    progress_bar.set_position(position.load(Ordering::Relaxed));
+
+    Ok(reader.inner.into_inner().into())
+}
 
-    let file = spawn_blocking(move || {
-        let mut archive = zip::ZipArchive::new(&mut reader)?;
+/// Fails if any entry of `archive` would be extracted to a path longer than Windows allows.
+fn check_path_lengths<R: Read + Seek>(
+    archive: &zip::ZipArchive<R>,
+    destination: &Path,
+) -> Result<(), ToolchainError> {
+    let root_dir = archive
+        .root_dir(root_dir_common_filter)
+        .map_err(ExtractError::Zip)?;
+    for name in archive.file_names() {
+        let name = Path::new(name);
+        let relative_path = root_dir
+            .as_deref()
+            .and_then(|root_dir| name.strip_prefix(root_dir).ok())
+            .unwrap_or(name);
+        let path = destination.join(relative_path);
+        if path.as_os_str().len() > MAX_EXTENDED_PATH_LEN {
+            return Err(ExtractError::PathTooLong { path }.into());
+        }
+    }
+    Ok(())
+}
 
-        archive.extract_unwrapped_root_dir(destination, root_dir_common_filter)?;
+/// Reads an archive while recording how far into it the reader is, and stops reading once
+/// cancelled.
+struct Tracked<R> {
+    inner: R,
+    position: Arc<AtomicU64>,
+    cancel_token: CancellationToken,
+}
 
-        Ok::<_, ZipError>(reader.into_inner())
-    })
-    .await
-    .unwrap()
-    .map_err(ExtractError::Zip)?;
+impl<R: Read> Read for Tracked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cancel_token.is_cancelled() {
+            return Err(io::Error::other("cancelled"));
+        }
+        let len = self.inner.read(buf)?;
+        self.position.fetch_add(len as u64, Ordering::Relaxed);
+        Ok(len)
+    }
+}
 
-    Ok(file.into())
+impl<R: Seek> Seek for Tracked<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.inner.seek(pos)?;
+        self.position.store(position, Ordering::Relaxed);
+        Ok(position)
+    }
 }
 
 pub async fn extract_tar_xz(
     tar_xz_file: fs::File,
     destination: PathBuf,
+    threads: Option<NonZeroU32>,
     cancel_token: CancellationToken,
 ) -> Result<fs::File, ToolchainError> {
     let mut reader = BufReader::new(tar_xz_file.into_std().await);
@@ -104,13 +222,22 @@ pub async fn extract_tar_xz(
 
     let file = spawn_blocking({
         let temp_destination = temp_destination.clone();
+        let cancel_token = cancel_token.clone();
         move || {
-            let mut decompressor = XzDecoder::new(&mut reader);
-            let mut archive = tar::Archive::new(&mut decompressor);
-
-            archive.unpack(temp_destination.path())?;
+            let decompressor =
+                xz_decoder(Cancellable::new(&mut reader, cancel_token.clone()), threads)?;
+            let mut archive = tar::Archive::new(decompressor);
+
+            if let Err(err) = archive.unpack(temp_destination.path()) {
+                return Err(if cancel_token.is_cancelled() {
+                    ToolchainError::Cancelled
+                } else {
+                    err.into()
+                });
+            }
             debug!("Done unpacking");
-            Ok::<_, io::Error>(reader.into_inner())
+            drop(archive);
+            Ok(reader.into_inner())
         }
     })
     .await
@@ -124,6 +251,58 @@ pub async fn extract_tar_xz(
     Ok(file.into())
 }
 
+/// The most memory the xz decoder's threads may use together. Past this, fewer threads are
+/// used, down to decoding on one thread.
+const XZ_THREADING_MEMLIMIT: u64 = 1024 * 1024 * 1024;
+
+/// Creates an xz decoder which decodes the archive's blocks on `threads` threads, or one per
+/// core by default.
+///
+/// Only archives which were compressed in multiple blocks can be decoded in parallel; others
+/// are decoded on one thread.
+pub fn xz_decoder<R: Read>(reader: R, threads: Option<NonZeroU32>) -> io::Result<XzDecoder<R>> {
+    let threads = threads
+        .or_else(|| {
+            std::thread::available_parallelism()
+                .ok()
+                .and_then(|n| NonZeroU32::try_from(n).ok())
+        })
+        .map_or(1, NonZeroU32::get);
+    trace!(threads, "Creating xz decoder");
+
+    let stream = MtStreamBuilder::new()
+        .threads(threads)
+        .memlimit_threading(XZ_THREADING_MEMLIMIT)
+        .memlimit_stop(u64::MAX)
+        .decoder()?;
+    Ok(XzDecoder::new_stream(reader, stream))
+}
+
+/// A reader which fails once its token is cancelled, so blocking work that reads from it
+/// stops soon after.
+struct Cancellable<R> {
+    inner: R,
+    cancel_token: CancellationToken,
+}
+
+impl<R> Cancellable<R> {
+    fn new(inner: R, cancel_token: CancellationToken) -> Self {
+        Self {
+            inner,
+            cancel_token,
+        }
+    }
+}
+
+impl<R: Read> Read for Cancellable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cancel_token.is_cancelled() {
+            return Err(io::Error::other("cancelled"));
+        }
+        self.inner.read(buf)
+    }
+}
+
 async fn find_dir_contained_by(parent_dir: &Path) -> Result<PathBuf, ToolchainError> {
     let mut contents_path = None;
 
@@ -145,6 +324,7 @@ pub async fn mv(
     dst: &Path,
     cancel_token: CancellationToken,
 ) -> Result<(), ToolchainError> {
+    let dst = &long_path(dst)?;
     match fs::rename(src, dst).await {
         Ok(()) => Ok(()),
         // Moving from /tmp/ to /anywhere-else/ isn't possible with a simple fs::rename because
@@ -158,6 +338,23 @@ pub async fn mv(
     }
 }
 
+/// Identifies a file on disk, so paths which are hard links to the same file can be found.
+#[cfg(unix)]
+fn file_id(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Copies a folder, keeping its symlinks and the hard links between its files.
+///
+/// Only the first path to each hard-linked file is copied. The others are linked to that copy
+/// once every file has been copied, or copied as well if that fails.
 #[instrument(skip(cancel_token))]
 async fn copy_folder(
     source: &Path,
@@ -167,13 +364,18 @@ async fn copy_folder(
     debug!("Copying folder");
 
     let source = Arc::new(fs::canonicalize(source).await?);
-    let destination = Arc::new(destination);
+    let destination = Arc::new(long_path(&destination)?);
 
-    let mut tasks = spawn_blocking({
+    let (mut tasks, links) = spawn_blocking({
+        let cancel_token = cancel_token.clone();
         move || {
             let mut tasks = JoinSet::new();
+            // Where the first path to each hard-linked file was copied
+            let mut copied: HashMap<(u64, u64), PathBuf> = HashMap::new();
+            let mut links = Vec::new();
 
-            for entry in WalkDir::new(&*source) {
+            // Sorted, so the same path to a hard-linked file is always the one that's copied
+            for entry in WalkDir::new(&*source).sort_by_file_name() {
                 let entry = entry.map_err(ExtractError::WalkDir)?;
 
                 if cancel_token.is_cancelled() {
@@ -181,6 +383,19 @@ async fn copy_folder(
                     return Err(ToolchainError::Cancelled);
                 }
 
+                if entry.file_type().is_file() {
+                    let metadata = entry.metadata().map_err(ExtractError::from)?;
+                    if let Some(id) = file_id(&metadata) {
+                        let relative_path = entry.path().strip_prefix(&*source).unwrap();
+                        let destination_path = destination.join(relative_path);
+                        if let Some(original) = copied.get(&id) {
+                            links.push((entry.into_path(), original.clone(), destination_path));
+                            continue;
+                        }
+                        copied.insert(id, destination_path);
+                    }
+                }
+
                 let source = source.clone();
                 let destination = destination.clone();
                 let cancel_token = cancel_token.clone();
@@ -230,7 +445,7 @@ async fn copy_folder(
                 });
             }
 
-            Ok::<_, ToolchainError>(tasks)
+            Ok::<_, ToolchainError>((tasks, links))
         }
     })
     .await
@@ -240,5 +455,165 @@ async fn copy_folder(
         result.unwrap()?;
     }
 
+    for (source_path, original, destination_path) in links {
+        cancel_token.check_cancellation(ToolchainError::Cancelled)?;
+        trace!(?original, ?destination_path, "Creating hard link");
+        if let Err(error) = fs::hard_link(&original, &destination_path).await {
+            debug!(%error, ?destination_path, "Couldn't create hard link, copying instead");
+            fs::copy(&source_path, &destination_path).await?;
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a ZIP archive with the given files, where files starting with `->` are symlinks
+    /// to the rest of their contents.
+    async fn zip(dir: &Path, files: &[(&str, &str)]) -> fs::File {
+        use std::io::Write;
+
+        let path = dir.join("archive.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, contents) in files {
+            if let Some(target) = contents.strip_prefix("->") {
+                zip.add_symlink(*name, target, options).unwrap();
+            } else {
+                zip.start_file(*name, options).unwrap();
+                zip.write_all(contents.as_bytes()).unwrap();
+            }
+        }
+        zip.finish().unwrap();
+        fs::File::open(path).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn extracts_zip_root_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = zip(
+            dir.path(),
+            &[("ATfE/bin/clang", "clang"), ("ATfE/lib/libc.a", "libc")],
+        )
+        .await;
+        let len = archive.metadata().await.unwrap().len();
+        let destination = dir.path().join("destination");
+        let progress_bar = ProgressBar::hidden();
+
+        extract_zip(
+            archive,
+            destination.clone(),
+            &progress_bar,
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(destination.join("bin/clang")).unwrap(),
+            "clang"
+        );
+        assert!(destination.join("lib/libc.a").is_file());
+        assert_eq!(progress_bar.length(), Some(len));
+        assert!(progress_bar.position() > 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn zip_cant_write_through_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = dir.path().join("outside");
+        std::fs::create_dir(&outside).unwrap();
+        let archive = zip(
+            dir.path(),
+            &[
+                ("ATfE/bin/clang", "clang"),
+                ("ATfE/escape", "->../../outside"),
+                ("ATfE/escape/evil", "evil"),
+            ],
+        )
+        .await;
+
+        let result = extract_zip(
+            archive,
+            dir.path().join("toolchains/destination"),
+            &ProgressBar::hidden(),
+            CancellationToken::new(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(!outside.join("evil").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn copies_hard_links_as_links() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        std::fs::create_dir_all(source.join("bin")).unwrap();
+        std::fs::write(source.join("bin/clang"), "clang").unwrap();
+        std::fs::hard_link(source.join("bin/clang"), source.join("bin/clang++")).unwrap();
+        std::fs::write(source.join("bin/ld.lld"), "lld").unwrap();
+
+        let destination = dir.path().join("destination");
+        copy_folder(&source, destination.clone(), CancellationToken::new())
+            .await
+            .unwrap();
+
+        let inode = |name: &str| std::fs::metadata(destination.join(name)).unwrap().ino();
+        assert_eq!(inode("bin/clang"), inode("bin/clang++"));
+        assert_ne!(inode("bin/clang"), inode("bin/ld.lld"));
+        // The copies aren't linked to the originals
+        assert_ne!(
+            inode("bin/clang"),
+            std::fs::metadata(source.join("bin/clang")).unwrap().ino()
+        );
+        assert_eq!(
+            std::fs::read_to_string(destination.join("bin/clang++")).unwrap(),
+            "clang"
+        );
+        assert_eq!(
+            std::fs::read_to_string(destination.join("bin/ld.lld")).unwrap(),
+            "lld"
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn long_paths_are_unchanged() {
+        let path = Path::new("relative/../toolchains/21.1.1");
+        assert_eq!(long_path(path).unwrap(), path);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_paths() {
+        assert_eq!(
+            long_path(Path::new(r"C:\Users\me\toolchains\21.1.1")).unwrap(),
+            Path::new(r"\\?\C:\Users\me\toolchains\21.1.1")
+        );
+        // `..` isn't resolved in extended-length paths, so it's removed first
+        assert_eq!(
+            long_path(Path::new(r"C:\Users\me\..\toolchains")).unwrap(),
+            Path::new(r"\\?\C:\Users\toolchains")
+        );
+        assert_eq!(
+            long_path(Path::new(r"\\server\share\toolchains")).unwrap(),
+            Path::new(r"\\?\UNC\server\share\toolchains")
+        );
+        assert_eq!(
+            long_path(Path::new(r"\\?\C:\toolchains")).unwrap(),
+            Path::new(r"\\?\C:\toolchains")
+        );
+
+        let relative = long_path(Path::new("toolchains")).unwrap();
+        assert!(relative.to_string_lossy().starts_with(r"\\?\"));
+        assert!(relative.ends_with("toolchains"));
+    }
+}
@@ -0,0 +1,345 @@
+//! Installing toolchains from archives made by [`export`](super::export).
+
+use std::{
+    env::consts,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::Utc;
+use clap::Args;
+use indicatif::ProgressBar;
+use liblzma::read::XzDecoder;
+use tokio::task::spawn_blocking;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+use url::Url;
+
+use crate::{
+    PROGRESS_STYLE_MSG, PROGRESS_STYLE_SPINNER,
+    config::Config,
+    confirm, fs, msg,
+    toolchain::{
+        ChecksumAlgorithm, InstallationState, ToolchainClient, ToolchainError,
+        calculate_file_checksum, delete_path,
+        export::{ExportManifest, MANIFEST_NAME, TOOLCHAIN_DIR},
+        extract,
+        metadata::{self, InstallMetadata},
+        parse_checksum_file,
+    },
+    ui,
+};
+
+#[derive(Args, Debug)]
+pub struct ImportOpts {
+    /// The archive to import, as written by `toolchain export`
+    pub file: PathBuf,
+    /// Import the toolchain even if it was exported on a different OS or architecture
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// Installs a toolchain from an exported archive, after checking that it was made for this
+/// computer.
+pub async fn import(opts: &ImportOpts, config: &Config) -> crate::Result<()> {
+    let config = config.resolve(None)?;
+    let toolchain = config.toolchain_client().await?;
+    import_with(&toolchain, opts, config.assume_yes).await
+}
+
+/// Installs a toolchain from an exported archive with the given client.
+async fn import_with(
+    toolchain: &ToolchainClient,
+    opts: &ImportOpts,
+    assume_yes: bool,
+) -> crate::Result<()> {
+    let file_name = opts
+        .file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let archive = fs::File::open(&opts.file).await?;
+    let cancel_token = CancellationToken::new();
+    let progress_bar = ui::add_progress(
+        ProgressBar::new(0)
+            .with_style(PROGRESS_STYLE_MSG.clone())
+            .with_message("Verifying"),
+    );
+    let checksum = calculate_file_checksum(
+        &archive,
+        ChecksumAlgorithm::Sha256,
+        &progress_bar,
+        &cancel_token,
+    )
+    .await?;
+    let checksum_path = opts.file.with_file_name(format!("{file_name}.sha256"));
+    let checksum_verified = match fs::read_to_string(&checksum_path).await {
+        Ok(contents) => {
+            let expected = parse_checksum_file(&contents, &file_name, ChecksumAlgorithm::Sha256)?;
+            if !expected.eq_ignore_ascii_case(&checksum) {
+                return Err(ToolchainError::ChecksumMismatch {
+                    algorithm: ChecksumAlgorithm::Sha256,
+                    expected,
+                    actual: checksum,
+                }
+                .into());
+            }
+            true
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            eprintln!(
+                "{:>12} {} has no checksum file next to it, so it can't be verified",
+                ui::paint("Warning", ui::WARNING),
+                file_name
+            );
+            false
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let manifest = read_manifest(&opts.file).await?;
+    debug!(?manifest, "Read export manifest");
+    if (manifest.os != consts::OS || manifest.arch != consts::ARCH) && !opts.force {
+        return Err(ToolchainError::ImportHostMismatch {
+            os: manifest.os,
+            arch: manifest.arch,
+        }
+        .into());
+    }
+
+    let version = manifest.version;
+    let install_path = toolchain.install_path_for(&version)?;
+    let _lock = toolchain
+        .lock_version(&version, true, &cancel_token)
+        .await?;
+    if toolchain.installation_state(&version) == InstallationState::Installed {
+        let confirmation = confirm(
+            &format!("LLVM toolchain {version} is already installed. Replace it?"),
+            &format!("Deletes {}.", install_path.display()),
+            assume_yes,
+        )?;
+        if !confirmation {
+            return Ok(());
+        }
+    }
+
+    let spinner = ui::add_progress(
+        ProgressBar::new_spinner()
+            .with_message("Extracting toolchain... (this may take a few minutes)")
+            .with_style(PROGRESS_STYLE_SPINNER.clone()),
+    );
+    spinner.enable_steady_tick(Duration::from_millis(300));
+
+    // Unpack next to the final location so the toolchain can be renamed into place, rather than
+    // copied across filesystems.
+    fs::create_dir_all(toolchain.toolchains_path()).await?;
+    let staging = tempfile::tempdir_in(toolchain.toolchains_path())?;
+    spawn_blocking({
+        let archive_path = opts.file.clone();
+        let staging = staging.path().to_owned();
+        move || {
+            let reader = BufReader::new(std::fs::File::open(archive_path)?);
+            tar::Archive::new(extract::xz_decoder(reader, None)?).unpack(staging)
+        }
+    })
+    .await
+    .unwrap()?;
+
+    let contents = staging.path().join(TOOLCHAIN_DIR);
+    if !contents.is_dir() {
+        return Err(ToolchainError::NotAnExport {
+            path: opts.file.clone(),
+        }
+        .into());
+    }
+    if install_path.exists() {
+        debug!("Destination folder already exists, removing it");
+        delete_path(&install_path, toolchain.delete_mode()).await?;
+    }
+    extract::mv(&contents, &install_path, cancel_token).await?;
+    spinner.finish_and_clear();
+
+    let asset_url = Url::from_file_path(std::path::absolute(&opts.file)?)
+        .expect("absolute paths are valid file URLs");
+    let metadata = InstallMetadata {
+        schema_version: metadata::SCHEMA_VERSION,
+        version: version.clone(),
+        asset_name: file_name,
+        asset_url,
+        checksum_algorithm: ChecksumAlgorithm::Sha256,
+        checksum,
+        checksum_verified,
+        installed_at: Utc::now(),
+        installed_by: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    metadata.write(&install_path).await?;
+
+    msg!("Imported", "{version} from {}", opts.file.display());
+    Ok(())
+}
+
+/// Reads the manifest at the start of an exported archive, without unpacking the rest.
+async fn read_manifest(path: &Path) -> Result<ExportManifest, ToolchainError> {
+    let not_an_export = || ToolchainError::NotAnExport {
+        path: path.to_owned(),
+    };
+
+    let path = path.to_owned();
+    let manifest = spawn_blocking(move || {
+        let reader = BufReader::new(std::fs::File::open(path)?);
+        let mut archive = tar::Archive::new(XzDecoder::new(reader));
+        let Some(entry) = archive.entries()?.next() else {
+            return Ok(None);
+        };
+        let entry = entry?;
+        if entry.path()?.as_os_str() != MANIFEST_NAME {
+            return Ok(None);
+        }
+        Ok::<_, io::Error>(serde_json::from_reader::<_, ExportManifest>(entry).ok())
+    })
+    .await
+    .unwrap();
+
+    match manifest {
+        Ok(Some(manifest)) => Ok(manifest),
+        Ok(None) => Err(not_an_export()),
+        // Anything which isn't an xz-compressed tarball
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Err(not_an_export()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Error,
+        toolchain::{
+            ToolchainVersion,
+            export::{SCHEMA_VERSION, export_to, write_archive},
+        },
+    };
+
+    /// A client with its own toolchains directory, and an installed toolchain if `installed`
+    /// is given.
+    async fn client(dir: &Path, installed: Option<&str>) -> ToolchainClient {
+        if let Some(name) = installed {
+            let bin = dir.join("toolchains").join(name).join("bin");
+            std::fs::create_dir_all(&bin).unwrap();
+            std::fs::write(bin.join("clang"), "#!/bin/sh\n").unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(bin.join("clang"), std::fs::Permissions::from_mode(0o755))
+                    .unwrap();
+            }
+        }
+        ToolchainClient::new(dir.join("toolchains"), dir.join("cache"))
+            .await
+            .unwrap()
+    }
+
+    fn import_opts(file: PathBuf) -> ImportOpts {
+        ImportOpts { file, force: false }
+    }
+
+    #[tokio::test]
+    async fn imports_exported_toolchain() {
+        let dir = tempfile::tempdir().unwrap();
+        let version = ToolchainVersion::named("21.1.1").unwrap();
+        let exporter = client(&dir.path().join("exporter"), Some("21.1.1")).await;
+        let archive = export_to(&exporter, version.clone(), &dir.path().join("out"))
+            .await
+            .unwrap();
+
+        let importer = client(&dir.path().join("importer"), None).await;
+        import_with(&importer, &import_opts(archive), false)
+            .await
+            .unwrap();
+
+        assert!(importer.version_is_installed(&version));
+        let install_path = importer.install_path_for(&version).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(install_path.join("bin/clang")).unwrap(),
+            "#!/bin/sh\n"
+        );
+        let metadata = importer.install_metadata(&version).await.unwrap().unwrap();
+        assert!(metadata.checksum_verified);
+        // The staging directory is gone once the toolchain is moved into place
+        let mut entries = std::fs::read_dir(importer.toolchains_path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| !name.ends_with(".lock"))
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(entries, ["21.1.1"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let exporter = client(&dir.path().join("exporter"), Some("21.1.1")).await;
+        let archive = export_to(
+            &exporter,
+            ToolchainVersion::named("21.1.1").unwrap(),
+            &dir.path().join("out"),
+        )
+        .await
+        .unwrap();
+        let file_name = archive.file_name().unwrap().to_str().unwrap();
+        std::fs::write(
+            archive.with_file_name(format!("{file_name}.sha256")),
+            format!("{}  {file_name}\n", "0".repeat(64)),
+        )
+        .unwrap();
+
+        let importer = client(&dir.path().join("importer"), None).await;
+        let result = import_with(&importer, &import_opts(archive), false).await;
+
+        assert!(matches!(
+            result,
+            Err(Error::Toolchain(ToolchainError::ChecksumMismatch { .. }))
+        ));
+        assert!(!importer.toolchains_path().join("21.1.1").exists());
+    }
+
+    #[tokio::test]
+    async fn rejects_other_hosts() {
+        let dir = tempfile::tempdir().unwrap();
+        let install_path = dir.path().join("exporter/toolchains/21.1.1");
+        client(&dir.path().join("exporter"), Some("21.1.1")).await;
+        let manifest = ExportManifest {
+            schema_version: SCHEMA_VERSION,
+            version: ToolchainVersion::named("21.1.1").unwrap(),
+            os: "plan9".to_string(),
+            arch: "mips".to_string(),
+            exported_at: Utc::now(),
+            exported_by: env!("CARGO_PKG_VERSION").to_string(),
+            install: None,
+        };
+        let archive = dir
+            .path()
+            .join("21.1.1-plan9-mips.swiftv5-toolchain.tar.xz");
+        write_archive(&archive, &install_path, &manifest, &ProgressBar::hidden()).unwrap();
+
+        let importer = client(&dir.path().join("importer"), None).await;
+        let result = import_with(&importer, &import_opts(archive.clone()), false).await;
+
+        assert!(matches!(
+            result,
+            Err(Error::Toolchain(ToolchainError::ImportHostMismatch { os, arch }))
+                if os == "plan9" && arch == "mips"
+        ));
+        assert!(!importer.toolchains_path().join("21.1.1").exists());
+
+        // Unless it's forced
+        let opts = ImportOpts {
+            file: archive,
+            force: true,
+        };
+        import_with(&importer, &opts, false).await.unwrap();
+        assert!(importer.toolchains_path().join("21.1.1/bin/clang").exists());
+    }
+}
@@ -1,78 +1,671 @@
-use std::process::exit;
+use std::{
+    fmt::{self, Display},
+    io::{self, IsTerminal},
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    msg,
+    Error,
+    config::{Config, ConfigSource, EffectiveConfig, edit},
+    confirm, msg,
     project::Project,
-    toolchain::{HostArch, HostOS, ToolchainClient, ToolchainVersion},
+    toolchain::{
+        ArchiveFormat, DeleteMode, HostArch, HostOS, InstallationState, ToolchainAsset,
+        ToolchainClient, ToolchainError, ToolchainRelease, ToolchainRepo, ToolchainVersion,
+    },
+    ui,
 };
-use inquire::Confirm;
-use owo_colors::OwoColorize;
+use chrono::{DateTime, Utc};
+use clap::{ArgGroup, Args};
+use indicatif::{HumanBytes, ProgressBar, ProgressDrawTarget};
+use inquire::{InquireError, Select};
 use tokio_util::sync::CancellationToken;
+use tracing::debug;
+use url::Url;
 
-pub async fn install(force: bool) -> crate::Result<()> {
-    let project = Project::find().await?;
-    let toolchain = ToolchainClient::using_data_dir().await?;
-
-    let toolchain_release;
-    let confirm_message;
-    let toolchain_version;
-    if let Some(config) = project.config().await? {
-        toolchain_version = ToolchainVersion::named(&config.llvm_version);
-        toolchain_release = toolchain.get_release(&toolchain_version).await?;
-        confirm_message = format!("Download & install LLVM toolchain {toolchain_version}?");
-    } else {
-        toolchain_release = toolchain.latest_release().await?;
-        toolchain_version = toolchain_release.version().to_owned();
-        confirm_message =
-            format!("Download & install latest LLVM toolchain ({toolchain_version})?");
-    }
-
-    if !force {
-        let already_installed = toolchain.install_path_for(&toolchain_version);
-        if already_installed.exists() {
-            println!(
-                "Toolchain up-to-date: {} at {}",
-                toolchain_version.to_string().bold(),
-                already_installed.display().green()
-            );
-            return Ok(());
+#[derive(Args, Debug, Default)]
+#[command(group(ArgGroup::new("chosen_version").args(["toolchain_version", "select"])))]
+pub struct InstallOpts {
+    /// Force re-installation of the toolchain, even if it is already installed
+    #[arg(long)]
+    pub force: bool,
+    /// Fail if the toolchain's checksum file is missing, instead of installing it unverified
+    #[arg(long)]
+    pub require_checksum: bool,
+    /// Delete a replaced installation permanently instead of moving it to the trash
+    #[arg(long)]
+    pub no_trash: bool,
+    /// Allow installing a prerelease toolchain when no version is pinned in v5.toml
+    #[arg(long)]
+    pub include_prereleases: bool,
+    /// Install the latest toolchain, ignoring any pinned version. Works outside of a project
+    #[arg(long)]
+    pub latest: bool,
+    /// Install this version instead of the one pinned in v5.toml. Works outside of a project
+    #[arg(long, value_name = "VERSION", conflicts_with = "latest")]
+    pub toolchain_version: Option<String>,
+    /// Choose the version to install from a list of releases. Works outside of a project
+    #[arg(long, conflicts_with_all = ["latest", "toolchain_version"])]
+    pub select: bool,
+    /// Pin the installed version in v5.toml
+    #[arg(long, requires = "chosen_version")]
+    pub save: bool,
+    /// The archive format to download, if the release has one, instead of the host's usual choice
+    #[arg(long, value_enum)]
+    pub format: Option<ArchiveFormat>,
+    /// Install from a fork of the toolchain's GitHub repository
+    #[arg(long, value_name = "OWNER/NAME")]
+    pub repo: Option<ToolchainRepo>,
+    /// How many threads to decompress the toolchain with, instead of one per core. Fewer threads
+    /// use less memory
+    #[arg(long, value_name = "N")]
+    pub extract_threads: Option<NonZeroU32>,
+    /// Fail instead of waiting if another swift-v5 process is installing the same toolchain
+    #[arg(long)]
+    pub no_wait: bool,
+}
+
+impl InstallOpts {
+    /// Whether the toolchain to install depends on the project, or the project is edited.
+    ///
+    /// `--latest`, `--toolchain-version` and `--select` pick the version without a project, unless
+    /// it's being saved with `--save`.
+    pub fn uses_project(&self) -> bool {
+        let chosen = self.latest || self.toolchain_version.is_some() || self.select;
+        self.save || !chosen
+    }
+}
+
+/// What [`ToolchainClient::install_version`] did.
+#[derive(Debug, Clone)]
+pub struct InstallOutcome {
+    /// The toolchain version which was selected.
+    pub version: ToolchainVersion,
+    /// Where the toolchain is installed, or would have been if the install was cancelled.
+    pub path: PathBuf,
+    pub action: InstallAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallAction {
+    /// The toolchain was already installed, so nothing was done.
+    AlreadyInstalled,
+    /// The toolchain was downloaded and installed.
+    Downloaded {
+        /// The number of bytes transferred, which excludes any resumed part of the download.
+        bytes: u64,
+        duration: Duration,
+    },
+    /// The user declined to install the toolchain.
+    Cancelled,
+}
+
+/// Which toolchain [`ToolchainClient::install_version`] should install.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionRequest {
+    /// The newest release in the toolchain repository.
+    Latest { include_prereleases: bool },
+    /// A specific release.
+    Exact(ToolchainVersion),
+}
+
+impl Default for VersionRequest {
+    fn default() -> Self {
+        Self::Latest {
+            include_prereleases: false,
         }
     }
+}
+
+/// Settings for [`ToolchainClient::install_version`].
+#[derive(Clone, Default)]
+pub struct InstallOptions {
+    pub version: VersionRequest,
+    /// Reinstall the toolchain even if it's already installed, discarding any cached download.
+    pub force: bool,
+    /// Stops the install when cancelled, which makes it fail with
+    /// [`ToolchainError::Cancelled`](super::ToolchainError::Cancelled).
+    pub cancel_token: CancellationToken,
+    /// Fail with [`ToolchainError::InstallInProgress`](super::ToolchainError::InstallInProgress)
+    /// if another process is installing the same version, instead of waiting for it.
+    pub no_wait: bool,
+    /// The archive formats to accept, best first, instead of the client's preference.
+    pub formats: Option<Vec<ArchiveFormat>>,
+    /// The host architectures to accept, best first, instead of [`HostArch::current`].
+    pub arches: Option<Vec<HostArch>>,
+    /// Receives updates as the install runs. Without one, nothing is shown.
+    pub reporter: Option<Arc<dyn InstallReporter>>,
+}
+
+/// What [`ToolchainClient::install_version`] is about to download.
+#[derive(Clone, Copy)]
+pub struct InstallPlan<'a> {
+    pub release: &'a ToolchainRelease,
+    pub asset: &'a ToolchainAsset,
+    /// Where the archive will be downloaded from, which may be a mirror.
+    pub url: &'a Url,
+    /// How much of the archive is already cached from an interrupted download.
+    pub downloaded: u64,
+}
+
+/// Receives updates from [`ToolchainClient::install_version`].
+///
+/// Every method does nothing by default, and progress bars are hidden.
+pub trait InstallReporter: Send + Sync {
+    /// Decides whether to go ahead once the release and archive are chosen, before anything
+    /// is downloaded. Returning `false` cancels the install.
+    fn confirm(&self, _plan: &InstallPlan<'_>) -> crate::Result<bool> {
+        Ok(true)
+    }
+
+    /// Called before an interrupted install is repaired.
+    fn repairing(&self, _version: &ToolchainVersion) {}
 
-    let confirmation = Confirm::new(&confirm_message)
-        .with_default(true)
-        .with_help_message("Required support libraries for Embedded Swift. No = cancel")
-        .prompt()?;
+    /// Called when a cached download is discarded because the install was forced.
+    fn evicted(&self, _bytes: u64) {}
 
-    if !confirmation {
-        eprintln!("Cancelled.");
-        exit(1);
+    /// Called when a downloaded asset didn't match its checksum and is being downloaded again.
+    fn retrying(&self, _asset: &ToolchainAsset) {}
+
+    /// Called when the download starts.
+    fn downloading(&self, _plan: &InstallPlan<'_>) {}
+
+    /// Called when an asset is installed without verifying it, since its release has no
+    /// checksum file.
+    fn unverified(&self, _asset: &ToolchainAsset) {}
+
+    /// Called when the toolchain `name` was moved from the legacy toolchains directory `from`,
+    /// see [`ToolchainClient::migrate_from`].
+    fn migrated(&self, _name: &str, _from: &Path) {}
+
+    /// Sets up one of the install's progress bars before it's first drawn.
+    fn progress_bar(&self, bar: ProgressBar) -> ProgressBar {
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+        bar
     }
+}
 
-    let asset = toolchain_release.asset_for(HostOS::current(), HostArch::current())?;
+/// Reports nothing.
+struct SilentReporter;
 
-    msg!(
-        "Downloading",
-        "{} <{}>",
-        asset.name.bold(),
-        asset.browser_download_url.green()
-    );
+impl InstallReporter for SilentReporter {}
 
-    let cancel_token = CancellationToken::new();
+impl ToolchainClient {
+    /// Resolves, downloads, verifies and extracts a toolchain.
+    ///
+    /// Nothing is printed or asked; everything goes through the options' reporter instead.
+    pub async fn install_version(&self, options: InstallOptions) -> crate::Result<InstallOutcome> {
+        let InstallOptions {
+            version,
+            force,
+            cancel_token,
+            no_wait,
+            formats,
+            arches,
+            reporter,
+        } = options;
+        let reporter = reporter.unwrap_or_else(|| Arc::new(SilentReporter));
+        let mut client = self.clone();
+        client.reporter = Some(reporter.clone());
+
+        let release = match &version {
+            VersionRequest::Exact(version) => client.get_release(version).await?,
+            VersionRequest::Latest {
+                include_prereleases,
+            } => client.latest_release(*include_prereleases).await?,
+        };
+        let version = release.version().clone();
+        let path = client.install_path_for(&version)?;
 
-    tokio::spawn({
-        let cancel_token = cancel_token.clone();
-        async move {
-            tokio::signal::ctrl_c().await.unwrap();
-            cancel_token.cancel();
+        // Held until the install is over, so two processes don't extract over each other. Taken
+        // before looking at what's installed, since whoever held it may have just installed it.
+        let _lock = client
+            .lock_version(&version, !no_wait, &cancel_token)
+            .await?;
+
+        match client.installation_state(&version) {
+            InstallationState::Installed if !force => {
+                return Ok(InstallOutcome {
+                    version,
+                    path,
+                    action: InstallAction::AlreadyInstalled,
+                });
+            }
+            InstallationState::Partial => reporter.repairing(&version),
+            _ => {}
+        }
+
+        let formats = formats.unwrap_or_else(|| client.format_preference(HostOS::current()));
+        let arches = arches.unwrap_or_else(|| HostArch::current().to_vec());
+        let asset = release.asset_for(HostOS::current(), &arches, &formats)?;
+
+        // A forced reinstall is often because of a bad download, which shouldn't be resumed.
+        if force {
+            let evicted = client.evict_cached(&asset.name).await?;
+            if evicted > 0 {
+                reporter.evicted(evicted);
+            }
+        }
+
+        // Part of the archive may already be cached from an interrupted download.
+        let downloaded = client.downloaded_len(&asset).await?;
+        let url = client.download_url(&asset)?;
+        let plan = InstallPlan {
+            release: &release,
+            asset: &asset,
+            url: &url,
+            downloaded,
+        };
+        if !reporter.confirm(&plan)? {
+            return Ok(InstallOutcome {
+                version,
+                path,
+                action: InstallAction::Cancelled,
+            });
+        }
+        reporter.downloading(&plan);
+
+        let started = Instant::now();
+        let path = client
+            .download_and_install(&release, &asset, cancel_token)
+            .await?;
+
+        Ok(InstallOutcome {
+            version,
+            path,
+            action: InstallAction::Downloaded {
+                bytes: asset.size - downloaded,
+                duration: started.elapsed(),
+            },
+        })
+    }
+}
+
+/// Installs the toolchain the project uses, or the latest one if it doesn't pin a version.
+///
+/// With `--latest`, `--toolchain-version` or `--select`, no project is needed, and that toolchain
+/// is installed regardless.
+///
+/// This is the interactive front end to [`ToolchainClient::install_version`].
+///
+/// Returns `None` without installing anything if the project uses a local toolchain, or if the
+/// user backed out of choosing a version.
+pub async fn install(opts: &InstallOpts, config: &Config) -> crate::Result<Option<InstallOutcome>> {
+    let project = if opts.uses_project() {
+        Some(Project::find().await?)
+    } else {
+        None
+    };
+    let project_config = match &project {
+        Some(project) => project.config().await?,
+        None => None,
+    };
+    let config = config.resolve(project_config)?;
+    let chosen = opts.toolchain_version.is_some() || opts.select;
+    if let (Some(project), Some(llvm_path), false) = (&project, &config.llvm_path, chosen) {
+        msg!(
+            "Skipping",
+            "install, since {} uses the local toolchain at {}",
+            project.config_path().display(),
+            ui::paint(project.path().join(llvm_path).display(), ui::LOCATION)
+        );
+        return Ok(None);
+    }
+
+    let version = if opts.select {
+        let toolchain = terminal_client(opts, &config).await?;
+        let Some(version) = select_version(&toolchain, config.assume_yes).await? else {
             eprintln!("Cancelled.");
+            return Ok(None);
+        };
+        VersionRequest::Exact(version)
+    } else if let Some(version) = &opts.toolchain_version {
+        debug!(
+            version,
+            "Using the toolchain version given on the command line"
+        );
+        VersionRequest::Exact(config.toolchain_repo.parse_version(version)?)
+    } else if opts.latest {
+        debug!("Installing the latest toolchain as requested");
+        VersionRequest::Latest {
+            include_prereleases: opts.include_prereleases,
+        }
+    } else if let Some(llvm_version) = &config.llvm_version {
+        let source = config.source("llvm-version");
+        debug!(llvm_version, ?source, "Using the pinned toolchain version");
+        VersionRequest::Exact(config.toolchain_repo.parse_version(llvm_version)?)
+    } else {
+        debug!("No toolchain version is pinned, using the latest");
+        VersionRequest::Latest {
+            include_prereleases: opts.include_prereleases,
+        }
+    };
+
+    let outcome = install_request(opts, &config, version).await?;
+    if opts.save && outcome.action != InstallAction::Cancelled {
+        edit::set("llvm-version", &outcome.version.name).await?;
+    }
+    Ok(Some(outcome))
+}
+
+/// Installs a toolchain which has already been picked, showing progress in the terminal.
+pub(crate) async fn install_request(
+    opts: &InstallOpts,
+    config: &EffectiveConfig,
+    version: VersionRequest,
+) -> crate::Result<InstallOutcome> {
+    let toolchain = terminal_client(opts, config).await?;
+
+    // Worth pointing out, since a different version than usual is about to be downloaded
+    let configured = match (&version, &config.llvm_version) {
+        (VersionRequest::Exact(version), Some(pinned)) => config
+            .toolchain_repo
+            .parse_version(pinned)
+            .ok()
+            .filter(|pinned| pinned != version)
+            .map(|pinned| (pinned, config.source("llvm-version"))),
+        _ => None,
+    };
+
+    let cancel_token = CancellationToken::new();
+    let reporter = TerminalReporter {
+        latest: matches!(version, VersionRequest::Latest { .. }),
+        configured,
+        assume_yes: config.assume_yes,
+        cancel_token: cancel_token.clone(),
+    };
+    toolchain
+        .install_version(InstallOptions {
+            version,
+            force: opts.force,
+            cancel_token,
+            no_wait: opts.no_wait,
+            reporter: Some(Arc::new(reporter)),
+            ..Default::default()
+        })
+        .await
+}
+
+/// Creates a client set up by the install flags.
+async fn terminal_client(
+    opts: &InstallOpts,
+    config: &EffectiveConfig,
+) -> crate::Result<ToolchainClient> {
+    let mut toolchain = config
+        .toolchain_client()
+        .await?
+        .with_checksum_required(opts.require_checksum)
+        .with_preferred_format(opts.format)
+        .with_extract_threads(opts.extract_threads);
+    if opts.no_trash {
+        toolchain = toolchain.with_delete_mode(DeleteMode::Permanent);
+    }
+    if let Some(repo) = &opts.repo {
+        // Keep the configured tag format, which forks usually share
+        let repo = ToolchainRepo {
+            owner: repo.owner.clone(),
+            name: repo.name.clone(),
+            ..toolchain.repo().clone()
+        };
+        toolchain = toolchain.with_repo(repo);
+    }
+    Ok(toolchain)
+}
+
+/// How many releases `--select` lists before offering to show older ones.
+const SELECT_RECENT: usize = 10;
+
+/// A row in the `--select` list.
+#[derive(Clone)]
+enum ReleaseChoice {
+    Release {
+        version: ToolchainVersion,
+        published_at: Option<DateTime<Utc>>,
+        prerelease: bool,
+        installed: bool,
+    },
+    ShowOlder,
+}
+
+impl Display for ReleaseChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self::Release {
+            version,
+            published_at,
+            prerelease,
+            installed,
+        } = self
+        else {
+            return write!(f, "Show older…");
+        };
+
+        let published_at = published_at.map_or_else(
+            || "unpublished".to_string(),
+            |date| date.format("%Y-%m-%d").to_string(),
+        );
+        write!(f, "{:<12} {published_at}", version.to_string())?;
+        if *prerelease {
+            write!(f, " prerelease")?;
+        }
+        if *installed {
+            write!(f, " (installed)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Asks the user to choose a toolchain version from the repository's releases, newest first.
+///
+/// Only the most recent releases are listed until the user asks for older ones. Returns `None`
+/// if the user backs out with Esc or Ctrl-C.
+async fn select_version(
+    toolchain: &ToolchainClient,
+    assume_yes: bool,
+) -> crate::Result<Option<ToolchainVersion>> {
+    if assume_yes {
+        return Err(Error::SelectUnavailable {
+            reason: "answers are assumed to be yes",
+        });
+    }
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return Err(Error::SelectUnavailable {
+            reason: "there's no interactive terminal",
+        });
+    }
+
+    let mut releases = toolchain.releases();
+    let mut choices = Vec::new();
+    let mut skipped = Vec::new();
+    let mut limit = SELECT_RECENT;
+    let mut more = true;
+    let mut cursor = 0;
+    loop {
+        while more && choices.len() < limit {
+            let Some(release) = releases.next().await? else {
+                more = false;
+                break;
+            };
+            if release.is_draft() || !toolchain.repo().is_toolchain_tag(release.tag_name()) {
+                skipped.push(release.tag_name().to_string());
+                continue;
+            }
+            choices.push(ReleaseChoice::Release {
+                version: release.version().clone(),
+                published_at: release.published_at(),
+                prerelease: release.is_prerelease(),
+                installed: toolchain.version_is_installed(release.version()),
+            });
+        }
+        if choices.is_empty() {
+            return Err(ToolchainError::LatestReleaseMissing {
+                candidates: skipped,
+            }
+            .into());
+        }
+
+        let mut options = choices.clone();
+        if more {
+            options.push(ReleaseChoice::ShowOlder);
+        }
+        let answer = Select::new("Which toolchain version?", options)
+            .with_starting_cursor(cursor)
+            .with_page_size(SELECT_RECENT + 1)
+            .prompt();
+        match answer {
+            Ok(ReleaseChoice::Release { version, .. }) => return Ok(Some(version)),
+            Ok(ReleaseChoice::ShowOlder) => {
+                // Start on the first of the older releases
+                cursor = choices.len();
+                limit = usize::MAX;
+            }
+            Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => {
+                return Ok(None);
+            }
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+/// Reports an install's progress in the terminal, asking before anything is downloaded.
+///
+/// The default asks as if a pinned version was being installed, and is also used to report
+/// what a client does outside of installs, like migrating toolchains.
+#[derive(Default)]
+pub(crate) struct TerminalReporter {
+    /// Whether the latest toolchain was requested, rather than a pinned one.
+    latest: bool,
+    /// The configured version and where it's from, if a different one was requested.
+    configured: Option<(ToolchainVersion, Option<ConfigSource>)>,
+    assume_yes: bool,
+    cancel_token: CancellationToken,
+}
+
+impl InstallReporter for TerminalReporter {
+    fn confirm(&self, plan: &InstallPlan<'_>) -> crate::Result<bool> {
+        let version = plan.release.version();
+        let prerelease = prerelease_label(plan.release);
+        let mut description = if self.latest {
+            format!("latest LLVM toolchain ({version}{prerelease})")
+        } else {
+            format!("LLVM toolchain {version}{prerelease}")
+        };
+        if let Some((configured, source)) = &self.configured {
+            description.push_str(&format!(", overriding {configured}"));
+            if let Some(source) = source {
+                description.push_str(&format!(" from {source}"));
+            }
         }
-    });
 
-    let destination = toolchain
-        .download_and_install(&toolchain_release, asset, cancel_token)
-        .await?;
-    msg!("Downloaded", "to {}", destination.display());
+        let size = plan.asset.size;
+        let download_size = if plan.downloaded > 0 {
+            format!(
+                "{} of {} left",
+                HumanBytes(size - plan.downloaded),
+                HumanBytes(size)
+            )
+        } else {
+            HumanBytes(size).to_string()
+        };
+
+        confirm(
+            &format!(
+                "Download {} ({download_size}) and install the {description}?",
+                plan.asset.name
+            ),
+            "Required support libraries for Embedded Swift. No = cancel",
+            self.assume_yes,
+        )
+    }
 
-    Ok(())
+    fn repairing(&self, version: &ToolchainVersion) {
+        msg!("Repairing", "{version}, which was only partially installed");
+    }
+
+    fn evicted(&self, bytes: u64) {
+        msg!("Evicted", "{} cached download", HumanBytes(bytes));
+    }
+
+    fn retrying(&self, asset: &ToolchainAsset) {
+        msg!(
+            "Retrying",
+            "{}, which didn't match its checksum, from scratch",
+            ui::paint(&asset.name, ui::EMPHASIS)
+        );
+    }
+
+    fn downloading(&self, plan: &InstallPlan<'_>) {
+        msg!(
+            "Downloading",
+            "{} <{}>",
+            ui::paint(&plan.asset.name, ui::EMPHASIS),
+            ui::paint(plan.url, ui::LOCATION)
+        );
+
+        tokio::spawn({
+            let cancel_token = self.cancel_token.clone();
+            async move {
+                tokio::signal::ctrl_c().await.unwrap();
+                cancel_token.cancel();
+                eprintln!("Cancelled.");
+            }
+        });
+    }
+
+    fn unverified(&self, asset: &ToolchainAsset) {
+        ui::suspend(|| {
+            eprintln!(
+                "{:>12} {} has no checksum file, so the download can't be verified",
+                ui::paint("Warning", ui::WARNING),
+                asset.name
+            )
+        });
+    }
+
+    fn migrated(&self, name: &str, from: &Path) {
+        msg!("Migrated", "{name} from {}", from.display());
+    }
+
+    fn progress_bar(&self, bar: ProgressBar) -> ProgressBar {
+        ui::add_progress(bar)
+    }
+}
+
+fn prerelease_label(release: &ToolchainRelease) -> &'static str {
+    if release.is_prerelease() {
+        " — prerelease"
+    } else {
+        ""
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        opts: InstallOpts,
+    }
+
+    fn uses_project(args: &[&str]) -> bool {
+        let cli = Cli::try_parse_from(["install"].iter().chain(args)).unwrap();
+        cli.opts.uses_project()
+    }
+
+    #[test]
+    fn chosen_versions_dont_use_project() {
+        assert!(uses_project(&[]));
+        assert!(uses_project(&["--force"]));
+        assert!(!uses_project(&["--latest"]));
+        assert!(!uses_project(&["--toolchain-version", "21.1.1"]));
+        assert!(!uses_project(&["--select"]));
+        assert!(uses_project(&["--toolchain-version", "21.1.1", "--save"]));
+        assert!(uses_project(&["--select", "--save"]));
+    }
 }
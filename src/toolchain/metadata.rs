@@ -0,0 +1,135 @@
+//! Where an installed toolchain came from.
+//!
+//! A manifest is written into each toolchain's directory once it's installed. Toolchains
+//! installed by older versions of swift-v5 don't have one.
+
+use std::{io::ErrorKind, path::Path};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use url::Url;
+
+use crate::{
+    fs,
+    toolchain::{ChecksumAlgorithm, ToolchainError, ToolchainVersion},
+};
+
+/// The name of the manifest inside a toolchain's directory.
+pub const FILE_NAME: &str = ".swift-v5-install.json";
+
+/// The current version of the manifest's format.
+///
+/// Fields added in later versions must have a default, so that older manifests can still be
+/// read.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The contents of a toolchain's install manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallMetadata {
+    /// The version of the manifest's format, see [`SCHEMA_VERSION`].
+    pub schema_version: u32,
+    pub version: ToolchainVersion,
+    /// The name of the archive the toolchain was extracted from.
+    pub asset_name: String,
+    /// Where the archive was downloaded from, which may be a mirror.
+    pub asset_url: Url,
+    /// Older manifests only recorded SHA-256 checksums.
+    #[serde(default)]
+    pub checksum_algorithm: ChecksumAlgorithm,
+    /// The checksum of the archive, in hex.
+    #[serde(alias = "sha256")]
+    pub checksum: String,
+    /// Whether `checksum` matched the one published with the release.
+    pub checksum_verified: bool,
+    pub installed_at: DateTime<Utc>,
+    /// The version of swift-v5 which installed the toolchain.
+    pub installed_by: String,
+}
+
+impl InstallMetadata {
+    /// Reads the manifest in a toolchain's directory, or returns `None` if it doesn't have one.
+    pub async fn read(install_path: &Path) -> Result<Option<Self>, ToolchainError> {
+        let path = install_path.join(FILE_NAME);
+        let contents = match fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                debug!(?path, "No install metadata");
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let metadata: Self = serde_json::from_str(&contents)
+            .map_err(|error| std::io::Error::new(ErrorKind::InvalidData, error))?;
+        if metadata.schema_version > SCHEMA_VERSION {
+            debug!(
+                schema_version = metadata.schema_version,
+                "Install metadata is from a newer version of swift-v5"
+            );
+        }
+
+        Ok(Some(metadata))
+    }
+
+    /// Writes the manifest into a toolchain's directory.
+    pub async fn write(&self, install_path: &Path) -> Result<(), ToolchainError> {
+        let contents =
+            serde_json::to_string_pretty(self).expect("install metadata is serializable");
+        fs::write(install_path.join(FILE_NAME), contents).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_sha256_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        let hash = "ab".repeat(32);
+        let manifest = serde_json::json!({
+            "schema_version": 1,
+            "version": "21.1.1",
+            "asset_name": "ATfE-21.1.1-Linux-x86_64.tar.xz",
+            "asset_url": "https://github.com/download/ATfE-21.1.1-Linux-x86_64.tar.xz",
+            "sha256": hash,
+            "checksum_verified": true,
+            "installed_at": "2025-01-01T00:00:00Z",
+            "installed_by": "0.1.0",
+        });
+        std::fs::write(dir.path().join(FILE_NAME), manifest.to_string()).unwrap();
+
+        let metadata = InstallMetadata::read(dir.path()).await.unwrap().unwrap();
+        assert_eq!(metadata.checksum_algorithm, ChecksumAlgorithm::Sha256);
+        assert_eq!(metadata.checksum, hash);
+    }
+
+    #[tokio::test]
+    async fn round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let metadata = InstallMetadata {
+            schema_version: SCHEMA_VERSION,
+            version: ToolchainVersion::named("21.1.1").unwrap(),
+            asset_name: "ATfE-21.1.1-Linux-x86_64.tar.xz".to_string(),
+            asset_url: Url::parse("file:///mirror/ATfE-21.1.1-Linux-x86_64.tar.xz").unwrap(),
+            checksum_algorithm: ChecksumAlgorithm::Sha512,
+            checksum: "cd".repeat(64),
+            checksum_verified: false,
+            installed_at: Utc::now(),
+            installed_by: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        metadata.write(dir.path()).await.unwrap();
+        assert_eq!(
+            InstallMetadata::read(dir.path()).await.unwrap(),
+            Some(metadata)
+        );
+        assert_eq!(
+            InstallMetadata::read(&dir.path().join("missing"))
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
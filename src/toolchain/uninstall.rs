@@ -0,0 +1,241 @@
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use indicatif::HumanBytes;
+
+use crate::{
+    Error,
+    config::{Config, EffectiveConfig},
+    confirm, msg,
+    project::{Project, ProjectConfig},
+    symlink::{DEFAULT_LINK_PATH, link_path},
+    toolchain::{
+        DeleteMode, InstallationState, ToolchainError, ToolchainVersion, delete_path, dir_size,
+    },
+};
+
+#[derive(Args, Debug)]
+pub struct UninstallOpts {
+    /// The toolchain version to uninstall, like `21.1.1`
+    pub version: String,
+    /// Uninstall the toolchain even if the current project uses it
+    #[arg(long)]
+    pub force: bool,
+    /// Delete the toolchain permanently instead of moving it to the trash
+    #[arg(long)]
+    pub no_trash: bool,
+}
+
+/// Deletes an installed toolchain, unless the current project depends on it.
+pub async fn uninstall(opts: &UninstallOpts, config: &Config) -> crate::Result<()> {
+    let project = match Project::find().await {
+        Ok(project) => Some(project),
+        Err(Error::CannotFindProject { .. }) => None,
+        Err(err) => return Err(err),
+    };
+    let project_config = match &project {
+        Some(project) => project.config().await?,
+        None => None,
+    };
+    let effective = config.resolve(project_config)?;
+    let version = effective.toolchain_repo.parse_version(&opts.version)?;
+    let mut toolchain = effective.toolchain_client().await?;
+    if opts.no_trash {
+        toolchain = toolchain.with_delete_mode(DeleteMode::Permanent);
+    }
+
+    let install_path = toolchain.install_path_for(&version)?;
+    if toolchain.installation_state(&version) == InstallationState::Absent {
+        return Err(ToolchainError::NotInstalled { version }.into());
+    }
+
+    if !opts.force {
+        let reasons = in_use_reasons(
+            project.as_ref(),
+            project_config,
+            &effective,
+            &version,
+            &install_path,
+        );
+        if !reasons.is_empty() {
+            return Err(ToolchainError::InUse { version, reasons }.into());
+        }
+    }
+
+    let confirmation = confirm(
+        &format!("Uninstall LLVM toolchain {version}?"),
+        &format!("Deletes {}.", install_path.display()),
+        effective.assume_yes,
+    )?;
+    if !confirmation {
+        return Ok(());
+    }
+
+    let size = dir_size(&install_path).await?;
+    delete_path(&install_path, toolchain.delete_mode()).await?;
+    msg!("Uninstalled", "{version}, freeing {}", HumanBytes(size));
+
+    Ok(())
+}
+
+/// Lists why `version`, installed at `install_path`, is still needed by the current project.
+///
+/// The version pinned in the project's `v5.toml` is checked rather than the effective one, so
+/// overriding it with an environment variable doesn't make the pinned toolchain removable.
+fn in_use_reasons(
+    project: Option<&Project>,
+    project_config: Option<&ProjectConfig>,
+    effective: &EffectiveConfig,
+    version: &ToolchainVersion,
+    install_path: &Path,
+) -> Vec<String> {
+    let mut reasons = Vec::new();
+
+    if let Some(project) = project
+        && let Some(pinned) = project_config.and_then(|config| config.llvm_version.as_ref())
+        && effective
+            .toolchain_repo
+            .parse_version(pinned.get_ref())
+            .is_ok_and(|pinned| pinned == *version)
+    {
+        reasons.push(format!(
+            "it is pinned in {}",
+            project.config_path().display()
+        ));
+    }
+
+    let mut links = vec![PathBuf::from(DEFAULT_LINK_PATH)];
+    if let Some(project) = project {
+        links.push(link_path(project, effective));
+    }
+    links.dedup_by(|a, b| same_path(a, b));
+    for link in links {
+        if links_into(&link, install_path) {
+            reasons.push(format!("{} links to it", link.display()));
+        }
+    }
+
+    reasons
+}
+
+/// Checks whether `link` is a symlink which resolves to `target` or somewhere inside it.
+fn links_into(link: &Path, target: &Path) -> bool {
+    if !link.is_symlink() {
+        return false;
+    }
+
+    match (link.canonicalize(), target.canonicalize()) {
+        (Ok(resolved), Ok(target)) => resolved.starts_with(target),
+        _ => false,
+    }
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (std::path::absolute(a), std::path::absolute(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A project in a temporary directory with the given `v5.toml`, and the path a toolchain
+    /// would be installed at.
+    struct Fixture {
+        dir: tempfile::TempDir,
+        project: Project,
+        project_config: ProjectConfig,
+        install_path: PathBuf,
+    }
+
+    fn fixture(config: &str) -> Fixture {
+        let dir = tempfile::tempdir().unwrap();
+        let project = Project::new(dir.path().join("robot"));
+        std::fs::create_dir(project.path()).unwrap();
+        let project_config =
+            ProjectConfig::parse(&project.config_path(), config.to_string()).unwrap();
+        let install_path = dir.path().join("toolchains/21.1.1");
+        std::fs::create_dir_all(install_path.join("bin")).unwrap();
+        Fixture {
+            dir,
+            project,
+            project_config,
+            install_path,
+        }
+    }
+
+    fn reasons(fixture: &Fixture, project: bool) -> Vec<String> {
+        let effective = Config::default()
+            .resolve(Some(&fixture.project_config))
+            .unwrap();
+        in_use_reasons(
+            project.then_some(&fixture.project),
+            project.then_some(&fixture.project_config),
+            &effective,
+            &ToolchainVersion::named("21.1.1").unwrap(),
+            &fixture.install_path,
+        )
+    }
+
+    #[test]
+    fn pinned_version() {
+        let fixture = fixture("llvm-version = \"21.1.1\"\n");
+        assert_eq!(
+            reasons(&fixture, true),
+            [format!(
+                "it is pinned in {}",
+                fixture.project.config_path().display()
+            )]
+        );
+
+        let other = self::fixture("llvm-version = \"22.1.0\"\n");
+        assert!(reasons(&other, true).is_empty());
+    }
+
+    #[test]
+    fn pinned_version_when_overridden() {
+        // As if SWIFT_V5_LLVM_VERSION picked another version
+        let fixture = fixture("llvm-version = \"21.1.1\"\n");
+        let overridden = ProjectConfig::parse(
+            Path::new("v5.toml"),
+            "llvm-version = \"22.1.0\"\n".to_string(),
+        )
+        .unwrap();
+        let effective = Config::default().resolve(Some(&overridden)).unwrap();
+        let reasons = in_use_reasons(
+            Some(&fixture.project),
+            Some(&fixture.project_config),
+            &effective,
+            &ToolchainVersion::named("21.1.1").unwrap(),
+            &fixture.install_path,
+        );
+        assert_eq!(reasons.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn linked_version() {
+        let fixture = fixture("llvm-version = \"22.1.0\"\n");
+        let link = fixture.project.path().join(DEFAULT_LINK_PATH);
+        std::os::unix::fs::symlink(&fixture.install_path, &link).unwrap();
+        assert_eq!(
+            reasons(&fixture, true),
+            [format!("{} links to it", link.display())]
+        );
+
+        // A link to a different toolchain doesn't count
+        std::fs::remove_file(&link).unwrap();
+        let other = fixture.dir.path().join("toolchains/22.1.0");
+        std::fs::create_dir_all(&other).unwrap();
+        std::os::unix::fs::symlink(&other, &link).unwrap();
+        assert!(reasons(&fixture, true).is_empty());
+    }
+
+    #[test]
+    fn no_project() {
+        let fixture = fixture("llvm-version = \"21.1.1\"\n");
+        assert!(reasons(&fixture, false).is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+
+use crate::{
+    Error,
+    config::{Config, EffectiveConfig},
+    project::Project,
+    toolchain::{ToolchainClient, ToolchainError, ToolchainVersion, find_tool, is_toolchain_dir},
+};
+
+/// Finds a binary like `clang` or `llvm-objcopy` in the project's toolchain.
+///
+/// Outside of a project, or if the project doesn't pin a version or use a local toolchain, the
+/// newest installed toolchain is used.
+pub async fn which(tool: &str, config: &Config) -> crate::Result<PathBuf> {
+    let path = active_toolchain(config).await?.tool_path(tool)?;
+    // Symlinks aren't resolved, since tools like clang++ behave differently when renamed
+    Ok(std::path::absolute(path)?)
+}
+
+/// The toolchain the current project builds with.
+pub(crate) enum ActiveToolchain {
+    /// A toolchain version installed by swift-v5.
+    Installed {
+        client: Box<ToolchainClient>,
+        version: ToolchainVersion,
+    },
+    /// The project's local toolchain, set with `llvm-path`.
+    Local(PathBuf),
+}
+
+impl ActiveToolchain {
+    /// The toolchain's version, or `None` for a local toolchain.
+    pub fn version(&self) -> Option<&ToolchainVersion> {
+        match self {
+            Self::Installed { version, .. } => Some(version),
+            Self::Local(_) => None,
+        }
+    }
+
+    /// The toolchain's root directory, failing if it isn't installed.
+    pub fn root(&self) -> crate::Result<PathBuf> {
+        match self {
+            Self::Installed { client, version } => {
+                if !client.version_is_installed(version) {
+                    return Err(ToolchainError::NotInstalled {
+                        version: version.clone(),
+                    }
+                    .into());
+                }
+                Ok(client.install_path_for(version)?)
+            }
+            Self::Local(path) => {
+                if !is_toolchain_dir(path) {
+                    return Err(ToolchainError::LocalToolchainInvalid { path: path.clone() }.into());
+                }
+                Ok(path.clone())
+            }
+        }
+    }
+
+    /// Returns the path to a binary like `clang` in the toolchain.
+    pub fn tool_path(&self, tool: &str) -> crate::Result<PathBuf> {
+        match self {
+            Self::Installed { client, version } => Ok(client.tool_path(version, tool)?),
+            Self::Local(_) => Ok(find_tool(&self.root()?, tool)?),
+        }
+    }
+}
+
+/// Picks the current project's local toolchain or pinned version, or the newest installed one.
+pub(crate) async fn active_toolchain(config: &Config) -> crate::Result<ActiveToolchain> {
+    let project = match Project::find().await {
+        Ok(project) => Some(project),
+        Err(Error::CannotFindProject { .. }) => None,
+        Err(err) => return Err(err),
+    };
+    let project_config = match &project {
+        Some(project) => project.config().await?,
+        None => None,
+    };
+    let effective = config.resolve(project_config)?;
+    resolve(project.as_ref(), &effective).await
+}
+
+async fn resolve(
+    project: Option<&Project>,
+    effective: &EffectiveConfig,
+) -> crate::Result<ActiveToolchain> {
+    // Like `build`, a local toolchain is used directly
+    if let (Some(project), Some(llvm_path)) = (project, &effective.llvm_path) {
+        return Ok(ActiveToolchain::Local(project.path().join(llvm_path)));
+    }
+
+    let client = effective.toolchain_client().await?;
+    let version = match &effective.llvm_version {
+        Some(llvm_version) => effective.toolchain_repo.parse_version(llvm_version)?,
+        None => client
+            .installed_versions()
+            .await?
+            .pop()
+            .ok_or(ToolchainError::NoneInstalled)?,
+    };
+
+    Ok(ActiveToolchain::Installed {
+        client: Box::new(client),
+        version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A project whose `v5.toml` points `llvm-path` at `llvm`.
+    fn local_project(dir: &std::path::Path) -> (Project, EffectiveConfig) {
+        let project = Project::new(dir.to_owned());
+        let mut effective = Config::default().resolve(None).unwrap();
+        // Set directly, since resolving it from a v5.toml would let tests which set
+        // `SWIFT_V5_LLVM_VERSION` override it
+        effective.llvm_version = None;
+        effective.llvm_path = Some(PathBuf::from("llvm"));
+        (project, effective)
+    }
+
+    #[tokio::test]
+    async fn uses_local_toolchain() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("llvm/bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        for tool in ["clang", "llvm-size"] {
+            std::fs::write(bin.join(tool), "").unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(bin.join(tool), std::fs::Permissions::from_mode(0o755))
+                    .unwrap();
+            }
+        }
+        let (project, effective) = local_project(dir.path());
+
+        let toolchain = resolve(Some(&project), &effective).await.unwrap();
+
+        assert_eq!(toolchain.version(), None);
+        assert_eq!(toolchain.root().unwrap(), dir.path().join("llvm"));
+        assert_eq!(
+            toolchain.tool_path("llvm-size").unwrap(),
+            bin.join("llvm-size")
+        );
+        assert!(matches!(
+            toolchain.tool_path("llvm-nm"),
+            Err(Error::Toolchain(ToolchainError::ToolNotFound { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn local_toolchain_must_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let (project, effective) = local_project(dir.path());
+
+        let toolchain = resolve(Some(&project), &effective).await.unwrap();
+
+        assert!(matches!(
+            toolchain.tool_path("clang"),
+            Err(Error::Toolchain(ToolchainError::LocalToolchainInvalid { path }))
+                if path == dir.path().join("llvm")
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+//! Terminal styling and status output.
+//!
+//! Whether to use colors and print status lines is decided once in `main` with [`init`].
+//! Everything which styles its output checks [`colors_enabled`] or goes through [`paint`], and
+//! status lines are printed with the [`msg!`](crate::msg) macro.
+//!
+//! Progress bars are drawn together through one [`MultiProgress`], so bars which are alive at the
+//! same time don't draw over each other. Anything else written to stderr while a bar may be
+//! visible, like status lines and logs, hides the bars first.
+
+use std::{
+    env,
+    fmt::{Arguments, Display},
+    io::{self, IsTerminal, Write},
+    sync::{
+        LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use clap::ValueEnum;
+use indicatif::{MultiProgress, ProgressBar};
+use miette::MietteHandlerOpts;
+use owo_colors::{Style, Styled};
+
+static COLORS_ENABLED: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+static PROGRESS: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
+
+/// The style of `msg!` labels.
+pub const LABEL: Style = Style::new().green().bold();
+/// The style of warning labels.
+pub const WARNING: Style = Style::new().yellow().bold();
+/// The style of names and headings which should stand out.
+pub const EMPHASIS: Style = Style::new().bold();
+/// The style of paths and URLs.
+pub const LOCATION: Style = Style::new().green();
+/// The style of supplementary details, like where a setting came from.
+pub const DIMMED: Style = Style::new().dimmed();
+
+/// When to color the output.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color the output if stderr is a terminal, unless NO_COLOR is set
+    #[default]
+    Auto,
+    /// Always color the output
+    Always,
+    /// Never color the output
+    Never,
+}
+
+impl ColorChoice {
+    /// Decides whether this choice means colors should be used.
+    ///
+    /// In auto mode, `NO_COLOR` disables colors and `CLICOLOR_FORCE` enables them even when
+    /// stderr isn't a terminal. See <https://no-color.org> and <https://bixense.com/clicolors>.
+    pub fn enabled(self) -> bool {
+        // Unset, empty and "0" all count as off
+        let is_set =
+            |name| env::var_os(name).is_some_and(|value| !value.is_empty() && value != "0");
+
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) => {
+                false
+            }
+            ColorChoice::Auto if is_set("CLICOLOR_FORCE") => true,
+            ColorChoice::Auto if env::var_os("CLICOLOR").is_some_and(|value| value == "0") => false,
+            ColorChoice::Auto => io::stderr().is_terminal(),
+        }
+    }
+}
+
+/// How a command prints its report.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// A single JSON object, for scripts and editor integrations
+    Json,
+}
+
+/// Decides whether to use colors for the rest of the invocation, including in error reports,
+/// and whether status lines are printed.
+pub fn init(choice: ColorChoice, quiet: bool) {
+    let enabled = choice.enabled();
+    COLORS_ENABLED.store(enabled, Ordering::Relaxed);
+    QUIET.store(quiet, Ordering::Relaxed);
+
+    // Only fails if a hook was already installed, in which case that one is kept
+    _ = miette::set_hook(Box::new(move |_| {
+        Box::new(MietteHandlerOpts::new().color(enabled).build())
+    }));
+}
+
+/// Whether output should be colored.
+pub fn colors_enabled() -> bool {
+    COLORS_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Applies `style` to `value`, or leaves it plain if colors are disabled.
+pub fn paint<T>(value: T, style: Style) -> Styled<T> {
+    if colors_enabled() {
+        style.style(value)
+    } else {
+        Style::new().style(value)
+    }
+}
+
+/// Whether status lines are suppressed.
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Prints a status line like `  Downloading foo.tar.xz` to stderr, unless in quiet mode.
+///
+/// Status lines never go to stdout, which is reserved for output that can be piped into other
+/// programs. This backs the [`msg!`](crate::msg) macro.
+pub fn status(label: impl Display, message: Arguments<'_>) {
+    if is_quiet() {
+        return;
+    }
+
+    suspend(|| eprintln!("{:>12} {message}", paint(label, LABEL)));
+}
+
+/// Draws a progress bar below any others which are currently shown.
+///
+/// Every progress bar should be added with this before it's first drawn.
+pub fn add_progress(bar: ProgressBar) -> ProgressBar {
+    PROGRESS.add(bar)
+}
+
+/// Hides the progress bars while `f` runs, so it can write to stderr without garbling them.
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    PROGRESS.suspend(f)
+}
+
+/// Writes to stderr with the progress bars hidden, for use as the log output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrWriter;
+
+impl Write for StderrWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        suspend(|| io::stderr().write(buf))
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        suspend(|| io::stderr().write_all(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
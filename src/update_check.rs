@@ -0,0 +1,176 @@
+//! Mentioning new swift-v5 releases after ordinary commands.
+//!
+//! The latest version is looked up at most once a day, and the result is cached so the notice
+//! keeps showing until swift-v5 is updated. Any failure is ignored, since the check should
+//! never get in the way of the command the user actually ran.
+
+use std::{path::Path, time::Duration};
+
+use axoupdater::{AxoUpdater, Version};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{DIRS, fs, invocation, ui};
+
+/// How long to wait for the latest release before giving up.
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// How often to look for a new release.
+const INTERVAL: TimeDelta = TimeDelta::hours(24);
+
+/// The result of the last check, kept in the cache directory.
+#[derive(Debug, Serialize, Deserialize)]
+struct UpdateCheck {
+    checked_at: DateTime<Utc>,
+    latest: Option<Version>,
+}
+
+/// Prints a notice if a newer version of swift-v5 has been released.
+///
+/// Only installs which can update themselves are checked, since others can't act on it.
+/// Nothing is checked in quiet mode.
+pub async fn notify(github_token: Option<&str>) {
+    if ui::is_quiet() {
+        return;
+    }
+
+    let mut updater = AxoUpdater::new_for("swift-v5");
+    if updater.load_receipt().is_err() {
+        debug!("No install receipt, skipping update check");
+        return;
+    }
+    if let Some(token) = github_token {
+        updater.set_github_token(token);
+    }
+
+    let cache_path = DIRS.cache_dir().join("update-check.json");
+    let query = async { Ok(updater.query_new_version().await?.cloned()) };
+    let latest = match latest_version(&cache_path, query).await {
+        Ok(latest) => latest,
+        Err(err) => {
+            debug!(?err, "Couldn't save the update check");
+            return;
+        }
+    };
+
+    let current = Version::parse(env!("CARGO_PKG_VERSION")).expect("package version is valid");
+    if let Some(latest) = latest
+        && latest > current
+    {
+        eprintln!(
+            "A new version of swift-v5 is available ({current} → {latest}); run {}",
+            ui::paint(format!("`{}`", invocation::command("update")), ui::EMPHASIS)
+        );
+    }
+}
+
+/// Returns the latest release, from the cache at `cache_path` if it was checked recently or
+/// by running `query` otherwise.
+///
+/// A failed or timed out query is cached too, along with the release found before it, so
+/// being offline doesn't slow down every command.
+async fn latest_version(
+    cache_path: &Path,
+    query: impl Future<Output = crate::Result<Option<Version>>>,
+) -> crate::Result<Option<Version>> {
+    let cached = match fs::read_to_string(cache_path).await {
+        Ok(contents) => serde_json::from_str::<UpdateCheck>(&contents).ok(),
+        Err(_) => None,
+    };
+    if let Some(cached) = &cached
+        && Utc::now() - cached.checked_at < INTERVAL
+    {
+        debug!(?cached, "Using cached update check");
+        return Ok(cached.latest.clone());
+    }
+
+    let latest = match tokio::time::timeout(TIMEOUT, query).await {
+        Ok(Ok(latest)) => latest,
+        Ok(Err(err)) => {
+            debug!(?err, "Update check failed");
+            cached.and_then(|cached| cached.latest)
+        }
+        Err(_) => {
+            debug!("Update check timed out");
+            cached.and_then(|cached| cached.latest)
+        }
+    };
+
+    let check = UpdateCheck {
+        checked_at: Utc::now(),
+        latest: latest.clone(),
+    };
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(
+        cache_path,
+        serde_json::to_string(&check).expect("update check is serializable"),
+    )
+    .await?;
+
+    Ok(latest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    fn write_cache(path: &Path, checked_at: DateTime<Utc>, latest: Option<&str>) {
+        let check = UpdateCheck {
+            checked_at,
+            latest: latest.map(version),
+        };
+        std::fs::write(path, serde_json::to_string(&check).unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn caches_failed_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("update-check.json");
+
+        let failed = async { Err(crate::Error::Io(std::io::Error::other("offline"))) };
+        assert_eq!(latest_version(&cache_path, failed).await.unwrap(), None);
+
+        // Not checked again until the interval has passed
+        let unreachable = async { panic!("checked again") };
+        assert_eq!(
+            latest_version(&cache_path, unreachable).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_previous_release_when_check_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("update-check.json");
+        write_cache(&cache_path, Utc::now() - INTERVAL * 2, Some("9.0.0"));
+
+        let failed = async { Err(crate::Error::Io(std::io::Error::other("offline"))) };
+        assert_eq!(
+            latest_version(&cache_path, failed).await.unwrap(),
+            Some(version("9.0.0"))
+        );
+
+        let cached: UpdateCheck =
+            serde_json::from_str(&std::fs::read_to_string(&cache_path).unwrap()).unwrap();
+        assert!(Utc::now() - cached.checked_at < INTERVAL);
+    }
+
+    #[tokio::test]
+    async fn checks_again_after_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("update-check.json");
+        write_cache(&cache_path, Utc::now() - INTERVAL * 2, None);
+
+        let found = async { Ok(Some(version("9.0.0"))) };
+        assert_eq!(
+            latest_version(&cache_path, found).await.unwrap(),
+            Some(version("9.0.0"))
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Picks the archive to download from a release's assets.
+
+mod common;
+
+use swift_v5::toolchain::{
+    ArchiveFormat, ChecksumAlgorithm, HostArch, HostOS, ToolchainError, ToolchainRelease,
+};
+use url::Url;
+
+use common::release;
+
+const TAG: &str = "release-21.1.1-ATfE";
+
+/// A release with assets of the given names.
+fn release_with(asset_names: &[&str]) -> ToolchainRelease {
+    let assets: Vec<(&str, usize)> = asset_names.iter().map(|name| (*name, 1024)).collect();
+    ToolchainRelease::new(serde_json::from_value(release(TAG, &assets)).unwrap())
+}
+
+#[test]
+fn prefers_native_assets() {
+    let release = release_with(&[
+        "ATfE-21.1.1-Darwin-universal.dmg",
+        "ATfE-21.1.1-Darwin-AArch64.dmg",
+        "ATfE-21.1.1-Darwin-AArch64.dmg.sha256",
+        "ATfE-21.1.1-Linux-x86_64.tar.xz",
+        "ATfE-21.1.1-Linux-AArch64.tar.xz",
+        "ATfE-21.1.1-Windows-x86_64.zip",
+    ]);
+    let pick = |os, arches: &[HostArch], formats: &[ArchiveFormat]| {
+        release
+            .asset_for(os, arches, formats)
+            .map(|asset| asset.name)
+    };
+
+    let mac_arches = [HostArch::AAarch64, HostArch::Universal];
+    let asset = release
+        .asset_for(HostOS::Darwin, &mac_arches, &[ArchiveFormat::Dmg])
+        .unwrap();
+    assert_eq!(asset.name, "ATfE-21.1.1-Darwin-AArch64.dmg");
+    assert_eq!(
+        asset.checksum_api_urls,
+        [(
+            ChecksumAlgorithm::Sha256,
+            Url::parse("https://api.github.com/repos/arm/arm-toolchain/releases/assets/3").unwrap()
+        )]
+    );
+
+    // Intel Macs only run the universal build
+    assert_eq!(
+        pick(
+            HostOS::Darwin,
+            &[HostArch::X86_64, HostArch::Universal],
+            &[ArchiveFormat::Dmg]
+        )
+        .unwrap(),
+        "ATfE-21.1.1-Darwin-universal.dmg"
+    );
+    assert_eq!(
+        pick(HostOS::Linux, &[HostArch::X86_64], &[ArchiveFormat::TarXz]).unwrap(),
+        "ATfE-21.1.1-Linux-x86_64.tar.xz"
+    );
+}
+
+#[test]
+fn prefers_arch_over_format() {
+    let release = release_with(&[
+        "ATfE-21.1.1-Darwin-universal.dmg",
+        "ATfE-21.1.1-Darwin-AArch64.tar.xz",
+    ]);
+    let asset = release
+        .asset_for(
+            HostOS::Darwin,
+            &[HostArch::AAarch64, HostArch::Universal],
+            &[ArchiveFormat::Dmg, ArchiveFormat::TarXz],
+        )
+        .unwrap();
+    assert_eq!(asset.name, "ATfE-21.1.1-Darwin-AArch64.tar.xz");
+}
+
+#[test]
+fn no_matching_asset() {
+    let release = release_with(&["ATfE-21.1.1-Darwin-universal.dmg"]);
+    assert!(matches!(
+        release.asset_for(HostOS::Windows, &[HostArch::X86_64], &[ArchiveFormat::Zip]),
+        Err(ToolchainError::ReleaseAssetMissing { candidates, .. })
+            if candidates == ["ATfE-21.1.1-Darwin-universal.dmg"]
+    ));
+    // Formats which aren't allowed are never picked
+    assert!(
+        release
+            .asset_for(
+                HostOS::Darwin,
+                &[HostArch::Universal],
+                &[ArchiveFormat::TarXz]
+            )
+            .is_err()
+    );
+}
@@ -0,0 +1,49 @@
+//! Fixtures shared by the integration tests.
+
+/// A release with the given tag and assets, given as their names and sizes, as GitHub's API
+/// returns it.
+pub fn release(tag: &str, assets: &[(&str, usize)]) -> serde_json::Value {
+    let assets: Vec<serde_json::Value> = assets
+        .iter()
+        .zip(1..)
+        .map(|((name, size), id)| {
+            serde_json::json!({
+                "url": format!("https://api.github.com/repos/arm/arm-toolchain/releases/assets/{id}"),
+                "browser_download_url": format!(
+                    "https://github.com/arm/arm-toolchain/releases/download/{tag}/{name}"
+                ),
+                "id": id,
+                "node_id": "",
+                "name": name,
+                "label": null,
+                "state": "uploaded",
+                "content_type": "application/octet-stream",
+                "size": size,
+                "download_count": 0,
+                "created_at": "2025-01-01T00:00:00Z",
+                "updated_at": "2025-01-01T00:00:00Z",
+                "uploader": null,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "url": "https://api.github.com/repos/arm/arm-toolchain/releases/1",
+        "html_url": format!("https://github.com/arm/arm-toolchain/releases/tag/{tag}"),
+        "assets_url": "https://api.github.com/repos/arm/arm-toolchain/releases/1/assets",
+        "upload_url": "",
+        "tarball_url": null,
+        "zipball_url": null,
+        "id": 1,
+        "node_id": "",
+        "tag_name": tag,
+        "target_commitish": "main",
+        "name": null,
+        "body": null,
+        "draft": false,
+        "prerelease": false,
+        "created_at": null,
+        "published_at": null,
+        "author": null,
+        "assets": assets,
+    })
+}
@@ -0,0 +1,765 @@
+//! Downloads and installs toolchains from a mock GitHub, which serves a small fixture archive.
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use indicatif::{ProgressBar, ProgressDrawTarget};
+use octocrab::Octocrab;
+use sha2::{Digest, Sha256};
+use swift_v5::toolchain::{
+    HostArch, HostOS, ToolchainAsset, ToolchainClient, ToolchainError, ToolchainVersion,
+    install::{InstallAction, InstallOptions, InstallPlan, InstallReporter, VersionRequest},
+    metadata::InstallMetadata,
+};
+use tempfile::TempDir;
+use tokio_util::sync::CancellationToken;
+use url::Url;
+use wiremock::{
+    Mock, MockServer, ResponseTemplate,
+    matchers::{header, method, path},
+};
+
+use common::release;
+
+const TAG: &str = "release-21.1.1-ATfE";
+
+/// Hides the client's progress bars.
+struct Silent;
+
+impl InstallReporter for Silent {}
+
+/// Counts how often a download is retried.
+#[derive(Default)]
+struct Retries(AtomicUsize);
+
+impl InstallReporter for Retries {
+    fn retrying(&self, _asset: &ToolchainAsset) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Records the message of each progress bar the client shows.
+#[derive(Default)]
+struct Bars(Mutex<Vec<String>>);
+
+impl InstallReporter for Bars {
+    fn progress_bar(&self, bar: ProgressBar) -> ProgressBar {
+        self.0.lock().unwrap().push(bar.message());
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+        bar
+    }
+}
+
+/// Records what an install reported, and answers its confirmation with `confirm`.
+struct Recorder {
+    confirm: bool,
+    events: Mutex<Vec<String>>,
+}
+
+impl Recorder {
+    fn new(confirm: bool) -> Arc<Self> {
+        Arc::new(Self {
+            confirm,
+            events: Mutex::default(),
+        })
+    }
+
+    fn record(&self, event: String) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn events(&self) -> Vec<String> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl InstallReporter for Recorder {
+    fn confirm(&self, plan: &InstallPlan<'_>) -> swift_v5::Result<bool> {
+        self.record(format!(
+            "confirm {} from {}",
+            plan.release.version(),
+            plan.downloaded
+        ));
+        Ok(self.confirm)
+    }
+
+    fn evicted(&self, bytes: u64) {
+        self.record(format!("evicted {bytes}"));
+    }
+
+    fn downloading(&self, plan: &InstallPlan<'_>) {
+        self.record(format!("downloading {}", plan.asset.name));
+    }
+
+    fn unverified(&self, asset: &ToolchainAsset) {
+        self.record(format!("unverified {}", asset.name));
+    }
+}
+
+/// A mock GitHub with a release of version 21.1.1, and directories to install it to.
+struct Harness {
+    server: MockServer,
+    dir: TempDir,
+    asset_name: String,
+    archive: Vec<u8>,
+}
+
+impl Harness {
+    async fn new() -> Self {
+        let server = MockServer::start().await;
+        let asset_name = format!(
+            "ATfE-21.1.1-{}-{}.tar.xz",
+            HostOS::current().as_ref(),
+            HostArch::current()[0].as_ref()
+        );
+        let archive = archive();
+
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/repos/arm/arm-toolchain/releases/tags/{TAG}"
+            )))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(release(TAG, &[(&asset_name, archive.len())])),
+            )
+            .mount(&server)
+            .await;
+
+        Self {
+            server,
+            dir: tempfile::tempdir().unwrap(),
+            asset_name,
+            archive,
+        }
+    }
+
+    /// A client which looks up releases and downloads files from the mock server.
+    async fn client(&self) -> ToolchainClient {
+        self.client_with_archive_server(None).await
+    }
+
+    /// Like [`Harness::client`], but downloads the archive from `archive_server` if it's given.
+    async fn client_with_archive_server(
+        &self,
+        archive_server: Option<SocketAddr>,
+    ) -> ToolchainClient {
+        let gh_client = Octocrab::builder()
+            .base_uri(self.server.uri())
+            .unwrap()
+            .build()
+            .unwrap();
+        let mock_server = Url::parse(&self.server.uri()).unwrap();
+        let archive_path = self.archive_path();
+
+        ToolchainClient::builder(self.dir.path().join("toolchains"), self.cache_path())
+            .github_client(Arc::new(gh_client))
+            .rewrite_urls(move |mut url| {
+                // Files on a local mirror are left alone
+                if url.scheme() == "file" {
+                    return url;
+                }
+                let server = match archive_server {
+                    Some(addr) if url.path() == archive_path => {
+                        Url::parse(&format!("http://{addr}")).unwrap()
+                    }
+                    _ => mock_server.clone(),
+                };
+                url.set_scheme(server.scheme()).unwrap();
+                url.set_host(server.host_str()).unwrap();
+                url.set_port(server.port()).unwrap();
+                url
+            })
+            .build()
+            .await
+            .unwrap()
+            .with_reporter(Arc::new(Silent))
+    }
+
+    fn cache_path(&self) -> PathBuf {
+        self.dir.path().join("cache")
+    }
+
+    /// The path the archive is downloaded from.
+    fn archive_path(&self) -> String {
+        format!(
+            "/arm/arm-toolchain/releases/download/{TAG}/{}",
+            self.asset_name
+        )
+    }
+
+    /// Serves the archive, once, in full.
+    async fn serve_archive(&self) {
+        Mock::given(method("GET"))
+            .and(path(self.archive_path()))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(self.archive.clone()))
+            .expect(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Serves a SHA-256 checksum file for the archive with the given checksum.
+    async fn serve_checksum(&self, checksum: &str) {
+        Mock::given(method("GET"))
+            .and(path(format!("{}.sha256", self.archive_path())))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(format!("{checksum}  {}\n", self.asset_name)),
+            )
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Lists the release as the only one in the repository.
+    async fn serve_release_list(&self) {
+        Mock::given(method("GET"))
+            .and(path("/repos/arm/arm-toolchain/releases"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json([release(TAG, &[(&self.asset_name, self.archive.len())])]),
+            )
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Looks up the release and installs its archive for this host.
+    async fn install(&self, client: &ToolchainClient) -> Result<PathBuf, ToolchainError> {
+        let release = client
+            .get_release(&ToolchainVersion::named("21.1.1").unwrap())
+            .await?;
+        let asset = release.asset_for(
+            HostOS::current(),
+            HostArch::current(),
+            &client.format_preference(HostOS::current()),
+        )?;
+        client
+            .download_and_install(&release, &asset, CancellationToken::new())
+            .await
+    }
+}
+
+/// Serves one response on a bare TCP socket, which advertises the whole archive but closes the
+/// connection after the first `len` bytes of it, as a dropped connection would.
+fn serve_truncated(archive: Vec<u8>, len: usize) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let read = stream.read(&mut buffer).unwrap();
+            if read == 0 {
+                return;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+            archive.len()
+        );
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(&archive[..len]).unwrap();
+        stream.shutdown(Shutdown::Both).unwrap();
+    });
+    addr
+}
+
+/// The archive with one byte changed, as a corrupted download would be.
+fn corrupted(archive: &[u8]) -> Vec<u8> {
+    let mut corrupted = archive.to_vec();
+    let middle = corrupted.len() / 2;
+    corrupted[middle] ^= 0xff;
+    corrupted
+}
+
+/// A `.tar.xz` archive laid out like a toolchain, with a single root directory.
+fn archive() -> Vec<u8> {
+    let mut tar = tar::Builder::new(Vec::new());
+    let clang = b"#!/bin/sh\n";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(clang.len() as u64);
+    header.set_mode(0o755);
+    tar.append_data(&mut header, "ATfE-21.1.1/bin/clang", &clang[..])
+        .unwrap();
+
+    let mut encoder = liblzma::write::XzEncoder::new(Vec::new(), 6);
+    std::io::Write::write_all(&mut encoder, &tar.into_inner().unwrap()).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn sha256(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+async fn metadata(path: &Path) -> InstallMetadata {
+    InstallMetadata::read(path).await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn downloads_and_installs() {
+    let harness = Harness::new().await;
+    harness.serve_archive().await;
+    harness.serve_checksum(&sha256(&harness.archive)).await;
+    let client = harness.client().await;
+
+    let path = harness.install(&client).await.unwrap();
+
+    assert_eq!(path, harness.dir.path().join("toolchains/21.1.1"));
+    assert!(path.join("bin/clang").is_file());
+    let metadata = metadata(&path).await;
+    assert_eq!(metadata.checksum, sha256(&harness.archive));
+    assert!(metadata.checksum_verified);
+    assert!(
+        metadata
+            .asset_url
+            .as_str()
+            .starts_with(&harness.server.uri())
+    );
+}
+
+#[tokio::test]
+async fn resumes_interrupted_download() {
+    let harness = Harness::new().await;
+    let half = harness.archive.len() / 2;
+    std::fs::create_dir_all(harness.cache_path()).unwrap();
+    std::fs::write(
+        harness.cache_path().join(&harness.asset_name),
+        &harness.archive[..half],
+    )
+    .unwrap();
+
+    // Only the rest of the archive is served
+    Mock::given(method("GET"))
+        .and(path(harness.archive_path()))
+        .and(header(
+            "range",
+            format!("bytes={half}-{}", harness.archive.len() - 1),
+        ))
+        .respond_with(ResponseTemplate::new(206).set_body_bytes(&harness.archive[half..]))
+        .expect(1)
+        .mount(&harness.server)
+        .await;
+    harness.serve_checksum(&sha256(&harness.archive)).await;
+    let client = harness.client().await;
+
+    let path = harness.install(&client).await.unwrap();
+
+    assert!(path.join("bin/clang").is_file());
+    assert!(metadata(&path).await.checksum_verified);
+}
+
+#[tokio::test]
+async fn rejects_checksum_mismatch() {
+    let harness = Harness::new().await;
+    Mock::given(method("GET"))
+        .and(path(harness.archive_path()))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(harness.archive.clone()))
+        .mount(&harness.server)
+        .await;
+    let wrong = sha256(b"something else");
+    harness.serve_checksum(&wrong).await;
+    let client = harness.client().await;
+
+    let error = harness.install(&client).await.unwrap_err();
+
+    assert!(
+        matches!(
+            &error,
+            ToolchainError::RepeatedChecksumMismatch { expected, actual, .. }
+                if *expected == wrong && *actual == sha256(&harness.archive)
+        ),
+        "{error:?}"
+    );
+    assert!(!harness.dir.path().join("toolchains/21.1.1").exists());
+}
+
+#[tokio::test]
+async fn installs_without_checksum() {
+    let harness = Harness::new().await;
+    harness.serve_archive().await;
+    let client = harness.client().await;
+
+    let path = harness.install(&client).await.unwrap();
+
+    let metadata = metadata(&path).await;
+    assert_eq!(metadata.checksum, sha256(&harness.archive));
+    assert!(!metadata.checksum_verified);
+}
+
+#[tokio::test]
+async fn requires_checksum() {
+    let harness = Harness::new().await;
+    harness.serve_archive().await;
+    let bars = Arc::new(Bars::default());
+    let client = harness
+        .client()
+        .await
+        .with_checksum_required(true)
+        .with_reporter(bars.clone());
+
+    let error = harness.install(&client).await.unwrap_err();
+
+    assert!(
+        matches!(&error, ToolchainError::ChecksumMissing { url } if url.path().ends_with(".sha256")),
+        "{error:?}"
+    );
+    // The download is rejected before it's hashed
+    assert!(
+        !bars
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|message| message == "Verifying")
+    );
+    assert!(!harness.dir.path().join("toolchains/21.1.1").exists());
+}
+
+#[tokio::test]
+async fn copies_from_local_mirror() {
+    let harness = Harness::new().await;
+    let mirror = harness.dir.path().join("mirror");
+    std::fs::create_dir(&mirror).unwrap();
+    let archive = mirror.join(&harness.asset_name);
+    std::fs::write(&archive, &harness.archive).unwrap();
+    std::fs::write(
+        mirror.join(format!("{}.sha256", harness.asset_name)),
+        format!("{}  {}\n", sha256(&harness.archive), harness.asset_name),
+    )
+    .unwrap();
+    // Like a shared network drive
+    let mut permissions = std::fs::metadata(&archive).unwrap().permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(&archive, permissions).unwrap();
+    let client = harness
+        .client()
+        .await
+        .with_mirror(Url::from_directory_path(&mirror).unwrap());
+
+    let path = harness.install(&client).await.unwrap();
+
+    assert!(path.join("bin/clang").is_file());
+    assert!(metadata(&path).await.checksum_verified);
+    // The cached archive is a copy, so changing it can't change the mirror's
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        assert_eq!(std::fs::metadata(&archive).unwrap().nlink(), 1);
+    }
+    client.evict_cached(&harness.asset_name).await.unwrap();
+    assert_eq!(std::fs::read(&archive).unwrap(), harness.archive);
+}
+
+#[tokio::test]
+async fn keeps_truncated_download() {
+    let harness = Harness::new().await;
+    let half = harness.archive.len() / 2;
+    let archive_server = serve_truncated(harness.archive.clone(), half);
+    harness.serve_checksum(&sha256(&harness.archive)).await;
+    let client = harness
+        .client_with_archive_server(Some(archive_server))
+        .await;
+
+    let error = harness.install(&client).await.unwrap_err();
+
+    assert!(
+        matches!(
+            error,
+            ToolchainError::TruncatedDownload { expected, actual }
+                if expected == harness.archive.len() as u64 && actual == half as u64
+        ),
+        "{error:?}"
+    );
+    // The part which was received is kept, so the next attempt resumes it
+    let cached = std::fs::read(harness.cache_path().join(&harness.asset_name)).unwrap();
+    assert_eq!(cached, harness.archive[..half]);
+}
+
+#[tokio::test]
+async fn downloads_again_after_checksum_mismatch() {
+    let harness = Harness::new().await;
+    // The first download is corrupted, and the second one isn't
+    Mock::given(method("GET"))
+        .and(path(harness.archive_path()))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(corrupted(&harness.archive)))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .expect(1)
+        .mount(&harness.server)
+        .await;
+    harness.serve_archive().await;
+    harness.serve_checksum(&sha256(&harness.archive)).await;
+    let retries = Arc::new(Retries::default());
+    let client = harness.client().await.with_reporter(retries.clone());
+
+    let path = harness.install(&client).await.unwrap();
+
+    assert_eq!(retries.0.load(Ordering::SeqCst), 1);
+    assert!(path.join("bin/clang").is_file());
+    let metadata = metadata(&path).await;
+    assert_eq!(metadata.checksum, sha256(&harness.archive));
+    assert!(metadata.checksum_verified);
+}
+
+#[tokio::test]
+async fn gives_up_after_second_checksum_mismatch() {
+    let harness = Harness::new().await;
+    let corrupted = corrupted(&harness.archive);
+    Mock::given(method("GET"))
+        .and(path(harness.archive_path()))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(corrupted.clone()))
+        .expect(2)
+        .mount(&harness.server)
+        .await;
+    harness.serve_checksum(&sha256(&harness.archive)).await;
+    let retries = Arc::new(Retries::default());
+    let client = harness.client().await.with_reporter(retries.clone());
+
+    let error = harness.install(&client).await.unwrap_err();
+
+    assert!(
+        matches!(
+            &error,
+            ToolchainError::RepeatedChecksumMismatch { expected, actual, .. }
+                if *expected == sha256(&harness.archive) && *actual == sha256(&corrupted)
+        ),
+        "{error:?}"
+    );
+    assert_eq!(retries.0.load(Ordering::SeqCst), 1);
+    assert!(!harness.dir.path().join("toolchains/21.1.1").exists());
+}
+
+#[tokio::test]
+async fn concurrent_installs_download_once() {
+    let harness = Harness::new().await;
+    harness.serve_archive().await;
+    harness.serve_checksum(&sha256(&harness.archive)).await;
+    // Separate clients, like two swift-v5 processes
+    let (first, second) = (harness.client().await, harness.client().await);
+    let options = InstallOptions {
+        version: VersionRequest::Exact(ToolchainVersion::named("21.1.1").unwrap()),
+        ..Default::default()
+    };
+
+    let (first, second) = tokio::join!(
+        first.install_version(options.clone()),
+        second.install_version(options),
+    );
+
+    // Whichever install waited for the lock finds the toolchain already installed
+    let mut actions = [first.unwrap().action, second.unwrap().action];
+    actions.sort_by_key(|action| *action == InstallAction::AlreadyInstalled);
+    assert!(
+        matches!(
+            actions,
+            [
+                InstallAction::Downloaded { bytes, .. },
+                InstallAction::AlreadyInstalled,
+            ] if bytes == harness.archive.len() as u64
+        ),
+        "{actions:?}"
+    );
+    assert!(
+        harness
+            .dir
+            .path()
+            .join("toolchains/21.1.1/bin/clang")
+            .is_file()
+    );
+}
+
+fn install_options(version: VersionRequest, reporter: &Arc<Recorder>) -> InstallOptions {
+    InstallOptions {
+        version,
+        reporter: Some(reporter.clone()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn installs_latest_version() {
+    let harness = Harness::new().await;
+    harness.serve_release_list().await;
+    harness.serve_archive().await;
+    harness.serve_checksum(&sha256(&harness.archive)).await;
+    let client = harness.client().await;
+    let reporter = Recorder::new(true);
+
+    let outcome = client
+        .install_version(install_options(VersionRequest::default(), &reporter))
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.version, ToolchainVersion::named("21.1.1").unwrap());
+    assert_eq!(outcome.path, harness.dir.path().join("toolchains/21.1.1"));
+    assert!(
+        matches!(outcome.action, InstallAction::Downloaded { bytes, .. } if bytes == harness.archive.len() as u64),
+        "{:?}",
+        outcome.action
+    );
+    assert_eq!(
+        reporter.events(),
+        [
+            "confirm v21.1.1 from 0".to_string(),
+            format!("downloading {}", harness.asset_name),
+        ]
+    );
+    assert!(client.version_is_installed(&outcome.version));
+}
+
+#[tokio::test]
+async fn reports_unverified_install() {
+    let harness = Harness::new().await;
+    harness.serve_archive().await;
+    let client = harness.client().await;
+    let reporter = Recorder::new(true);
+    let version = ToolchainVersion::named("21.1.1").unwrap();
+
+    client
+        .install_version(install_options(VersionRequest::Exact(version), &reporter))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        reporter.events(),
+        [
+            "confirm v21.1.1 from 0".to_string(),
+            format!("downloading {}", harness.asset_name),
+            format!("unverified {}", harness.asset_name),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn declined_install_downloads_nothing() {
+    let harness = Harness::new().await;
+    Mock::given(method("GET"))
+        .and(path(harness.archive_path()))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(harness.archive.clone()))
+        .expect(0)
+        .mount(&harness.server)
+        .await;
+    let client = harness.client().await;
+    let reporter = Recorder::new(false);
+    let version = ToolchainVersion::named("21.1.1").unwrap();
+
+    let outcome = client
+        .install_version(install_options(
+            VersionRequest::Exact(version.clone()),
+            &reporter,
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.action, InstallAction::Cancelled);
+    assert_eq!(reporter.events(), ["confirm v21.1.1 from 0"]);
+    assert!(!client.version_is_installed(&version));
+}
+
+#[tokio::test]
+async fn skips_installed_version_unless_forced() {
+    let harness = Harness::new().await;
+    harness.serve_checksum(&sha256(&harness.archive)).await;
+    Mock::given(method("GET"))
+        .and(path(harness.archive_path()))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(harness.archive.clone()))
+        .expect(2)
+        .mount(&harness.server)
+        .await;
+    let client = harness.client().await;
+    let version = VersionRequest::Exact(ToolchainVersion::named("21.1.1").unwrap());
+
+    let reporter = Recorder::new(true);
+    client
+        .install_version(install_options(version.clone(), &reporter))
+        .await
+        .unwrap();
+
+    let reporter = Recorder::new(true);
+    let outcome = client
+        .install_version(install_options(version.clone(), &reporter))
+        .await
+        .unwrap();
+    assert_eq!(outcome.action, InstallAction::AlreadyInstalled);
+    assert!(reporter.events().is_empty());
+
+    // The cached archive is discarded rather than reused
+    let reporter = Recorder::new(true);
+    let outcome = client
+        .install_version(InstallOptions {
+            force: true,
+            ..install_options(version, &reporter)
+        })
+        .await
+        .unwrap();
+    assert!(matches!(outcome.action, InstallAction::Downloaded { .. }));
+    assert_eq!(
+        reporter.events(),
+        [
+            format!("evicted {}", harness.archive.len()),
+            "confirm v21.1.1 from 0".to_string(),
+            format!("downloading {}", harness.asset_name),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn suggests_similar_versions() {
+    let harness = Harness::new().await;
+    Mock::given(method("GET"))
+        .and(path(
+            "/repos/arm/arm-toolchain/releases/tags/release-21.1.2-ATfE",
+        ))
+        .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+            "message": "Not Found",
+            "documentation_url": "https://docs.github.com/rest",
+        })))
+        .mount(&harness.server)
+        .await;
+    let releases: Vec<_> = [
+        "release-21.1.1-ATfE",
+        "release-21.1.0-ATfE",
+        "release-19.1.5-ATfE",
+        "nightly",
+    ]
+    .iter()
+    .map(|tag| release(tag, &[(&harness.asset_name, harness.archive.len())]))
+    .collect();
+    Mock::given(method("GET"))
+        .and(path("/repos/arm/arm-toolchain/releases"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(releases))
+        .expect(1)
+        .mount(&harness.server)
+        .await;
+    let client = harness.client().await;
+
+    let result = client
+        .get_release(&ToolchainVersion::named("21.1.2").unwrap())
+        .await;
+
+    let Err(ToolchainError::VersionNotFound {
+        version,
+        suggestions,
+    }) = result
+    else {
+        panic!("expected VersionNotFound");
+    };
+    assert_eq!(version.name, "21.1.2");
+    assert_eq!(
+        suggestions,
+        [
+            ToolchainVersion::named("21.1.1").unwrap(),
+            ToolchainVersion::named("21.1.0").unwrap(),
+        ]
+    );
+}
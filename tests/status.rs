@@ -0,0 +1,48 @@
+//! Checks where status lines are printed, by running swift-v5 and capturing its output.
+
+// Windows finds the config directory without looking at the environment
+#![cfg(unix)]
+
+use std::process::{Command, Output};
+
+/// Runs swift-v5 with its config and data directories in `home`.
+fn swift_v5(home: &std::path::Path, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_swift-v5"))
+        .args(args)
+        .env_clear()
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("SWIFT_V5_NO_UPDATE_CHECK", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    output
+}
+
+#[test]
+fn status_lines_go_to_stderr() {
+    let home = tempfile::tempdir().unwrap();
+    let output = swift_v5(home.path(), &["toolchain", "default", "21.1.1"]);
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    // Not a terminal, so there are no colors
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "     Updated default toolchain version to v21.1.1\n"
+    );
+}
+
+#[test]
+fn quiet_hides_status_lines() {
+    let home = tempfile::tempdir().unwrap();
+    let output = swift_v5(home.path(), &["--quiet", "toolchain", "default", "21.1.1"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    // Output meant for stdout is still printed
+    let output = swift_v5(home.path(), &["--quiet", "toolchain", "default"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "21.1.1\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}